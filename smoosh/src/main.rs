@@ -1,20 +1,34 @@
 use clap::{builder::PossibleValue, Parser, ValueEnum};
-use itertools::{EitherOrBoth, Itertools};
-use linked_hash_map::LinkedHashMap;
-use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufRead, IsTerminal, Write};
 use std::path::PathBuf;
-use wad::{Lump, Wad};
+use std::sync::Arc;
+use wad::{
+    CapStrategy, CollisionChoice, CollisionResolver, DirectoryEntry, Game, Lump, LumpCollision,
+    LumpId, MapOrder, MapinfoSecrets, MergeOptions, MusicStrategy, NamespaceStrategy, Palette,
+    Port, SecretDetector, SlotSecrets, SourceMapId, ThingCap, Wad, WadError, WriteOptions,
+    DEFAULT_LEVEL_NAME_FORMAT, DEFAULT_SWATCH_SIZE, MAX_SWATCH_SIZE,
+};
 
 #[derive(Debug, Clone, Copy)]
 enum Mode {
     Concat,
     Chaos,
     Slog,
+    Shuffle,
+    Difficulty,
 }
 
 impl ValueEnum for Mode {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Self::Concat, Self::Chaos, Self::Slog]
+        &[
+            Self::Concat,
+            Self::Chaos,
+            Self::Slog,
+            Self::Shuffle,
+            Self::Difficulty,
+        ]
     }
 
     fn to_possible_value(&self) -> Option<PossibleValue> {
@@ -24,252 +38,1111 @@ impl ValueEnum for Mode {
             Self::Chaos => PossibleValue::new("chaos").help("Full randomization. Fun guaranteed?"),
             Self::Slog => PossibleValue::new("slog")
                 .help("Each map is kept close to its original slot. (MAP01, MAP01, MAP02, ...)"),
+            Self::Shuffle => {
+                PossibleValue::new("shuffle").help("Concatenate each WAD, but in a random order.")
+            }
+            Self::Difficulty => PossibleValue::new("difficulty")
+                .help("Easiest maps first, going by how many monsters they have, and how tough."),
         })
     }
 }
 
+/// Ways of recognizing secret maps.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Secrets {
+    /// Maps some MAPINFO entry's `secretnext` leads to.
+    Mapinfo,
+    /// Maps in the original games' secret slots (MAP31, MAP32, ExM9).
+    Slots,
+}
+
+/// The games the output can be made for.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum TargetGame {
+    /// Ultimate Doom: E1M1 through E4M9.
+    Doom,
+    /// Doom II: MAP01 through MAP32.
+    Doom2,
+    /// Heretic: E1M1 through E5M9.
+    Heretic,
+    /// Hexen: MAP01 onwards.
+    Hexen,
+}
+
+/// The source ports the output can be made for.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum TargetPort {
+    Vanilla,
+    Boom,
+    Mbf21,
+    Zdoom,
+    Eternity,
+}
+
+impl From<TargetPort> for Port {
+    fn from(port: TargetPort) -> Self {
+        match port {
+            TargetPort::Vanilla => Self::Vanilla,
+            TargetPort::Boom => Self::Boom,
+            TargetPort::Mbf21 => Self::Mbf21,
+            TargetPort::Zdoom => Self::ZDoom,
+            TargetPort::Eternity => Self::Eternity,
+        }
+    }
+}
+
+/// The kinds of file the output can be written as.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum OutputFormat {
+    /// A single WAD, zipped if any input was.
+    Wad,
+    /// A pk3 with its maps in maps/, its resources in folders, a MAPINFO and a WADINFO.
+    Pk3,
+}
+
+/// Ways of keeping maps' music with them.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Music {
+    /// Leave music alone; maps play whatever their new slot plays.
+    Keep,
+    /// Rename music lumps to the ones the game plays in the maps' new slots.
+    Rename,
+    /// Give each map's music its own name and point to it from a generated MAPINFO.
+    Mapinfo,
+}
+
+impl From<Music> for MusicStrategy {
+    fn from(music: Music) -> Self {
+        match music {
+            Music::Keep => Self::Keep,
+            Music::Rename => Self::Rename,
+            Music::Mapinfo => Self::Mapinfo,
+        }
+    }
+}
+
+impl From<TargetGame> for Game {
+    fn from(game: TargetGame) -> Self {
+        match game {
+            TargetGame::Doom => Self::Doom,
+            TargetGame::Doom2 => Self::Doom2,
+            TargetGame::Heretic => Self::Heretic,
+            TargetGame::Hexen => Self::Hexen,
+        }
+    }
+}
+
+impl From<Mode> for MapOrder {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Concat => Self::Concat,
+            Mode::Chaos => Self::Chaos,
+            Mode::Slog => Self::Slog,
+            Mode::Shuffle => Self::Shuffle,
+            Mode::Difficulty => Self::ByDifficulty,
+        }
+    }
+}
+
 #[derive(Debug, clap::Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Options {
-    /// The input WADs, all generated by Obsidian.
+    /// Only report errors.
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Report more of what's going on. Repeat for even more detail.
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    merge: Option<MergeArgs>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Render a WAD's palette as a grid of color swatches.
+    Palette {
+        wad: PathBuf,
+
+        /// Where to write the PNG.
+        #[clap(short, long)]
+        output: PathBuf,
+
+        /// The size of each swatch, in pixels.
+        #[clap(
+            long,
+            default_value_t = DEFAULT_SWATCH_SIZE,
+            value_parser = clap::value_parser!(u32).range(1..=MAX_SWATCH_SIZE as i64),
+        )]
+        swatch_size: u32,
+    },
+    /// Render all of a WAD's graphics onto one PNG, labeled by name.
+    Sheet {
+        wad: PathBuf,
+
+        /// Where to write the PNG.
+        #[clap(short, long)]
+        output: PathBuf,
+
+        /// A WAD to take the palette from, for WADs without a PLAYPAL of their own.
+        #[clap(long)]
+        palette: Option<PathBuf>,
+    },
+    /// Draw the map progression a WAD's MAPINFO describes as a Graphviz DOT graph.
+    Graph {
+        wad: PathBuf,
+
+        /// Where to write the DOT file.
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+    /// List a WAD's directory.
+    Dir {
+        wad: PathBuf,
+
+        /// Print the directory as a JSON array.
+        #[clap(long)]
+        json: bool,
+    },
+    /// Add a file to the end of a WAD as a lump.
+    Add {
+        wad: PathBuf,
+
+        /// The name of the new lump.
+        name: String,
+
+        /// The file holding the lump's contents.
+        file: PathBuf,
+    },
+    /// Compare two WADs lump by lump.
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+
+        /// Print the differences as JSON.
+        #[clap(long)]
+        json: bool,
+    },
+    /// Copy a lump out of a WAD.
+    Extract {
+        wad: PathBuf,
+
+        /// The name of the lump.
+        lump: String,
+
+        /// Where to write the lump's raw data.
+        #[clap(short, long, conflicts_with = "wav", required_unless_present = "wav")]
+        output: Option<PathBuf>,
+
+        /// Decode the lump as a DMX sound and write it here as a WAV file.
+        #[clap(long)]
+        wav: Option<PathBuf>,
+    },
+    /// Describe a WAD's maps.
+    Info {
+        wad: PathBuf,
+
+        /// Also show the WAD's ENDOOM screen.
+        #[clap(long)]
+        endoom: bool,
+
+        /// Show the ENDOOM screen in color, using ANSI escape codes.
+        #[clap(long)]
+        color: bool,
+
+        /// Print overall statistics as JSON instead.
+        #[clap(long, conflicts_with_all = ["endoom", "color"])]
+        json: bool,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct MergeArgs {
+    /// The input WADs, all generated by Obsidian. A directory holding a WAD is read as that WAD,
+    /// patched by any DEH file beside it. Any other directory is read as a WAD of loose lump
+    /// files.
     #[clap(required = true)]
     wads: Vec<PathBuf>,
 
     /// This tool supports several ways to order the maps.
     #[clap(short, long)]
     mode: Mode,
+
+    /// The game the output is for, which decides how its maps are numbered.
+    #[clap(long, value_enum, default_value = "doom2")]
+    game: TargetGame,
+
+    /// The source port the output is for. This picks suitable defaults for the other options,
+    /// like leaving out lumps the port doesn't read.
+    #[clap(long, value_enum)]
+    port: Option<TargetPort>,
+
+    /// Check that the target port can play every input map's format before merging, listing
+    /// the maps it can't.
+    #[clap(long, requires = "port")]
+    compat_check: bool,
+
+    /// Refuse to merge if the compatibility check finds a problem.
+    #[clap(long, requires = "compat_check")]
+    strict: bool,
+
+    /// How maps keep their own music after being renumbered. Defaults to keep, unless the port
+    /// says otherwise.
+    #[clap(long, value_enum)]
+    music: Option<Music>,
+
+    /// Seed the random orderings, making them reproducible.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Refuse to write an output WAD larger than this many bytes.
+    #[clap(long)]
+    max_size: Option<u64>,
+
+    /// Keep each input's flats, sprites, etc. in a separate namespace block instead of unifying
+    /// them.
+    #[clap(long)]
+    isolate_namespaces: bool,
+
+    /// Move secret maps into the secret slots, recognizing them in these ways.
+    #[clap(long, value_delimiter = ',')]
+    secrets: Vec<Secrets>,
+
+    /// Generate a MAPINFO describing the merged map progression.
+    #[clap(long)]
+    mapinfo: bool,
+
+    /// The IWAD the output will be played with. Input lumps identical to the IWAD's are dropped.
+    #[clap(long)]
+    iwad: Option<PathBuf>,
+
+    /// Trim zero padding some tools leave after the records of the inputs' map lumps.
+    #[clap(long)]
+    trim_padding: bool,
+
+    /// Store identical lumps in the output only once.
+    #[clap(long)]
+    share_identical: bool,
+
+    /// What kind of file to write the output as.
+    #[clap(long, value_enum, default_value = "wad", conflicts_with = "split")]
+    output_format: OutputFormat,
+
+    /// Write the output as resources.wad plus maps_01.wad, maps_02.wad and so on, each holding
+    /// this many maps, with a load.txt listing the order to load them in.
+    #[clap(long, value_name = "MAPS", value_parser = clap::value_parser!(u32).range(1..))]
+    split: Option<u32>,
+
+    /// Store identical sprites in the output only once, even under different names.
+    #[clap(long)]
+    share_identical_sprites: bool,
+
+    /// Regenerate every map's BLOCKMAP from its geometry.
+    #[clap(long)]
+    rebuild_blockmap: bool,
+
+    /// Replace every map's REJECT with one that lets every sector see every other.
+    #[clap(long)]
+    rebuild_reject: bool,
+
+    /// Keep a map in a particular slot, given as INPUT:MAP=SLOT, where INPUT counts the input
+    /// WADs from 0. For example, `1:MAP03=MAP07` puts the second WAD's MAP03 in MAP07.
+    #[clap(long, value_parser = parse_pin)]
+    pin: Vec<(SourceMapId, String)>,
+
+    /// Leave out a map, given as INPUT:MAP like for `--pin`.
+    #[clap(long, value_parser = parse_map_id)]
+    skip: Vec<SourceMapId>,
+
+    /// Don't turn the DEH files found beside input WADs into DEHACKED lumps.
+    #[clap(long)]
+    no_auto_deh: bool,
+
+    /// Also write the output's DEHACKED lump to this file, for ports which load patches
+    /// alongside the WAD.
+    #[clap(long)]
+    deh_out: Option<PathBuf>,
+
+    /// Leave the DEHACKED lump out of the output WAD, so the patch only lives in `--deh-out`.
+    #[clap(long, requires = "deh_out")]
+    no_embedded_deh: bool,
+
+    /// Leave out every map, writing only the inputs' merged resources (textures, flats,
+    /// sprites, sounds, music and so on) for loading alongside map packs.
+    #[clap(long, conflicts_with_all = ["pin", "mapinfo"])]
+    only_resources: bool,
+
+    /// A WAD holding one map, like a hub or title map, to put in the first slot. The inputs'
+    /// maps follow it from the second slot on, and a MAPINFO is generated leading on from it.
+    #[clap(long, conflicts_with = "only_resources")]
+    title_map: Option<PathBuf>,
+
+    /// Experimental: keep at most N monsters in each Doom-format map, or with a trailing `%`,
+    /// remove that percentage of each map's monsters.
+    #[clap(long, value_name = "N|PERCENT%", value_parser = parse_cap)]
+    cap_monsters: Option<CapStrategy>,
+
+    /// How a generated MAPINFO names each level. `{slot}` is the map's new slot, `{map}` its
+    /// original name, `{input}` the file it came from, and `{name}` the name its input's MAPINFO
+    /// gives it.
+    #[clap(long, value_name = "FORMAT", default_value = DEFAULT_LEVEL_NAME_FORMAT)]
+    level_names: String,
+
+    /// Name each level in a generated MAPINFO after its slot alone.
+    #[clap(long, conflicts_with = "level_names")]
+    no_level_names: bool,
+
+    /// Only run the checks a merge would, without writing anything. Exits with 0 if nothing
+    /// turned up, 1 if there were warnings, or 2 if the merge would fail or the port given with
+    /// `--port` can't play some of the maps.
+    #[clap(long, conflicts_with = "interactive")]
+    validate_only: bool,
+
+    /// Ask which lump to keep whenever two inputs have differing lumps of the same name. When
+    /// not run from a terminal, the later input's lump is kept as usual.
+    #[clap(long)]
+    interactive: bool,
+}
+
+impl MergeArgs {
+    /// The paths of every WAD merged, numbered like the inputs. The title map, if any, comes
+    /// last.
+    fn input_paths(&self) -> Vec<PathBuf> {
+        self.wads.iter().chain(&self.title_map).cloned().collect()
+    }
+}
+
+fn parse_map_id(s: &str) -> Result<SourceMapId, String> {
+    let (input, map) = s.split_once(':').ok_or("expected INPUT:MAP")?;
+    let input = input
+        .parse()
+        .map_err(|_| format!("`{input}` is not an input number"))?;
+    Ok(SourceMapId::new(input, map))
+}
+
+fn parse_pin(s: &str) -> Result<(SourceMapId, String), String> {
+    let (map, slot) = s.split_once('=').ok_or("expected INPUT:MAP=SLOT")?;
+    Ok((parse_map_id(map)?, slot.to_string()))
+}
+
+fn parse_cap(s: &str) -> Result<CapStrategy, String> {
+    match s.strip_suffix('%') {
+        Some(percent) => match percent.parse() {
+            Ok(percent @ 0..=100) => Ok(CapStrategy::ReduceBy(percent)),
+            _ => Err(format!("`{percent}` is not a percentage")),
+        },
+        None => s
+            .parse()
+            .map(CapStrategy::AtMost)
+            .map_err(|_| format!("`{s}` is not a number")),
+    }
 }
 
-#[derive(Debug)]
-struct BinaryMap<'wad> {
-    things: &'wad Lump,
-    linedefs: &'wad Lump,
-    sidedefs: &'wad Lump,
-    vertexes: &'wad Lump,
-    segs: &'wad Lump,
-    ssectors: &'wad Lump,
-    nodes: &'wad Lump,
-    sectors: &'wad Lump,
-    reject: Option<&'wad Lump>,
-    blockmap: Option<&'wad Lump>,
-    behavior: Option<&'wad Lump>,
-    scripts: Option<&'wad Lump>,
-}
-
-#[derive(Debug)]
-enum MapLumps<'wad> {
-    Binary(BinaryMap<'wad>),
-    Udmf(&'wad Lump),
-}
-
-#[derive(Debug)]
-struct Map<'wad> {
-    orig_slot: usize,
-    lumps: MapLumps<'wad>,
-}
-
-struct MapIterator<'wad, 'res> {
-    wad: &'wad Wad,
-    last_idx: usize,
-    resource_lumps: &'res mut LinkedHashMap<String, &'wad Lump>,
-}
-
-const BINARY_MAP_SEQUENCE: &[&str] = &[
-    "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES", "SECTORS",
-];
-
-impl<'wad, 'res> Iterator for MapIterator<'wad, 'res> {
-    type Item = Map<'wad>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut result = None;
-        let mut maplump = self.wad.lumps.get(self.last_idx)?;
-        let orig_slot = 'blk: loop {
-            let mut next_map = || -> Option<&Lump> {
-                self.resource_lumps.insert(maplump.name.clone(), maplump);
-                self.last_idx += 1;
-                self.wad.lumps.get(self.last_idx)
-            };
-            while !maplump.name.starts_with("MAP") {
-                maplump = next_map()?;
+/// A short look at a lump's contents: its first line if it's text, or else its first bytes.
+fn preview(lump: &Lump) -> String {
+    const PREVIEW_LEN: usize = 32;
+    let head = &lump.data[..lump.data.len().min(PREVIEW_LEN)];
+    if head
+        .iter()
+        .all(|&b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+    {
+        let text = String::from_utf8_lossy(head);
+        format!("{:?}", text.lines().next().unwrap_or_default())
+    } else {
+        head.iter().map(|b| format!("{b:02x} ")).collect::<String>()
+    }
+}
+
+/// Asks on `output` which lump of a collision to keep, reading the answer from `input`. An
+/// empty answer, or running out of input, keeps the later input's lump.
+fn ask_collision(
+    inputs: &[PathBuf],
+    collision: &LumpCollision,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> CollisionChoice {
+    let _ = writeln!(output, "{} is in more than one input:", collision.name);
+    for (n, source, lump) in [
+        (1, collision.existing_source, collision.existing),
+        (2, collision.incoming_source, collision.incoming),
+    ] {
+        let _ = writeln!(
+            output,
+            "  {n}) {} ({} bytes) {}",
+            inputs[source].display(),
+            lump.data.len(),
+            preview(lump)
+        );
+    }
+    loop {
+        let _ = write!(output, "keep which one? [1/2, default 2] ");
+        let _ = output.flush();
+        let mut answer = String::new();
+        match input.read_line(&mut answer) {
+            Ok(0) | Err(_) => return CollisionChoice::TakeIncoming,
+            Ok(_) => {}
+        }
+        match answer.trim() {
+            "1" => return CollisionChoice::KeepExisting,
+            "" | "2" => return CollisionChoice::TakeIncoming,
+            _ => {}
+        }
+    }
+}
+
+/// Reads the input WADs, leaving out the skipped maps and the lumps identical to the IWAD's.
+fn read_inputs(opts: &MergeArgs) -> Result<Vec<Wad>, WadError> {
+    let mut wads = opts
+        .wads
+        .iter()
+        .map(|path| {
+            if path.is_dir() {
+                return Wad::from_dir(path, !opts.no_auto_deh);
             }
-            match maplump.name[3..].parse::<usize>() {
-                Ok(slot) => break 'blk slot,
-                Err(_) => {
-                    maplump = next_map()?;
-                }
-            };
-        };
-        'check_binary: {
-            let mut lumps = Vec::<&Lump>::new();
-            for pair in BINARY_MAP_SEQUENCE
-                .iter()
-                .copied()
-                .zip_longest(self.wad.lumps[self.last_idx + 1..].iter())
-            {
-                match pair {
-                    // ran out of lumps
-                    EitherOrBoth::Left(_) => break 'check_binary,
-                    // success
-                    EitherOrBoth::Right(_) => break,
-                    EitherOrBoth::Both(expected_name, lump) => {
-                        if lump.name != expected_name {
-                            // mismatch
-                            break 'check_binary;
-                        } else {
-                            lumps.push(lump);
-                        }
-                    }
-                }
+            if !opts.trim_padding {
+                return Wad::new(path);
             }
-            let mut idx = self.last_idx + 1 + lumps.len();
-            let reject = self.wad.lumps.get(idx).and_then(|lump| {
-                (lump.name == "REJECT").then(|| {
-                    idx += 1;
-                    lump
-                })
-            });
-            let blockmap = self.wad.lumps.get(idx).and_then(|lump| {
-                (lump.name == "BLOCKMAP").then(|| {
-                    idx += 1;
-                    lump
-                })
-            });
-            let behavior = self.wad.lumps.get(idx).and_then(|lump| {
-                (lump.name == "BEHAVIOR").then(|| {
-                    idx += 1;
-                    lump
-                })
-            });
-            let scripts = self.wad.lumps.get(idx).and_then(|lump| {
-                (lump.name == "SCRIPTS").then(|| {
-                    idx += 1;
-                    lump
-                })
-            });
-            self.last_idx = idx;
-            result = Some(Map {
-                orig_slot,
-                lumps: MapLumps::Binary(BinaryMap {
-                    things: lumps[0],
-                    linedefs: lumps[1],
-                    sidedefs: lumps[2],
-                    vertexes: lumps[3],
-                    segs: lumps[4],
-                    ssectors: lumps[5],
-                    nodes: lumps[6],
-                    sectors: lumps[7],
-                    reject,
-                    blockmap,
-                    behavior,
-                    scripts,
-                }),
-            });
+            // each trimmed lump is logged as it's trimmed
+            let (wad, _) = Wad::open_trim_padding(path)?;
+            Ok(wad)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for id in &opts.skip {
+        wads.get_mut(id.source)
+            .ok_or_else(|| WadError::Other(format!("there is no input {}", id.source)))?
+            .remove_map(&id.map.to_ascii_uppercase())?;
+    }
+    if let Some(path) = &opts.title_map {
+        let title = Wad::new(path)?;
+        let maps = title.maps().len();
+        if maps != 1 {
+            return Err(WadError::Other(format!(
+                "the title map WAD {} should hold one map, not {maps}",
+                path.display()
+            )));
         }
-        if result.is_none() {
-            // must be UDMF then...
-            if let Some(textmap) = self
-                .wad
-                .lumps
-                .get(self.last_idx + 1)
-                .and_then(|lump| (lump.name == "TEXTMAP").then(|| lump))
-            {
-                // MAP01, TEXTMAP, ENDMAP
-                self.last_idx += 3;
-                result = Some(Map {
-                    orig_slot,
-                    lumps: MapLumps::Udmf(textmap),
-                });
+        wads.push(title);
+    }
+    if let Some(iwad) = &opts.iwad {
+        let iwad = Wad::new(iwad)?;
+        for (wad, path) in wads.iter_mut().zip(opts.input_paths()) {
+            for name in wad.strip_iwad_duplicates(&iwad) {
+                log::info!(
+                    "dropped {name} from {}, since it's the same as the IWAD's",
+                    path.display()
+                );
             }
         }
+    }
+    Ok(wads)
+}
+
+/// Warns about each input map the target port can't play, giving how many there are.
+fn check_compat(opts: &MergeArgs, wads: &[Wad], port: TargetPort) -> usize {
+    let incompatible = Port::from(port).incompatible_maps(wads, opts.game.into());
+    for m in &incompatible {
+        log::warn!(
+            "{} in {} is a {:?}-format map, which {port:?} can't play",
+            m.map,
+            opts.input_paths()[m.input].display(),
+            m.format
+        );
+    }
+    incompatible.len()
+}
+
+/// The options to merge `wads`, the inputs read by [`read_inputs`], with.
+fn merge_options(opts: &MergeArgs, wads: &[Wad]) -> MergeOptions {
+    let defaults = opts.port.map_or_else(MergeOptions::default, |port| {
+        MergeOptions::profile(port.into())
+    });
+    let interactive = opts.interactive && std::io::stdin().is_terminal();
+    if opts.interactive && !interactive {
+        log::warn!("not reading from a terminal, so later inputs' lumps win collisions");
+    }
+    let inputs = opts.input_paths();
+    let mut pinned_slots = opts.pin.iter().cloned().collect::<HashMap<_, _>>();
+    if let (Some(_), Some(title)) = (&opts.title_map, wads.last()) {
+        let game = Game::from(opts.game);
+        let id = SourceMapId::new(wads.len() - 1, title.maps()[0].name);
+        pinned_slots.insert(id, game.slot_name(1));
+    }
+    MergeOptions {
+        order: opts.mode.into(),
+        target_game: opts.game.into(),
+        music: opts.music.map_or(defaults.music, Into::into),
+        max_output_size: opts.max_size,
+        namespace_strategy: if opts.isolate_namespaces {
+            NamespaceStrategy::Isolate
+        } else {
+            NamespaceStrategy::Unify
+        },
+        secret_detectors: opts
+            .secrets
+            .iter()
+            .map(|secrets| -> Arc<dyn SecretDetector> {
+                match secrets {
+                    Secrets::Mapinfo => Arc::new(MapinfoSecrets),
+                    Secrets::Slots => Arc::new(SlotSecrets),
+                }
+            })
+            .collect(),
+        generate_mapinfo: opts.mapinfo
+            || opts.title_map.is_some()
+            || defaults.generate_mapinfo
+            || matches!(opts.output_format, OutputFormat::Pk3),
+        rebuild_blockmap: opts.rebuild_blockmap,
+        rebuild_reject: opts.rebuild_reject,
+        seed: opts.seed,
+        pinned_slots,
+        resources_only: opts.only_resources,
+        level_name_format: (!opts.no_level_names).then(|| opts.level_names.clone()),
+        input_names: inputs
+            .iter()
+            .map(|path| {
+                path.file_stem()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect(),
+        thing_cap: opts.cap_monsters.map(|monsters| ThingCap {
+            monsters: Some(monsters),
+            ..ThingCap::default()
+        }),
+        collision_resolver: interactive.then(|| {
+            CollisionResolver::new(move |collision| {
+                ask_collision(
+                    &inputs,
+                    collision,
+                    &mut std::io::stdin().lock(),
+                    &mut std::io::stderr(),
+                )
+            })
+        }),
+        ..defaults
+    }
+}
+
+/// Runs every check a merge would, without writing anything, and gives the exit status: 0 if
+/// nothing turned up, 1 if there were only warnings, and 2 if the merge would fail or the target
+/// port can't play some of the maps.
+fn validate(opts: &MergeArgs) -> i32 {
+    let checked = read_inputs(opts).and_then(|wads| {
+        let incompatible = opts.port.map_or(0, |port| check_compat(opts, &wads, port));
+        let report = Wad::check_merge(&wads, &merge_options(opts, &wads))?;
+        Ok((incompatible, report))
+    });
+    match checked {
+        Err(e) => {
+            eprintln!("smoosh: {e}");
+            2
+        }
+        Ok((incompatible, _)) if incompatible > 0 => 2,
+        Ok((_, report)) if !report.warnings.is_empty() => 1,
+        Ok(_) => 0,
+    }
+}
+
+/// Writes the merged DEHACKED lump out to the `--deh-out` file, if there is one, taking it out
+/// of the output for `--no-embedded-deh`.
+fn write_deh(opts: &MergeArgs, out_wad: &mut Wad) -> Result<(), WadError> {
+    let Some(path) = &opts.deh_out else {
+        return Ok(());
+    };
+    match out_wad.lump("DEHACKED") {
+        Some(lump) => std::fs::write(path, &lump.data).map_err(WadError::CouldntWriteLump)?,
+        None => log::warn!("none of the inputs has a DEHACKED lump to write out"),
+    }
+    if opts.no_embedded_deh {
+        out_wad.retain_lumps(|_, lump| lump.name != "DEHACKED");
+    }
+    Ok(())
+}
+
+fn merge(opts: MergeArgs) -> Result<(), WadError> {
+    let wads = read_inputs(&opts)?;
+    if let (true, Some(port)) = (opts.compat_check, opts.port) {
+        let incompatible = check_compat(&opts, &wads, port);
+        if opts.strict && incompatible > 0 {
+            return Err(WadError::Other(format!(
+                "{incompatible} maps can't be played in {port:?}"
+            )));
+        }
+    }
+    let merge_opts = merge_options(&opts, &wads);
+    let (mut out_wad, _) = Wad::merge_owned(wads, &merge_opts)?;
+    write_deh(&opts, &mut out_wad)?;
+    if let OutputFormat::Pk3 = opts.output_format {
+        out_wad.append_lump_bytes(
+            "WADINFO",
+            wadinfo(&opts.input_paths(), &out_wad).into_bytes(),
+        )?;
+        return out_wad.write_pk3("output.pk3");
+    }
+    let write_opts = WriteOptions {
+        share_identical: opts.share_identical,
+        share_identical_sprites: opts.share_identical_sprites,
+        ..WriteOptions::default()
+    };
+    if let Some(maps_per_file) = opts.split {
+        let split = out_wad.split_maps(maps_per_file as usize);
+        // the resources have to come first, so the maps can find their textures
+        let mut load_order = vec![String::from("resources.wad")];
+        split
+            .resources
+            .write_with_options(&load_order[0], &write_opts)?;
+        for (i, wad) in split.maps.iter().enumerate() {
+            let name = format!("maps_{:02}.wad", i + 1);
+            wad.write_with_options(&name, &write_opts)?;
+            load_order.push(name);
+        }
+        return std::fs::write("load.txt", load_order.join("\n") + "\n")
+            .map_err(WadError::CouldntWriteLump);
+    }
+    out_wad.write_with_options(
+        format!("output.{}", if out_wad.was_zip { "pk3" } else { "wad" }),
+        &write_opts,
+    )
+}
+
+/// Describes a merged WAD: where it came from, and the maps it holds.
+fn wadinfo(inputs: &[PathBuf], wad: &Wad) -> String {
+    let mut text = String::from("Merged by smoosh from:\n");
+    for input in inputs {
+        text += &format!("    {}\n", input.display());
+    }
+    text += "\nMaps:\n";
+    for map in wad.maps() {
+        text += &format!("    {}\n", map.name);
+    }
+    text
+}
+
+fn palette(wad: PathBuf, output: PathBuf, swatch_size: u32) -> Result<(), Box<dyn Error>> {
+    let wad = Wad::new(wad)?;
+    let playpal = wad
+        .lump("PLAYPAL")
+        .ok_or_else(|| WadError::MissingLump(String::from("PLAYPAL")))?;
+    let palette = Palette::from_lump(playpal)?;
+    std::fs::write(
+        output,
+        wad::palette_to_png_with_swatch_size(&palette, swatch_size)?,
+    )?;
+    Ok(())
+}
+
+fn sheet(wad: PathBuf, output: PathBuf, palette: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let wad = Wad::new(wad)?;
+    let palette_wad = palette.map(Wad::new).transpose()?;
+    let playpal = palette_wad
+        .as_ref()
+        .unwrap_or(&wad)
+        .lump("PLAYPAL")
+        .ok_or_else(|| WadError::MissingLump(String::from("PLAYPAL")))?;
+    let palette = Palette::from_lump(playpal)?;
+    std::fs::write(output, wad::contact_sheet(&wad, &palette)?)?;
+    Ok(())
+}
+
+fn graph(wad: PathBuf, output: PathBuf) -> Result<(), Box<dyn Error>> {
+    let wad = Wad::new(wad)?;
+    let mapinfo = wad
+        .lump("ZMAPINFO")
+        .or_else(|| wad.lump("MAPINFO"))
+        .ok_or_else(|| WadError::MissingLump(String::from("MAPINFO")))?;
+    let entries = wad::parse_mapinfo(&String::from_utf8_lossy(&mapinfo.data));
+    std::fs::write(output, wad::progression_dot(&entries))?;
+    Ok(())
+}
+
+/// A directory entry as shown by `smoosh dir --json`.
+#[derive(serde::Serialize)]
+struct DirEntry<'a> {
+    #[serde(flatten)]
+    entry: &'a DirectoryEntry,
+    crc32: u32,
+}
+
+/// The WAD's directory, with a checksum of each lump.
+fn dir_entries(wad: &Wad) -> Vec<DirEntry<'_>> {
+    wad.directory
+        .iter()
+        .zip(&wad.lumps)
+        .map(|(entry, lump)| DirEntry {
+            entry,
+            crc32: crc32fast::hash(&lump.data),
+        })
+        .collect()
+}
+
+fn dir(wad: PathBuf, json: bool) -> Result<(), Box<dyn Error>> {
+    let wad = Wad::new(wad)?;
+    let entries = dir_entries(&wad);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        for DirEntry { entry, crc32 } in &entries {
+            println!(
+                "{:<8} {:>10} {:>10} {crc32:08x}",
+                entry.name, entry.offset, entry.size
+            );
+        }
+    }
+    Ok(())
+}
+
+fn add(path: PathBuf, name: String, file: PathBuf) -> Result<(), Box<dyn Error>> {
+    let mut wad = Wad::new(&path)?;
+    wad.append_raw_file(&name, &file)?;
+    wad.write(&path)?;
+    Ok(())
+}
+
+fn diff(old: PathBuf, new: PathBuf, json: bool) -> Result<(), Box<dyn Error>> {
+    let diff = Wad::new(old)?.diff(&Wad::new(new)?);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+    let show = |id: &LumpId| format!("{} #{}", id.name, id.occurrence + 1);
+    for id in &diff.removed {
+        println!("- {}", show(id));
+    }
+    for id in &diff.added {
+        println!("+ {}", show(id));
+    }
+    for change in &diff.changed {
+        println!(
+            "~ {} ({} -> {} bytes)",
+            show(&change.id),
+            change.old_size,
+            change.new_size
+        );
+    }
+    Ok(())
+}
+
+fn extract(
+    wad: PathBuf,
+    lump: String,
+    output: Option<PathBuf>,
+    wav: Option<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let wad = Wad::new(wad)?;
+    let count = wad.count_lumps_named(&lump);
+    if count > 1 {
+        log::info!("the WAD has {count} lumps named {lump}; extracting the last");
+    }
+    let lump = wad
+        .lump(&lump)
+        .ok_or_else(|| WadError::MissingLump(lump.clone()))?;
+    if let Some(wav) = wav {
+        std::fs::write(wav, wad::dmx_to_wav(&lump.dmx_sound()?))?;
+    }
+    if let Some(output) = output {
+        std::fs::write(output, &lump.data)?;
+    }
+    Ok(())
+}
+
+fn info(wad: PathBuf, endoom: bool, color: bool, json: bool) -> Result<(), Box<dyn Error>> {
+    let wad = Wad::new(wad)?;
+    let stats = wad.stats();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+    println!(
+        "{} lumps, {} maps, {} bytes",
+        stats.lump_count, stats.map_count, stats.total_size
+    );
+    if stats.collisions > 0 {
+        println!("{} lump names are used more than once", stats.collisions);
+    }
+    let summaries = wad.map_summaries();
+    for summary in &summaries {
+        println!(
+            "{:<8} {:<6} {:>6} things {:>6} linedefs {:>6} sectors",
+            summary.name,
+            format!("{:?}", summary.format),
+            summary.things,
+            summary.linedefs,
+            summary.sectors,
+        );
+        if let Some(namespace) = &summary.namespace {
+            println!("         namespace {namespace}");
+        }
+        for violation in &summary.limit_violations {
+            println!(
+                "         exceeds the vanilla limit of {} {}: has {}",
+                violation.limit, violation.lump, violation.count
+            );
+        }
+    }
+    if endoom {
+        let lump = wad
+            .lump("ENDOOM")
+            .ok_or_else(|| WadError::MissingLump(String::from("ENDOOM")))?;
+        let screen = if color {
+            wad::render_endoom_ansi(lump)?
+        } else {
+            wad::render_endoom(lump)?
+        };
+        println!("{screen}");
+    }
+    Ok(())
+}
 
-        if result.is_none() {
-            self.resource_lumps.insert(maplump.name.clone(), maplump);
-            self.last_idx += 1;
-            while self.last_idx < self.wad.lumps.len() {
-                let lump = &self.wad.lumps[self.last_idx];
-                self.resource_lumps.insert(lump.name.clone(), lump);
-                self.last_idx += 1;
+fn run(opts: Options) -> Result<(), Box<dyn Error>> {
+    match opts.command {
+        Some(Command::Palette {
+            wad,
+            output,
+            swatch_size,
+        }) => palette(wad, output, swatch_size),
+        Some(Command::Sheet {
+            wad,
+            output,
+            palette,
+        }) => sheet(wad, output, palette),
+        Some(Command::Graph { wad, output }) => graph(wad, output),
+        Some(Command::Dir { wad, json }) => dir(wad, json),
+        Some(Command::Add { wad, name, file }) => add(wad, name, file),
+        Some(Command::Diff { old, new, json }) => diff(old, new, json),
+        Some(Command::Extract {
+            wad,
+            lump,
+            output,
+            wav,
+        }) => extract(wad, lump, output, wav),
+        Some(Command::Info {
+            wad,
+            endoom,
+            color,
+            json,
+        }) => info(wad, endoom, color, json),
+        None => {
+            let opts = opts.merge.unwrap();
+            if opts.validate_only {
+                std::process::exit(validate(&opts));
             }
+            Ok(merge(opts)?)
         }
-        result
     }
 }
 
-fn get_all_maps<'wad, 'res>(
-    wad: &'wad Wad,
-    resource_lumps: &'res mut LinkedHashMap<String, &'wad Lump>,
-) -> MapIterator<'wad, 'res> {
-    MapIterator {
-        wad,
-        last_idx: 0,
-        resource_lumps,
+/// Prints log messages to stderr.
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
     }
+
+    fn log(&self, record: &log::Record) {
+        let level = match record.level() {
+            log::Level::Error => "error",
+            log::Level::Warn => "warning",
+            log::Level::Info => "note",
+            log::Level::Debug => "debug",
+            log::Level::Trace => "trace",
+        };
+        eprintln!("{level}: {}", record.args());
+    }
+
+    fn flush(&self) {}
 }
 
 fn main() {
     let opts = Options::parse();
-    let wads = opts
-        .wads
-        .iter()
-        .map(|path| Wad::new(path).unwrap())
-        .collect_vec();
-    let mut resource_lumps = LinkedHashMap::new();
-    let mut maps = wads
-        .iter()
-        .map(|wad| get_all_maps(wad, &mut resource_lumps).collect_vec())
-        .flatten()
-        .collect_vec();
-    let mut out_wad = Wad::new_empty(wads.iter().map(|w| w.was_zip).any(|b| b));
-    for lump in resource_lumps.values().copied() {
-        out_wad.add_lump(lump.clone());
-    }
-    match opts.mode {
-        Mode::Concat => {} // already in this order
-        Mode::Chaos => maps.shuffle(&mut rand::thread_rng()),
-        Mode::Slog => maps.sort_by(|m1, m2| m1.orig_slot.cmp(&m2.orig_slot)),
-    }
-    for (i, map) in maps.into_iter().enumerate() {
-        let mapnum = i + 1;
-        out_wad.add_lump(Lump {
-            name: format!("MAP{:02}", mapnum),
-            data: vec![],
-        });
-        match map.lumps {
-            MapLumps::Binary(b) => {
-                out_wad.add_lump(b.things.clone());
-                out_wad.add_lump(b.linedefs.clone());
-                out_wad.add_lump(b.sidedefs.clone());
-                out_wad.add_lump(b.vertexes.clone());
-                out_wad.add_lump(b.segs.clone());
-                out_wad.add_lump(b.ssectors.clone());
-                out_wad.add_lump(b.nodes.clone());
-                out_wad.add_lump(b.sectors.clone());
-                if let Some(reject) = b.reject {
-                    out_wad.add_lump(reject.clone());
-                }
-                if let Some(blockmap) = b.blockmap {
-                    out_wad.add_lump(blockmap.clone());
-                }
-                if let Some(behavior) = b.behavior {
-                    out_wad.add_lump(behavior.clone());
-                }
-                if let Some(scripts) = b.scripts {
-                    out_wad.add_lump(scripts.clone());
-                }
-            }
-            MapLumps::Udmf(textmap) => {
-                out_wad.add_lump(textmap.clone());
-                out_wad.add_lump(Lump {
-                    name: String::from("ENDMAP"),
-                    data: vec![],
-                });
-            }
+    log::set_logger(&Logger).unwrap();
+    log::set_max_level(match (opts.quiet, opts.verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    });
+    if let Err(e) = run(opts) {
+        eprintln!("smoosh: {e}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ask_collision_reads_the_choice() {
+        let inputs = [PathBuf::from("old.wad"), PathBuf::from("new.wad")];
+        let existing = Lump {
+            name: String::from("COLORMAP"),
+            data: b"old".to_vec(),
+        };
+        let incoming = Lump {
+            name: String::from("COLORMAP"),
+            data: b"newer".to_vec(),
+        };
+        let collision = LumpCollision {
+            name: "COLORMAP",
+            existing_source: 0,
+            existing: &existing,
+            incoming_source: 1,
+            incoming: &incoming,
+        };
+        for (answer, expected) in [
+            ("1\n", CollisionChoice::KeepExisting),
+            ("2\n", CollisionChoice::TakeIncoming),
+            ("\n", CollisionChoice::TakeIncoming),
+            ("x\n1\n", CollisionChoice::KeepExisting),
+            ("", CollisionChoice::TakeIncoming),
+        ] {
+            let mut output = Vec::new();
+            let choice = ask_collision(&inputs, &collision, &mut answer.as_bytes(), &mut output);
+            assert_eq!(choice, expected, "answering {answer:?}");
+            let output = String::from_utf8(output).unwrap();
+            assert!(output.contains("old.wad (3 bytes)"), "{output}");
+            assert!(output.contains("new.wad (5 bytes)"), "{output}");
+        }
+    }
+
+    /// Writes a WAD holding `lumps` to a file unique to `test`, and gives its path.
+    fn write_input(test: &str, lumps: &[(&str, &[u8])]) -> PathBuf {
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in lumps {
+            wad.append_lump_bytes(*name, data.to_vec()).unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("smoosh-{}-{test}.wad", std::process::id()));
+        wad.write(&path).unwrap();
+        path
+    }
+
+    /// The options for concatenating `inputs`, with `args` added.
+    fn merge_args(args: &[&str], inputs: &[&PathBuf]) -> MergeArgs {
+        let args = ["smoosh", "-m", "concat"]
+            .iter()
+            .chain(args)
+            .map(PathBuf::from)
+            .chain(inputs.iter().map(|&input| input.clone()));
+        Options::try_parse_from(args).unwrap().merge.unwrap()
+    }
+
+    /// The exit status of `smoosh --validate-only` on `inputs`.
+    fn validate_status(inputs: &[&PathBuf]) -> i32 {
+        validate(&merge_args(&["--validate-only"], inputs))
+    }
+
+    #[test]
+    fn validate_gives_an_exit_status() {
+        let clean = write_input("clean", &[("MAP01", b""), ("THINGS", b"")]);
+        let dangling = write_input("dangling", &[("SNDINFO", b"misc/boom DSBOOM\n")]);
+        let first = write_input("first", &[("COLORMAP", b"first")]);
+        let second = write_input("second", &[("COLORMAP", b"second")]);
+        let missing = std::env::temp_dir().join("smoosh-missing-input.wad");
+        assert_eq!(validate_status(&[&clean]), 0);
+        assert_eq!(validate_status(&[&clean, &dangling]), 1);
+        assert_eq!(validate_status(&[&first, &clean]), 0);
+        assert_eq!(validate_status(&[&first, &second]), 1);
+        assert_eq!(validate_status(&[&clean, &missing]), 2);
+        for input in [clean, dangling, first, second] {
+            std::fs::remove_file(input).unwrap();
+        }
+    }
+
+    #[test]
+    fn dir_json_has_every_entry() {
+        let path = write_input(
+            "dir",
+            &[("FIRST", b"one"), ("EMPTY", b""), ("FIRST", b"two")],
+        );
+        let wad = Wad::new(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let json = serde_json::to_value(dir_entries(&wad)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"name": "FIRST", "offset": 60, "size": 3, "crc32": crc32fast::hash(b"one")},
+                {"name": "EMPTY", "offset": 63, "size": 0, "crc32": 0},
+                {"name": "FIRST", "offset": 63, "size": 3, "crc32": crc32fast::hash(b"two")},
+            ])
+        );
+    }
+
+    #[test]
+    fn dehacked_can_go_in_its_own_file() {
+        let first = write_input(
+            "deh-first",
+            &[("DEHACKED", b"Patch File for DeHackEd v3.0\n")],
+        );
+        let second = write_input(
+            "deh-second",
+            &[("DEHACKED", b"Thing 1\nHit points = 200\n")],
+        );
+        let deh = std::env::temp_dir().join(format!("smoosh-{}-out.deh", std::process::id()));
+        let deh_out = deh.to_str().unwrap();
+        for (args, embedded) in [
+            (&["--deh-out", deh_out][..], true),
+            (&["--deh-out", deh_out, "--no-embedded-deh"], false),
+        ] {
+            let opts = merge_args(args, &[&first, &second]);
+            let wads = read_inputs(&opts).unwrap();
+            let (mut out_wad, _) = Wad::merge(&wads, &merge_options(&opts, &wads)).unwrap();
+            write_deh(&opts, &mut out_wad).unwrap();
+            // the later input's patch wins
+            assert_eq!(std::fs::read(&deh).unwrap(), b"Thing 1\nHit points = 200\n");
+            assert_eq!(out_wad.lump("DEHACKED").is_some(), embedded);
+            std::fs::remove_file(&deh).unwrap();
+        }
+        for input in [first, second] {
+            std::fs::remove_file(input).unwrap();
+        }
+    }
+
+    #[test]
+    fn title_map_goes_in_the_first_slot() {
+        let input = write_input(
+            "title-input",
+            &[
+                ("MAP01", b""),
+                ("THINGS", b"one"),
+                ("MAP02", b""),
+                ("THINGS", b"two"),
+            ],
+        );
+        let title = write_input("title", &[("HUB", b""), ("THINGS", b"hub")]);
+        let opts = merge_args(&["--title-map", title.to_str().unwrap()], &[&input]);
+        let wads = read_inputs(&opts).unwrap();
+        let (merged, _) = Wad::merge(&wads, &merge_options(&opts, &wads)).unwrap();
+        let maps = merged
+            .maps()
+            .iter()
+            .map(|m| (m.name.to_string(), m.lumps[1].data.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            maps,
+            [
+                (String::from("MAP01"), b"hub".to_vec()),
+                (String::from("MAP02"), b"one".to_vec()),
+                (String::from("MAP03"), b"two".to_vec()),
+            ]
+        );
+        let mapinfo = wad::parse_mapinfo(&String::from_utf8_lossy(
+            &merged.lump("MAPINFO").unwrap().data,
+        ));
+        assert_eq!(mapinfo[0].map, "MAP01");
+        assert_eq!(mapinfo[0].next.as_deref(), Some("MAP02"));
+
+        // a title map WAD must hold exactly one map
+        let opts = merge_args(&["--title-map", input.to_str().unwrap()], &[&title]);
+        let two_maps = read_inputs(&opts);
+        for path in [input, title] {
+            std::fs::remove_file(path).unwrap();
         }
+        assert!(matches!(two_maps, Err(WadError::Other(_))));
     }
-    out_wad
-        .write(format!(
-            "output.{}",
-            if out_wad.was_zip { "pk3" } else { "wad" }
-        ))
-        .unwrap();
 }
@@ -0,0 +1,41 @@
+//! Compares loading a WAD in full against reading just its directory. Run with
+//! `cargo bench -p wad`, setting `WAD_BENCH_FILE` to an IWAD to measure it instead of a
+//! generated WAD of 100,000 small lumps.
+
+use std::fs::File;
+use std::hint::black_box;
+use std::path::PathBuf;
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+use wad::Directory;
+use wad::Wad;
+
+fn generated_wad() -> PathBuf {
+    let path = std::env::temp_dir().join("wad-bench-load.wad");
+    let mut wad = Wad::new_empty(false);
+    for i in 0..100_000u32 {
+        wad.append_lump_bytes(format!("L{i}"), vec![i as u8; 64])
+            .unwrap();
+    }
+    wad.write(&path).unwrap();
+    path
+}
+
+fn load(c: &mut Criterion) {
+    let path = std::env::var_os("WAD_BENCH_FILE").map_or_else(generated_wad, PathBuf::from);
+    let mut group = c.benchmark_group("load");
+    group.sample_size(20);
+    group.bench_function("Wad::new", |b| {
+        b.iter(|| Wad::new(black_box(&path)).unwrap())
+    });
+    group.bench_function("Directory::from_reader", |b| {
+        b.iter(|| Directory::from_reader(File::open(black_box(&path)).unwrap()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, load);
+criterion_main!(benches);
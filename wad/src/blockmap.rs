@@ -0,0 +1,135 @@
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+
+use crate::map::SECTOR_SIZE;
+use crate::MapSlice;
+use crate::Vertex;
+use crate::WadError;
+use crate::WadResult;
+
+/// The width and height of one blockmap block, in map units.
+const BLOCK_SIZE: i32 = 128;
+
+/// Space left between the map's bounding box and the blockmap's edges.
+const MARGIN: i32 = 8;
+
+/// Builds a blockmap for the given lines, each a pair of indices into `vertexes`. Every line is
+/// listed in each block it touches, found by clipping it against every block in its bounding box.
+/// Lines referring to missing vertices are left out. Returns `None` if the result is too large
+/// to be addressed by the format's 16-bit offsets.
+fn blockmap(vertexes: &[Vertex], lines: &[(u16, u16)]) -> Option<Vec<u8>> {
+    let xs = vertexes.iter().map(|v| v.x as i32);
+    let ys = vertexes.iter().map(|v| v.y as i32);
+    let origin_x = xs.clone().min().unwrap_or(0) - MARGIN;
+    let origin_y = ys.clone().min().unwrap_or(0) - MARGIN;
+    let columns = (xs.max().unwrap_or(0) - origin_x) / BLOCK_SIZE + 1;
+    let rows = (ys.max().unwrap_or(0) - origin_y) / BLOCK_SIZE + 1;
+
+    let mut blocks = vec![Vec::new(); (columns * rows) as usize];
+    for (index, &(start, end)) in lines.iter().enumerate() {
+        let (Some(start), Some(end)) = (vertexes.get(start as usize), vertexes.get(end as usize))
+        else {
+            continue;
+        };
+        let (x1, y1) = (start.x as i32 - origin_x, start.y as i32 - origin_y);
+        let (x2, y2) = (end.x as i32 - origin_x, end.y as i32 - origin_y);
+        for row in y1.min(y2) / BLOCK_SIZE..=y1.max(y2) / BLOCK_SIZE {
+            for column in x1.min(x2) / BLOCK_SIZE..=x1.max(x2) / BLOCK_SIZE {
+                let left = column * BLOCK_SIZE;
+                let bottom = row * BLOCK_SIZE;
+                if touches_box((x1, y1), (x2, y2), left, bottom) {
+                    blocks[(row * columns + column) as usize].push(index as u16);
+                }
+            }
+        }
+    }
+
+    // Header, offset table, then one list per block: a zero, the line indices, and a 0xFFFF.
+    let mut words = vec![
+        origin_x as u16,
+        origin_y as u16,
+        columns as u16,
+        rows as u16,
+    ];
+    let mut offset = words.len() + blocks.len();
+    for block in &blocks {
+        words.push(u16::try_from(offset).ok()?);
+        offset += block.len() + 2;
+    }
+    for block in &blocks {
+        words.push(0);
+        words.extend(block);
+        words.push(0xFFFF);
+    }
+    let mut data = vec![0; words.len() * 2];
+    LittleEndian::write_u16_into(&words, &mut data);
+    Some(data)
+}
+
+/// Whether the segment from `a` to `b` touches the block whose lower left corner is at
+/// (`left`, `bottom`), edges included.
+fn touches_box(a: (i32, i32), b: (i32, i32), left: i32, bottom: i32) -> bool {
+    // Liang-Barsky clipping: narrow the segment's parameter range down edge by edge.
+    let (dx, dy) = ((b.0 - a.0) as f64, (b.1 - a.1) as f64);
+    let mut t0 = 0.0;
+    let mut t1 = 1.0;
+    for (p, q) in [
+        (-dx, (a.0 - left) as f64),
+        (dx, (left + BLOCK_SIZE - a.0) as f64),
+        (-dy, (a.1 - bottom) as f64),
+        (dy, (bottom + BLOCK_SIZE - a.1) as f64),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return false;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t.max(t0);
+            } else {
+                t1 = t.min(t1);
+            }
+            if t0 > t1 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+impl MapSlice<'_> {
+    /// Builds a fresh BLOCKMAP from the map's LINEDEFS and VERTEXES.
+    pub fn build_blockmap(&self) -> WadResult<Vec<u8>> {
+        let Some(linedef_size) = self.format.linedef_size() else {
+            return Err(WadError::Other(format!(
+                "{} is a UDMF map, which has no blockmap",
+                self.name
+            )));
+        };
+        let vertexes = self
+            .lump("VERTEXES")
+            .map_or_else(Vec::new, |lump| Vertex::read_all(&lump.data));
+        // Both binary formats start a linedef with its two vertex indices.
+        let lines = self.lump("LINEDEFS").map_or_else(Vec::new, |lump| {
+            lump.data
+                .chunks_exact(linedef_size)
+                .map(|l| {
+                    (
+                        LittleEndian::read_u16(&l[0..]),
+                        LittleEndian::read_u16(&l[2..]),
+                    )
+                })
+                .collect()
+        });
+        blockmap(&vertexes, &lines).ok_or_else(|| WadError::BlockmapTooLarge(self.name.to_string()))
+    }
+
+    /// Builds a REJECT lump which allows every sector to see every other sector.
+    pub fn build_reject(&self) -> Vec<u8> {
+        let sectors = self
+            .lump("SECTORS")
+            .map_or(0, |l| l.data.len() / SECTOR_SIZE);
+        vec![0; (sectors * sectors).div_ceil(8)]
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::Lump;
+use crate::Wad;
+use crate::WadError;
+use crate::WadResult;
+
+/// The extensions of DeHackEd patch files.
+const DEH_EXTENSIONS: &[&str] = &["deh", "bex"];
+
+/// Finds the files in a directory with one of the given extensions, sorted by name.
+fn files_with_extension(dir: &Path, extensions: &[&str]) -> WadResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(WadError::CouldntReadLump)? {
+        let path = entry.map_err(WadError::CouldntReadLump)?.path();
+        let matches = path.extension().is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|wanted| ext.eq_ignore_ascii_case(wanted))
+        });
+        if matches && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The only one of a bundle's files of some kind, if it has any.
+fn only_file(dir: &Path, extensions: &[&'static str]) -> WadResult<Option<PathBuf>> {
+    let mut files = files_with_extension(dir, extensions)?;
+    if files.len() > 1 {
+        return Err(WadError::AmbiguousBundle {
+            dir: dir.display().to_string(),
+            kind: extensions[0],
+        });
+    }
+    Ok(files.pop())
+}
+
+impl Wad {
+    /// Reads a WAD from a directory. A directory holding a `.wad` file is a mod's distribution
+    /// bundle: the WAD is read, and if `apply_deh` is set, a `.deh` or `.bex` patch beside it
+    /// becomes its DEHACKED lump, replacing any it had. Any other directory is read as loose
+    /// lump files, like [`Wad::from_lump_dir`] does.
+    pub fn from_dir<P: AsRef<Path>>(path: P, apply_deh: bool) -> WadResult<Self> {
+        let path = path.as_ref();
+        let Some(wad_path) = only_file(path, &["wad"])? else {
+            return Self::from_lump_dir(path);
+        };
+        let mut wad = Self::new(wad_path)?;
+        if !apply_deh {
+            return Ok(wad);
+        }
+        if let Some(deh) = only_file(path, DEH_EXTENSIONS)? {
+            let data = fs::read(deh).map_err(WadError::CouldntReadLump)?;
+            wad.retain_lumps(|_, lump| lump.name != "DEHACKED");
+            wad.add_lump(Lump {
+                name: String::from("DEHACKED"),
+                data,
+            });
+        }
+        Ok(wad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MergeOptions;
+
+    #[test]
+    fn bundles_bring_their_deh_patch() {
+        let dir = std::env::temp_dir().join(format!("wad-{}-bundle", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut wad = Wad::new_empty(false);
+        wad.append_lump_bytes("DEHACKED", b"old".to_vec()).unwrap();
+        wad.append_lump_bytes("MAP01", Vec::new()).unwrap();
+        wad.append_lump_bytes("THINGS", Vec::new()).unwrap();
+        wad.write(dir.join("mod.wad")).unwrap();
+        fs::write(dir.join("MOD.DEH"), "Patch File for DeHackEd v3.0\n").unwrap();
+        fs::write(dir.join("readme.txt"), "read me").unwrap();
+        let patched = Wad::from_dir(&dir, true);
+        let unpatched = Wad::from_dir(&dir, false);
+        fs::write(dir.join("other.bex"), "").unwrap();
+        let ambiguous = Wad::from_dir(&dir, true);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut other = Wad::new_empty(false);
+        other.append_lump_bytes("MAP01", Vec::new()).unwrap();
+        other.append_lump_bytes("THINGS", Vec::new()).unwrap();
+        let (merged, _) = Wad::merge(&[other, patched.unwrap()], &MergeOptions::default()).unwrap();
+        assert_eq!(merged.count_lumps_named("DEHACKED"), 1);
+        assert_eq!(
+            merged.lump("DEHACKED").unwrap().data,
+            b"Patch File for DeHackEd v3.0\n"
+        );
+        assert_eq!(merged.maps().len(), 2);
+        assert_eq!(unpatched.unwrap().lump("DEHACKED").unwrap().data, b"old");
+        assert!(matches!(
+            ambiguous,
+            Err(WadError::AmbiguousBundle { kind: "deh", .. })
+        ));
+    }
+}
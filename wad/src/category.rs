@@ -0,0 +1,209 @@
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+
+use crate::map::group_maps;
+use crate::Lump;
+use crate::Wad;
+
+/// A rough kind of lump, used to group related lumps together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum LumpCategory {
+    /// PLAYPAL, COLORMAP and similar color tables.
+    Palette,
+    /// Text definition lumps, like MAPINFO or DECORATE.
+    Text,
+    /// TEXTURE1, PNAMES and the other lumps defining wall textures and their animations.
+    TextureDefinitions,
+    /// MUS or MIDI music.
+    Music,
+    /// DMX sound effects.
+    Sound,
+    /// Recorded demos.
+    Demo,
+    /// Anything not recognized.
+    Other,
+}
+
+/// The order [`Wad::sorted_by_category`] is usually given.
+pub const DEFAULT_CATEGORY_ORDER: &[LumpCategory] = &[
+    LumpCategory::Palette,
+    LumpCategory::Text,
+    LumpCategory::TextureDefinitions,
+    LumpCategory::Music,
+    LumpCategory::Sound,
+    LumpCategory::Demo,
+    LumpCategory::Other,
+];
+
+const PALETTE_LUMPS: &[&str] = &["PLAYPAL", "COLORMAP", "TINTTAB", "XLATAB"];
+
+const TEXT_LUMPS: &[&str] = &[
+    "MAPINFO", "ZMAPINFO", "EMAPINFO", "UMAPINFO", "SNDINFO", "SNDSEQ", "DECORATE", "ZSCRIPT",
+    "MENUDEF", "SBARINFO", "GLDEFS", "LANGUAGE", "KEYCONF", "GAMEINFO", "ANIMDEFS", "TEXTURES",
+    "DEHACKED", "LOCKDEFS", "TERRAIN", "DMXGUS", "DMXGUSC", "WADINFO",
+];
+
+const TEXTURE_DEFINITION_LUMPS: &[&str] =
+    &["TEXTURE1", "TEXTURE2", "PNAMES", "ANIMATED", "SWITCHES"];
+
+/// Guesses what a lump holds, from its name and the start of its data.
+pub fn classify_lump(lump: &Lump) -> LumpCategory {
+    let name = lump.name.as_str();
+    let data = &lump.data;
+    if PALETTE_LUMPS.contains(&name) {
+        LumpCategory::Palette
+    } else if TEXT_LUMPS.contains(&name) {
+        LumpCategory::Text
+    } else if TEXTURE_DEFINITION_LUMPS.contains(&name) {
+        LumpCategory::TextureDefinitions
+    } else if name.starts_with("D_") || data.starts_with(b"MUS\x1a") || data.starts_with(b"MThd") {
+        LumpCategory::Music
+    } else if data.len() >= 8 && LittleEndian::read_u16(data) == 3 {
+        // the DMX sound format number
+        LumpCategory::Sound
+    } else if name.starts_with("DEMO") {
+        LumpCategory::Demo
+    } else {
+        LumpCategory::Other
+    }
+}
+
+impl Lump {
+    /// A file extension suiting the lump's contents, without the dot, for exporting it to a pk3.
+    /// Recognized file formats get their usual extension, text lumps get `txt`, and anything
+    /// else gets `lmp`.
+    pub fn guess_extension(&self) -> &'static str {
+        let data = &self.data;
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            "png"
+        } else if data.starts_with(b"MUS\x1a") {
+            "mus"
+        } else if data.starts_with(b"MThd") {
+            "mid"
+        } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WAVE") {
+            "wav"
+        } else if classify_lump(self) == LumpCategory::Text {
+            "txt"
+        } else {
+            "lmp"
+        }
+    }
+}
+
+/// Finds the `X_START`/`X_END` blocks in a sequence of lump names, of any namespace. An
+/// unterminated block runs to the end.
+fn marker_blocks(names: &[&str]) -> Vec<std::ops::Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < names.len() {
+        let Some(prefix) = names[i].strip_suffix("_START") else {
+            i += 1;
+            continue;
+        };
+        let end_marker = format!("{prefix}_END");
+        let end = names[i + 1..]
+            .iter()
+            .position(|name| *name == end_marker)
+            .map_or(names.len(), |pos| i + 1 + pos + 1);
+        blocks.push(i..end);
+        i = end;
+    }
+    blocks
+}
+
+impl Wad {
+    /// Reorders the WAD so lumps of the same category sit together, in the given category order.
+    /// Categories left out of `order` come after the rest. Maps and namespace blocks aren't
+    /// broken up; they follow the grouped lumps, in their original order. Lumps in the same
+    /// category keep their relative order too.
+    pub fn sorted_by_category(&self, order: &[LumpCategory]) -> Wad {
+        let names = self
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        let mut grouped = vec![false; names.len()];
+        for range in group_maps(&names)
+            .into_iter()
+            .map(|group| group.range)
+            .chain(marker_blocks(&names))
+        {
+            grouped[range].fill(true);
+        }
+        let mut free = (0..names.len())
+            .filter(|&i| !grouped[i])
+            .collect::<Vec<_>>();
+        free.sort_by_key(|&i| {
+            let category = classify_lump(&self.lumps[i]);
+            order
+                .iter()
+                .position(|&c| c == category)
+                .unwrap_or(order.len())
+        });
+        let mut out = Wad::with_capacity(self.lumps.len());
+        out.was_zip = self.was_zip;
+        for i in free
+            .into_iter()
+            .chain((0..names.len()).filter(|&i| grouped[i]))
+        {
+            out.add_lump(self.lumps[i].clone());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(wad: &Wad) -> Vec<&str> {
+        wad.lumps.iter().map(|l| l.name.as_str()).collect()
+    }
+
+    #[test]
+    fn lumps_are_grouped_around_maps_and_namespaces() {
+        let mut wad = Wad::new_empty(false);
+        for name in [
+            "D_E1M1", "PLAYPAL", "F_START", "D_FLAT", "FLOOR0_1", "F_END", "DEMO1", "D_E1M2",
+            "MAP01", "THINGS", "D_RUNNIN",
+        ] {
+            wad.append_lump_bytes(name, Vec::new()).unwrap();
+        }
+        assert_eq!(
+            names(&wad.sorted_by_category(DEFAULT_CATEGORY_ORDER)),
+            [
+                "PLAYPAL", "D_E1M1", "D_E1M2", "D_RUNNIN", "DEMO1", "F_START", "D_FLAT",
+                "FLOOR0_1", "F_END", "MAP01", "THINGS",
+            ]
+        );
+        assert_eq!(
+            names(&wad.sorted_by_category(&[LumpCategory::Music])),
+            [
+                "D_E1M1", "D_E1M2", "D_RUNNIN", "PLAYPAL", "DEMO1", "F_START", "D_FLAT",
+                "FLOOR0_1", "F_END", "MAP01", "THINGS",
+            ]
+        );
+    }
+
+    #[test]
+    fn extensions_follow_the_contents() {
+        let mut wave = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wave.resize(44, 0);
+        for (name, data, extension) in [
+            ("TITLEPIC", &b"\x89PNG\r\n\x1a\n\0"[..], "png"),
+            ("D_RUNNIN", b"MUS\x1a\0\0", "mus"),
+            ("D_INTRO", b"MThd\0\0\0\x06", "mid"),
+            ("DSPISTOL", &wave, "wav"),
+            ("RIFFRAFF", b"RIFF\0\0\0\0AVI ", "lmp"),
+            ("MAPINFO", b"map MAP01 \"One\" {}", "txt"),
+            ("PLAYPAL", &[0; 768], "lmp"),
+        ] {
+            let lump = Lump {
+                name: name.to_string(),
+                data: data.to_vec(),
+            };
+            assert_eq!(lump.guess_extension(), extension, "{name}");
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::Lump;
+use crate::Wad;
+
+type Hash = [u8; 32];
+
+/// Prefixes which keep a leaf's hash from ever matching an internal node's.
+const LEAF: u8 = 0;
+const NODE: u8 = 1;
+
+/// A hash tree over a WAD's lumps, for finding which lumps changed without comparing them all.
+/// Each leaf hashes one lump's name and data, and each node above hashes its two children's
+/// hashes. See [`Wad::checksum_tree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleTree {
+    /// The hashes of each level of the tree, from the leaves up to the root. A node without a
+    /// sibling is hashed alone.
+    levels: Vec<Vec<Hash>>,
+}
+
+fn leaf_hash(lump: &Lump) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF]);
+    // lump names never hold a NUL, so this separates the name from the data unambiguously
+    hasher.update(lump.name.as_bytes());
+    hasher.update([0]);
+    hasher.update(&lump.data);
+    hasher.finalize().into()
+}
+
+impl MerkleTree {
+    /// Builds the tree over `lumps`, in order.
+    pub fn new(lumps: &[Lump]) -> Self {
+        let mut levels = vec![lumps.iter().map(leaf_hash).collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|children| {
+                    let mut hasher = Sha256::new();
+                    hasher.update([NODE]);
+                    for child in children {
+                        hasher.update(child);
+                    }
+                    hasher.finalize().into()
+                })
+                .collect();
+            levels.push(level);
+        }
+        Self { levels }
+    }
+
+    /// The hash of the whole tree, or `None` for a WAD with no lumps.
+    pub fn root(&self) -> Option<Hash> {
+        self.levels.last().unwrap().first().copied()
+    }
+
+    /// The hash of each lump, in directory order.
+    pub fn leaves(&self) -> &[Hash] {
+        &self.levels[0]
+    }
+
+    /// The indices of the lumps which differ between the trees' WADs, in order. Lumps only one
+    /// of them has count as different. Subtrees with equal hashes are skipped without looking
+    /// inside, so a few changes in a large WAD are found in about logarithmic time. Lumps are
+    /// compared by position, so inserting or removing one makes every lump after it differ.
+    pub fn diff(&self, other: &MerkleTree) -> Vec<usize> {
+        let mut changed = Vec::new();
+        let top = self.levels.len().max(other.levels.len()) - 1;
+        self.diff_node(other, top, 0, &mut changed);
+        changed
+    }
+
+    /// Finds the differing leaves under node `index` of `level`.
+    fn diff_node(&self, other: &MerkleTree, level: usize, index: usize, changed: &mut Vec<usize>) {
+        let ours = self.levels.get(level).and_then(|l| l.get(index));
+        let theirs = other.levels.get(level).and_then(|l| l.get(index));
+        if ours == theirs {
+            return;
+        }
+        if level == 0 {
+            changed.push(index);
+            return;
+        }
+        for child in [2 * index, 2 * index + 1] {
+            self.diff_node(other, level - 1, child, changed);
+        }
+    }
+}
+
+impl Wad {
+    /// Builds a [`MerkleTree`] over the WAD's lumps.
+    pub fn checksum_tree(&self) -> MerkleTree {
+        MerkleTree::new(&self.lumps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lumps named `L0`, `L1` and so on, each holding its index.
+    fn lumps(count: usize) -> Vec<Lump> {
+        (0..count)
+            .map(|i| Lump {
+                name: format!("L{i}"),
+                data: vec![i as u8],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn one_changed_lump_is_found() {
+        let lumps = lumps(7);
+        let tree = MerkleTree::new(&lumps);
+        assert_eq!(tree.diff(&tree), []);
+        for changed in 0..lumps.len() {
+            let mut edited = lumps.clone();
+            edited[changed].data.push(0);
+            assert_eq!(tree.diff(&MerkleTree::new(&edited)), [changed]);
+            // a rename changes the leaf too
+            let mut renamed = lumps.clone();
+            renamed[changed].name.push('X');
+            assert_eq!(MerkleTree::new(&renamed).diff(&tree), [changed]);
+        }
+    }
+
+    #[test]
+    fn trees_of_different_heights_are_compared_by_position() {
+        let diff = |old: usize, new: usize| {
+            MerkleTree::new(&lumps(old)).diff(&MerkleTree::new(&lumps(new)))
+        };
+        assert_eq!(diff(5, 6), [5]);
+        assert_eq!(diff(6, 5), [5]);
+        assert_eq!(diff(4, 5), [4]);
+        assert_eq!(diff(2, 9), [2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(diff(0, 3), [0, 1, 2]);
+        assert_eq!(diff(1, 0), [0]);
+        assert_eq!(diff(0, 0), []);
+    }
+
+    #[test]
+    fn only_an_empty_tree_has_no_root() {
+        assert_eq!(MerkleTree::new(&[]).root(), None);
+        let one = MerkleTree::new(&lumps(1));
+        assert_eq!(one.root(), Some(one.leaves()[0]));
+        assert_ne!(
+            MerkleTree::new(&lumps(3)).root(),
+            MerkleTree::new(&lumps(4)).root()
+        );
+    }
+}
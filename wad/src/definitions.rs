@@ -0,0 +1,76 @@
+/// Lists the top-level definitions in a ZDoom-style definition lump (GLDEFS, MENUDEF, ...). A
+/// definition is named by the words leading up to its opening brace, so `pointlight ZOMBIEATK
+/// { ... }` yields `pointlight ZOMBIEATK`. Nothing inside the braces is looked at.
+pub fn definition_names(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut header = Vec::new();
+    let mut depth = 0usize;
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '/' if chars.peek().is_some_and(|&(_, c)| c == '/') => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek().is_some_and(|&(_, c)| c == '*') => {
+                chars.next();
+                let mut prev = '\0';
+                for (_, c) in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            '"' => {
+                let mut word = String::new();
+                for (_, c) in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                }
+                header.push(word);
+            }
+            '{' => {
+                if depth == 0 && !header.is_empty() {
+                    names.push(header.join(" "));
+                }
+                header.clear();
+                depth += 1;
+            }
+            '}' => {
+                header.clear();
+                depth = depth.saturating_sub(1);
+            }
+            ';' => header.clear(),
+            '#' => {
+                // preprocessor-style directive, like #include
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                header.clear();
+            }
+            c if c.is_whitespace() => {}
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || "{}\";/".contains(c) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                if depth == 0 {
+                    header.push(text[start..end].to_string());
+                }
+            }
+        }
+    }
+    names
+}
@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+
+/// The editor numbers of Doom's thing types, in DeHackEd's order, so `Thing 2` is 3004. Types
+/// which can't be placed in maps, like projectiles, have none.
+const DOOM_THING_IDS: &[Option<u16>] = &[
+    None,
+    Some(3004),
+    Some(9),
+    Some(64),
+    None,
+    Some(66),
+    None,
+    None,
+    Some(67),
+    None,
+    Some(65),
+    Some(3001),
+    Some(3002),
+    Some(58),
+    Some(3005),
+    Some(3003),
+    None,
+    Some(69),
+    Some(3006),
+    Some(7),
+    Some(68),
+    Some(16),
+    Some(71),
+    Some(84),
+    Some(72),
+    Some(88),
+    Some(89),
+    Some(87),
+    None,
+    None,
+    Some(2035),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    Some(14),
+    None,
+    Some(2018),
+    Some(2019),
+    Some(2014),
+    Some(2015),
+    Some(5),
+    Some(13),
+    Some(6),
+    Some(39),
+    Some(38),
+    Some(40),
+    Some(2011),
+    Some(2012),
+    Some(2013),
+    Some(2022),
+    Some(2023),
+    Some(2024),
+    Some(2025),
+    Some(2026),
+    Some(2045),
+    Some(83),
+    Some(2007),
+    Some(2048),
+    Some(2010),
+    Some(2046),
+    Some(2047),
+    Some(17),
+    Some(2008),
+    Some(2049),
+    Some(8),
+    Some(2006),
+    Some(2002),
+    Some(2005),
+    Some(2003),
+    Some(2004),
+    Some(2001),
+    Some(82),
+    Some(85),
+    Some(86),
+    Some(2028),
+    Some(30),
+    Some(31),
+    Some(32),
+    Some(33),
+    Some(37),
+    Some(36),
+    Some(41),
+    Some(42),
+    Some(43),
+    Some(44),
+    Some(45),
+    Some(46),
+    Some(55),
+    Some(56),
+    Some(57),
+    Some(47),
+    Some(48),
+    Some(34),
+    Some(35),
+    Some(49),
+    Some(50),
+    Some(51),
+    Some(52),
+    Some(53),
+    Some(59),
+    Some(60),
+    Some(61),
+    Some(62),
+    Some(63),
+    Some(22),
+    Some(15),
+    Some(18),
+    Some(21),
+    Some(23),
+    Some(20),
+    Some(19),
+    Some(10),
+    Some(12),
+    Some(28),
+    Some(24),
+    Some(27),
+    Some(29),
+    Some(25),
+    Some(26),
+    Some(54),
+    Some(70),
+    Some(73),
+    Some(74),
+    Some(75),
+    Some(76),
+    Some(77),
+    Some(78),
+    Some(79),
+    Some(80),
+    Some(81),
+];
+
+/// A `Thing` block of a DeHackEd patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DehackedThing {
+    /// The thing type's number, counting from 1.
+    pub number: usize,
+    /// The fields the patch sets, like `Hit points`, with their values.
+    pub fields: BTreeMap<String, String>,
+}
+
+impl DehackedThing {
+    /// The editor number maps place the thing with: the patch's `ID #` if it sets one, or else
+    /// the one Doom gives the type.
+    pub fn editor_number(&self) -> Option<u16> {
+        match self.fields.get("ID #") {
+            Some(id) => id.parse().ok(),
+            None => DOOM_THING_IDS.get(self.number.checked_sub(1)?).copied()?,
+        }
+    }
+}
+
+/// Reads the `Thing` blocks of a DeHackEd patch. A later block for the same thing adds to the
+/// fields of an earlier one. The rest of the patch is ignored.
+pub fn parse_dehacked_things(text: &str) -> BTreeMap<usize, DehackedThing> {
+    let mut things = BTreeMap::<usize, DehackedThing>::new();
+    let mut current = None;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if let Some(thing) = current.and_then(|n| things.get_mut(&n)) {
+                let key = key.trim().to_string();
+                thing.fields.insert(key, value.trim().to_string());
+            }
+            continue;
+        }
+        // any other line starts a new block
+        current = line
+            .strip_prefix("Thing ")
+            .and_then(|rest| rest.split_whitespace().next()?.parse().ok());
+        if let Some(number) = current {
+            things.entry(number).or_insert_with(|| DehackedThing {
+                number,
+                fields: BTreeMap::new(),
+            });
+        }
+    }
+    things
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use crate::Lump;
+use crate::Wad;
+
+/// One lump of a WAD, told apart from others with the same name by how many come before it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LumpId {
+    pub name: String,
+    /// 0 for the first lump with the name, 1 for the second, and so on.
+    pub occurrence: usize,
+}
+
+/// A lump whose data differs between two WADs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ChangedLump {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub id: LumpId,
+    pub old_size: usize,
+    pub new_size: usize,
+}
+
+/// The differences between two WADs, lump by lump. See [`Wad::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct WadDiff {
+    /// Lumps only the new WAD has, in its order.
+    pub added: Vec<LumpId>,
+    /// Lumps only the old WAD has, in its order.
+    pub removed: Vec<LumpId>,
+    /// Lumps both have, with different data, in the old WAD's order.
+    pub changed: Vec<ChangedLump>,
+}
+
+impl WadDiff {
+    /// Whether the WADs hold the same lumps.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Pairs each lump with its occurrence number.
+fn identify(lumps: &[Lump]) -> impl Iterator<Item = (LumpId, &Lump)> {
+    let mut seen = HashMap::<&str, usize>::new();
+    lumps.iter().map(move |lump| {
+        let count = seen.entry(&lump.name).or_default();
+        let id = LumpId {
+            name: lump.name.clone(),
+            occurrence: *count,
+        };
+        *count += 1;
+        (id, lump)
+    })
+}
+
+impl Wad {
+    /// Compares this WAD with a newer version of it. Lumps are matched up by name, with the
+    /// n-th of several same-named lumps matched to the other WAD's n-th. Moving a lump doesn't
+    /// count as a change.
+    pub fn diff(&self, other: &Wad) -> WadDiff {
+        let old = identify(&self.lumps).collect::<HashMap<_, _>>();
+        let new = identify(&other.lumps).collect::<HashMap<_, _>>();
+        let mut diff = WadDiff::default();
+        for (id, lump) in identify(&self.lumps) {
+            match new.get(&id) {
+                Some(new_lump) if new_lump.data != lump.data => diff.changed.push(ChangedLump {
+                    id,
+                    old_size: lump.data.len(),
+                    new_size: new_lump.data.len(),
+                }),
+                Some(_) => {}
+                None => diff.removed.push(id),
+            }
+        }
+        diff.added = identify(&other.lumps)
+            .map(|(id, _)| id)
+            .filter(|id| !old.contains_key(id))
+            .collect();
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wad(lumps: &[(&str, &[u8])]) -> Wad {
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in lumps {
+            wad.append_lump_bytes(*name, data.to_vec()).unwrap();
+        }
+        wad
+    }
+
+    fn id(name: &str, occurrence: usize) -> LumpId {
+        LumpId {
+            name: name.to_string(),
+            occurrence,
+        }
+    }
+
+    #[test]
+    fn diffs_match_duplicates_by_position() {
+        let old = wad(&[
+            ("MAP01", b""),
+            ("THINGS", b"one"),
+            ("MAP02", b""),
+            ("THINGS", b"two"),
+            ("ENDOOM", b"bye"),
+        ]);
+        let new = wad(&[
+            ("ENDOOM", b"bye"),
+            ("MAP01", b""),
+            ("THINGS", b"one"),
+            ("MAP02", b""),
+            ("THINGS", b"2"),
+            ("MAP03", b""),
+        ]);
+        assert!(old.diff(&old).is_empty());
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            WadDiff {
+                added: vec![id("MAP03", 0)],
+                removed: Vec::new(),
+                changed: vec![ChangedLump {
+                    id: id("THINGS", 1),
+                    old_size: 3,
+                    new_size: 1,
+                }],
+            }
+        );
+        let reverse = new.diff(&old);
+        assert_eq!(reverse.removed, [id("MAP03", 0)]);
+        assert!(reverse.added.is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// One `instrument, bank, bank, ...` line from a DMXGUS or DMXGUSC lump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GusMapping {
+    /// The MIDI instrument number. Percussion is numbered from 128.
+    pub instrument: u32,
+    /// The patch used for each memory size, followed by the patch file name.
+    pub patches: Vec<String>,
+}
+
+fn parse_line(line: &str) -> Option<GusMapping> {
+    let line = line.split('#').next().unwrap();
+    let mut fields = line.split(',').map(str::trim);
+    let instrument = fields.next()?.parse().ok()?;
+    Some(GusMapping {
+        instrument,
+        patches: fields.map(str::to_string).collect(),
+    })
+}
+
+/// Reads the instrument mappings from the text of a DMXGUS or DMXGUSC lump. Comments (starting
+/// with `#`) and lines that don't start with an instrument number are skipped.
+pub fn parse_dmxgus(text: &str) -> Vec<GusMapping> {
+    text.lines().filter_map(parse_line).collect()
+}
+
+/// Merges two GUS configs by instrument number. The base config's lines stay in place, with the
+/// overlay's mapping swapped in for every instrument both define. Instruments only the overlay
+/// defines are added at the end.
+pub fn merge_dmxgus(base: &str, overlay: &str) -> String {
+    let mut overrides = HashMap::new();
+    let mut order = Vec::new();
+    for line in overlay.lines() {
+        if let Some(mapping) = parse_line(line) {
+            if overrides.insert(mapping.instrument, line).is_none() {
+                order.push(mapping.instrument);
+            }
+        }
+    }
+    let newline = if base.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut seen = HashSet::new();
+    let mut out = String::new();
+    for line in base.lines() {
+        let line = match parse_line(line) {
+            Some(mapping) => {
+                seen.insert(mapping.instrument);
+                overrides.get(&mapping.instrument).copied().unwrap_or(line)
+            }
+            None => line,
+        };
+        out.push_str(line);
+        out.push_str(newline);
+    }
+    for instrument in order.into_iter().filter(|i| !seen.contains(i)) {
+        out.push_str(overrides[&instrument]);
+        out.push_str(newline);
+    }
+    out
+}
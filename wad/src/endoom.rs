@@ -0,0 +1,135 @@
+use crate::Lump;
+use crate::WadError;
+use crate::WadResult;
+
+const ENDOOM_SIZE: usize = 4000;
+const COLUMNS: usize = 80;
+
+/// The glyphs code page 437 shows for the control characters.
+const CP437_LOW: [char; 32] = [
+    ' ', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼', '►', '◄', '↕',
+    '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// Code page 437 above ASCII.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', 'É', 'æ', 'Æ',
+    'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', 'á', 'í', 'ó', 'ú', 'ñ', 'Ñ',
+    'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕',
+    '╣', '║', '╗', '╝', '╜', '╛', '┐', '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦',
+    '╠', '═', '╬', '╧', '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐',
+    '▀', 'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', '≡', '±',
+    '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Maps VGA color numbers (blue is 1, red is 4) to ANSI ones (red is 1, blue is 4).
+const VGA_TO_ANSI: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+fn cp437(byte: u8) -> char {
+    match byte {
+        0x00..=0x1F => CP437_LOW[byte as usize],
+        0x7F => '⌂',
+        0x20..=0x7E => byte as char,
+        0x80..=0xFF => CP437_HIGH[byte as usize - 0x80],
+    }
+}
+
+/// Splits an ENDOOM lump into its 25 rows of (character, attribute) pairs.
+fn rows(lump: &Lump) -> WadResult<std::slice::Chunks<'_, u8>> {
+    if lump.data.len() != ENDOOM_SIZE {
+        return Err(WadError::InvalidLumpSize {
+            name: lump.name.clone(),
+            size: lump.data.len(),
+        });
+    }
+    Ok(lump.data.chunks(COLUMNS * 2))
+}
+
+/// Converts an ENDOOM screen to text, one line per row.
+pub fn render_endoom(lump: &Lump) -> WadResult<String> {
+    let lines = rows(lump)?
+        .map(|row| row.chunks(2).map(|cell| cp437(cell[0])).collect::<String>())
+        .collect::<Vec<_>>();
+    Ok(lines.join("\n"))
+}
+
+/// Like [`render_endoom`], with the screen's colors (and blinking) given by ANSI escape codes.
+pub fn render_endoom_ansi(lump: &Lump) -> WadResult<String> {
+    let mut lines = Vec::new();
+    for row in rows(lump)? {
+        let mut line = String::new();
+        let mut current = None;
+        for cell in row.chunks(2) {
+            let (character, attribute) = (cell[0], cell[1]);
+            if current != Some(attribute) {
+                let fg = attribute & 0x0F;
+                let bg = (attribute >> 4) & 0x07;
+                let fg_base = if fg & 0x08 != 0 { 90 } else { 30 };
+                line.push_str(&format!(
+                    "\x1b[0;{};{}",
+                    fg_base + VGA_TO_ANSI[(fg & 0x07) as usize],
+                    40 + VGA_TO_ANSI[bg as usize]
+                ));
+                if attribute & 0x80 != 0 {
+                    line.push_str(";5");
+                }
+                line.push('m');
+                current = Some(attribute);
+            }
+            line.push(cp437(character));
+        }
+        line.push_str("\x1b[0m");
+        lines.push(line);
+    }
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ENDOOM of blank white-on-blue cells, with `text` written from the top left in
+    /// bright yellow on red, blinking.
+    fn endoom(text: &[u8]) -> Lump {
+        let mut data = [0x20, 0x17].repeat(ENDOOM_SIZE / 2);
+        for (i, &c) in text.iter().enumerate() {
+            data[i * 2] = c;
+            data[i * 2 + 1] = 0xCE;
+        }
+        Lump {
+            name: String::from("ENDOOM"),
+            data,
+        }
+    }
+
+    #[test]
+    fn endoom_renders_as_25_lines_of_cp437() {
+        let text = render_endoom(&endoom(b"\x01 DOOM \xDB\xB0")).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 25);
+        assert!(lines.iter().all(|line| line.chars().count() == COLUMNS));
+        assert!(lines[0].starts_with("☺ DOOM █░ "));
+        assert_eq!(lines[1].trim(), "");
+    }
+
+    #[test]
+    fn ansi_endoom_sets_colors_and_blinking() {
+        let text = render_endoom_ansi(&endoom(b"HI")).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 25);
+        assert!(lines[0].starts_with("\x1b[0;93;41;5mHI\x1b[0;37;44m "));
+        assert!(lines[0].ends_with(" \x1b[0m"));
+    }
+
+    #[test]
+    fn endoom_must_be_4000_bytes() {
+        let lump = Lump {
+            name: String::from("ENDOOM"),
+            data: vec![0; 3999],
+        };
+        assert!(matches!(
+            render_endoom(&lump),
+            Err(WadError::InvalidLumpSize { size: 3999, .. })
+        ));
+    }
+}
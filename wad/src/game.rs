@@ -0,0 +1,98 @@
+/// The game a merged WAD is made for, which decides how its maps are named and which slots are
+/// special.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Game {
+    /// Episodes of nine maps, `E1M1` to `E1M9`, with `ExM9` the secret level.
+    Doom,
+    /// `MAP01` onwards, with `MAP31` and `MAP32` the secret levels.
+    #[default]
+    Doom2,
+    /// Episodes of nine maps like Doom, with `ExM9` the secret level.
+    Heretic,
+    /// `MAP01` onwards. Hexen's hubs have no secret levels.
+    Hexen,
+}
+
+/// How many maps an episode of an episodic game has.
+const EPISODE_LENGTH: usize = 9;
+
+/// The music lumps Doom II plays in `MAP01` through `MAP32`.
+const DOOM2_MUSIC: &[&str] = &[
+    "D_RUNNIN", "D_STALKS", "D_COUNTD", "D_BETWEE", "D_DOOM", "D_THE_DA", "D_SHAWN", "D_DDTBLU",
+    "D_IN_CIT", "D_DEAD", "D_STLKS2", "D_THEDA2", "D_DOOM2", "D_DDTBL2", "D_RUNNI2", "D_DEAD2",
+    "D_STLKS3", "D_ROMERO", "D_SHAWN2", "D_MESSAG", "D_COUNT2", "D_DDTBL3", "D_AMPIE", "D_THEDA3",
+    "D_ADRIAN", "D_MESSG2", "D_ROMER2", "D_TENSE", "D_SHAWN3", "D_OPENIN", "D_EVIL", "D_ULTIMA",
+];
+
+impl Game {
+    fn is_episodic(self) -> bool {
+        matches!(self, Self::Doom | Self::Heretic)
+    }
+
+    /// The marker name of a slot, counting from 1. Episodic games count nine slots per episode,
+    /// so slot 12 is `E2M3`.
+    pub fn slot_name(self, slot: usize) -> String {
+        if self.is_episodic() {
+            let (episode, map) = ((slot - 1) / EPISODE_LENGTH, (slot - 1) % EPISODE_LENGTH);
+            format!("E{}M{}", episode + 1, map + 1)
+        } else {
+            format!("MAP{slot:02}")
+        }
+    }
+
+    /// The episode a slot belongs to, counting from 1, in games with episodes.
+    pub fn episode(self, slot: usize) -> Option<usize> {
+        (self.is_episodic() && slot > 0).then(|| (slot - 1) / EPISODE_LENGTH + 1)
+    }
+
+    /// Reads a slot from its marker name, the reverse of [`Game::slot_name`].
+    pub fn parse_slot(self, name: &str) -> Option<usize> {
+        let name = name.to_ascii_uppercase();
+        if self.is_episodic() {
+            let (episode, map) = name.strip_prefix('E')?.split_once('M')?;
+            let (episode, map) = (episode.parse::<usize>().ok()?, map.parse::<usize>().ok()?);
+            ((1..=EPISODE_LENGTH).contains(&map) && episode > 0)
+                .then(|| (episode - 1) * EPISODE_LENGTH + map)
+        } else {
+            name.strip_prefix("MAP")?
+                .parse()
+                .ok()
+                .filter(|&slot| slot > 0)
+        }
+    }
+
+    /// The slots the game reserves for secret levels. For episodic games, these are the last
+    /// maps of the first four episodes.
+    pub fn secret_slots(self) -> &'static [usize] {
+        match self {
+            Self::Doom | Self::Heretic => &[9, 18, 27, 36],
+            Self::Doom2 => &[31, 32],
+            Self::Hexen => &[],
+        }
+    }
+
+    /// The music lump the game itself plays in a slot, without a MAPINFO saying otherwise.
+    /// Episodic games only have their own music for the first three episodes, and Hexen takes
+    /// all of its music from MAPINFO.
+    pub fn vanilla_music(self, slot: usize) -> Option<String> {
+        let prefix = match self {
+            Self::Doom => "D_",
+            Self::Heretic => "MUS_",
+            Self::Doom2 => return DOOM2_MUSIC.get(slot.checked_sub(1)?).map(|s| s.to_string()),
+            Self::Hexen => return None,
+        };
+        (1..=3 * EPISODE_LENGTH)
+            .contains(&slot)
+            .then(|| format!("{prefix}{}", self.slot_name(slot)))
+    }
+
+    /// Where a generated MAPINFO sends the player after the last map.
+    pub fn ending(self) -> &'static str {
+        match self {
+            Self::Doom => "EndGame4",
+            Self::Doom2 => "EndGameC",
+            Self::Heretic => "EndGame1",
+            Self::Hexen => "EndTitle",
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use crate::map::group_maps;
+use crate::namespace::is_marker;
+use crate::Wad;
+
+impl Wad {
+    /// Removes lumps which are byte-for-byte the same as the base IWAD's lump of the same name,
+    /// ignoring case, like a PLAYPAL copied straight out of `doom2.wad`. They only take up space,
+    /// since the IWAD's copy would be used anyway. Map lumps and markers are never removed.
+    /// Returns the names of the removed lumps.
+    pub fn strip_iwad_duplicates(&mut self, iwad: &Wad) -> Vec<String> {
+        let iwad_lumps = iwad
+            .lumps
+            .iter()
+            .map(|lump| (lump.name.to_ascii_uppercase(), &lump.data))
+            .collect::<HashMap<_, _>>();
+        let names = self
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        let mut in_map = vec![false; names.len()];
+        for group in group_maps(&names) {
+            in_map[group.range].fill(true);
+        }
+        let mut removed = Vec::new();
+        self.retain_lumps(|i, lump| {
+            let duplicate = !in_map[i]
+                && !is_marker(&lump.name)
+                && iwad_lumps.get(&lump.name.to_ascii_uppercase()) == Some(&&lump.data);
+            if duplicate {
+                removed.push(lump.name.clone());
+            }
+            !duplicate
+        });
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lump;
+
+    /// A WAD holding `lumps`, with their names as given, in any case.
+    fn wad(lumps: &[(&str, &[u8])]) -> Wad {
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in lumps {
+            wad.add_lump(Lump {
+                name: name.to_string(),
+                data: data.to_vec(),
+            });
+        }
+        wad
+    }
+
+    #[test]
+    fn lumps_matching_the_iwad_are_stripped() {
+        let iwad = wad(&[
+            ("PLAYPAL", &[1; 768]),
+            ("COLORMAP", &[2; 256]),
+            ("ENDOOM", &[3; 4000]),
+            ("MAP01", b""),
+            ("THINGS", b"things"),
+        ]);
+        let mut pwad = wad(&[
+            ("playpal", &[1; 768]),
+            ("COLORMAP", &[9; 256]),
+            ("F_START", b""),
+            ("F_END", b""),
+            ("MAP01", b""),
+            ("THINGS", b"things"),
+        ]);
+        assert_eq!(pwad.strip_iwad_duplicates(&iwad), ["playpal"]);
+        let names = pwad
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["COLORMAP", "F_START", "F_END", "MAP01", "THINGS"]);
+        pwad.assert_valid().unwrap();
+    }
+}
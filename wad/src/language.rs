@@ -0,0 +1,92 @@
+/// Lists the strings a ZDoom LANGUAGE lump sets, as `(language, key)` pairs in the order they're
+/// set. Languages come from the section headers, so a string under `[enu default]` is listed
+/// once for each. Both are lowercased, since neither is case-sensitive. Strings before the first
+/// section header are left out, as are `$ifgame(...)` conditions on a string.
+pub fn language_keys(text: &str) -> Vec<(String, String)> {
+    let mut keys = Vec::new();
+    let mut languages = Vec::new();
+    let mut statement = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = '\0';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            '"' => {
+                // the string's contents can't hold anything the statement is split on
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    if c == '"' && !escaped {
+                        break;
+                    }
+                    escaped = c == '\\' && !escaped;
+                }
+                statement.push_str("\"\"");
+            }
+            '[' if statement.trim().is_empty() => {
+                languages = chars
+                    .by_ref()
+                    .take_while(|&c| c != ']')
+                    .collect::<String>()
+                    .split_whitespace()
+                    .map(str::to_ascii_lowercase)
+                    .collect();
+                statement.clear();
+            }
+            ';' => {
+                if let Some((key, _)) = statement.split_once('=') {
+                    if let Some(key) = key.split_whitespace().last() {
+                        for language in &languages {
+                            keys.push((language.clone(), key.to_ascii_lowercase()));
+                        }
+                    }
+                }
+                statement.clear();
+            }
+            c => statement.push(c),
+        }
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_listed_under_each_language_of_their_section() {
+        let text = "IGNORED = \"before any section\";\n\
+                    [enu default]\n\
+                    HUSTR_1 = \"level 1: entryway; \\\"the first\\\"\"; // HUSTR_9 = \"\";\n\
+                    /* GOTARMOR = \"\"; */\n\
+                    [fra]\n\
+                    $ifgame(doom) GotArmor =\n\"Armure\"\n\"!\";\n";
+        let keys = language_keys(text);
+        let pairs = keys
+            .iter()
+            .map(|(l, k)| (l.as_str(), k.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pairs,
+            [
+                ("enu", "hustr_1"),
+                ("default", "hustr_1"),
+                ("fra", "gotarmor")
+            ]
+        );
+    }
+}
@@ -1,4 +1,6 @@
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::Cursor;
 use std::io::Read;
@@ -8,6 +10,7 @@ use std::io::Write;
 use std::path::Path;
 use std::string::FromUtf8Error;
 
+use byteorder::ByteOrder;
 use byteorder::LittleEndian;
 use byteorder::ReadBytesExt;
 use byteorder::WriteBytesExt;
@@ -15,37 +18,259 @@ use zip::write::FileOptions;
 use zip::ZipArchive;
 use zip::ZipWriter;
 
+mod blockmap;
+mod bundle;
+mod category;
+#[cfg(feature = "checksum")]
+mod checksum;
+mod definitions;
+mod dehacked;
+mod diff;
+mod dmxgus;
+mod endoom;
+mod game;
+mod iwad;
+mod language;
+mod loadorder;
+mod lumpdir;
+mod map;
+mod mapinfo;
+mod merge;
+mod namespace;
+mod palette;
+mod picture;
+mod pk3;
+mod port;
+mod records;
+mod secret;
+mod sheet;
+mod sndinfo;
+mod sound;
+mod stats;
+mod texture;
+mod thingcap;
+mod udmf;
+
+pub use category::classify_lump;
+pub use category::LumpCategory;
+pub use category::DEFAULT_CATEGORY_ORDER;
+#[cfg(feature = "checksum")]
+pub use checksum::MerkleTree;
+pub use definitions::definition_names;
+pub use dehacked::parse_dehacked_things;
+pub use dehacked::DehackedThing;
+pub use diff::ChangedLump;
+pub use diff::LumpId;
+pub use diff::WadDiff;
+pub use dmxgus::merge_dmxgus;
+pub use dmxgus::parse_dmxgus;
+pub use dmxgus::GusMapping;
+pub use endoom::render_endoom;
+pub use endoom::render_endoom_ansi;
+pub use game::Game;
+pub use language::language_keys;
+pub use loadorder::resolve_load_order;
+pub use loadorder::ResolvedLump;
+pub use loadorder::ResolvedMap;
+pub use loadorder::ResolvedView;
+pub use map::LimitViolation;
+pub use map::MapFormat;
+pub use map::MapScheme;
+pub use map::MapSlice;
+pub use map::MapSummary;
+pub use map::SplitWad;
+pub use map::TrimmedPadding;
+pub use mapinfo::build_mapinfo;
+pub use mapinfo::parse_mapinfo;
+pub use mapinfo::progression_dot;
+pub use mapinfo::MapInfoEntry;
+pub use merge::ordered_maps;
+pub use merge::CollisionChoice;
+pub use merge::CollisionFn;
+pub use merge::CollisionResolver;
+pub use merge::LumpCollision;
+pub use merge::MapOrder;
+pub use merge::MergeOptions;
+pub use merge::MergeReport;
+pub use merge::MergeWarning;
+pub use merge::MusicStrategy;
+pub use merge::NamespaceStrategy;
+pub use merge::RenameFn;
+pub use merge::RenameHook;
+pub use merge::SourceMapId;
+pub use merge::DEFAULT_LEVEL_NAME_FORMAT;
+pub use merge::DEFINITION_LUMPS;
+pub use namespace::Namespace;
+pub use namespace::NamespaceBlock;
+pub use palette::palette_to_png;
+pub use palette::palette_to_png_with_swatch_size;
+pub use palette::Palette;
+pub use palette::DEFAULT_SWATCH_SIZE;
+pub use palette::MAX_SWATCH_SIZE;
+pub use picture::decode_picture;
+pub use picture::encode_picture;
+pub use picture::picture_to_png;
+pub use picture::Picture;
+pub use picture::Rect;
+pub use port::IncompatibleMap;
+pub use port::Port;
+pub use records::Linedef;
+pub use records::Sector;
+pub use records::Sidedef;
+pub use records::Thing;
+pub use records::Vertex;
+pub use secret::MapinfoSecrets;
+pub use secret::SecretDetector;
+pub use secret::SlotSecrets;
+pub use sheet::contact_sheet;
+pub use sndinfo::parse_sndinfo;
+pub use sndinfo::SoundAlias;
+pub use sound::decode_dmx;
+pub use sound::dmx_to_wav;
+pub use sound::encode_dmx;
+pub use sound::DmxSound;
+pub use stats::WadStats;
+pub use texture::build_pnames;
+pub use texture::build_textures;
+pub use texture::parse_pnames;
+pub use texture::parse_textures;
+pub use texture::Texture;
+pub use texture::TexturePatch;
+pub use thingcap::cap_things;
+pub use thingcap::classify_thing;
+pub use thingcap::CapStrategy;
+pub use thingcap::ThingCap;
+pub use thingcap::ThingClass;
+pub use udmf::parse_textmap;
+pub use udmf::TextMapSummary;
+
 trait FileLike: std::io::Read + std::io::Seek {}
 impl<T> FileLike for T where T: Read + Seek {}
 
 #[derive(Debug, thiserror::Error)]
 pub enum WadError {
     #[error("failed to read header: {0}")]
-    CouldntReadHeader(std::io::Error),
+    CouldntReadHeader(#[source] std::io::Error),
     #[error("failed to write header: {0}")]
-    CouldntWriteHeader(std::io::Error),
+    CouldntWriteHeader(#[source] std::io::Error),
     #[error("failed to read directory entry: {0}")]
-    CouldntReadEntry(std::io::Error),
+    CouldntReadEntry(#[source] std::io::Error),
     #[error("failed to write directory entry: {0}")]
-    CouldntWriteEntry(std::io::Error),
+    CouldntWriteEntry(#[source] std::io::Error),
     #[error("failed to read lump: {0}")]
-    CouldntReadLump(std::io::Error),
+    CouldntReadLump(#[source] std::io::Error),
     #[error("failed to write lump: {0}")]
-    CouldntWriteLump(std::io::Error),
+    CouldntWriteLump(#[source] std::io::Error),
+    #[error("failed to sync output to disk: {0}")]
+    CouldntSync(#[source] std::io::Error),
     #[error("invalid magic number: {0:?}")]
     InvalidMagicNumber([u8; 4]),
+    #[error("not a WAD file; it looks like {detected}")]
+    NotAWad { detected: &'static str },
     #[error("invalid lump name: {0}")]
-    InvalidLumpName(FromUtf8Error),
+    InvalidLumpName(#[source] FromUtf8Error),
+    #[error("lump {name} has an invalid size of {size} bytes")]
+    InvalidLumpSize { name: String, size: usize },
+    #[error("no lump named {0}")]
+    MissingLump(String),
+    #[error("no map named {0}")]
+    MissingMap(String),
+    #[error("the header has a negative lump count ({num_lumps}) or directory offset ({directory_offset})")]
+    CorruptHeader {
+        num_lumps: i32,
+        directory_offset: i32,
+    },
+    #[error("directory entry {index} ({name}) has a negative offset or size")]
+    CorruptDirectory { index: usize, name: String },
+    #[error("no lump at index {0}")]
+    InvalidLumpIndex(usize),
     #[error("trailing bytes")]
     TrailingBytes,
     #[error("early EOF")]
     UnexpectedEof,
+    #[error("output would be {size} bytes, which exceeds the limit of {limit}")]
+    OutputTooLarge { size: u64, limit: u64 },
+    #[error("the blockmap of {0} is too large to address")]
+    BlockmapTooLarge(String),
+    #[error("texture {texture} refers to patch {index}, which PNAMES doesn't have")]
+    InvalidPatchIndex { texture: String, index: i16 },
+    #[error("`{0}` is not a valid lump name")]
+    BadLumpName(String),
+    #[error("swatch size {0} isn't between 1 and 256")]
+    InvalidSwatchSize(u32),
+    #[error("picture has pixels outside its bounds")]
+    InvalidPicture,
+    #[error("picture column {column} starts at {offset}, outside the picture's data")]
+    InvalidPictureColumn { column: usize, offset: usize },
+    #[error("a {width}x{height} picture is too large")]
+    PictureTooLarge { width: usize, height: usize },
+    #[error("unknown sound format {0}")]
+    InvalidSoundFormat(u16),
+    #[error("malformed TEXTMAP: {0}")]
+    MalformedTextMap(String),
+    #[error("{dir} holds more than one .{kind} file")]
+    AmbiguousBundle { dir: String, kind: &'static str },
+    #[error("archive doesn't contain a WAD")]
+    NoWadInArchive,
+    #[error("{file} is compressed with {method}, which isn't supported")]
+    UnsupportedCompression { file: String, method: String },
+    #[error("archive is nested too deeply")]
+    TooDeeplyNested,
+    #[error("`{0}` is not a valid map slot")]
+    InvalidSlot(String),
+    #[error("{first} and {second} are both pinned to {slot}")]
+    PinnedSlotConflict {
+        slot: String,
+        first: String,
+        second: String,
+    },
+    #[error("input {input} has no map {map} to pin")]
+    PinnedMapMissing { input: usize, map: String },
+    #[error("lump {index} ({name}) at offset {offset} with size {size} runs past the end of the {file_len}-byte file")]
+    LumpOutOfBounds {
+        index: usize,
+        name: String,
+        offset: u64,
+        size: u64,
+        file_len: u64,
+    },
+    #[error("WAD is inconsistent: {0}")]
+    Inconsistent(String),
     #[error("{0}")]
     Other(String),
 }
 
 type WadResult<T> = Result<T, WadError>;
 
+/// Something questionable noticed about a WAD while reading it, which doesn't stop it being read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationWarning {
+    /// The header's lump count and directory offset don't fit the file, but would if their bytes
+    /// were swapped, so the WAD was probably written big-endian.
+    PossiblyByteSwapped {
+        num_lumps: i32,
+        directory_offset: i32,
+    },
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PossiblyByteSwapped {
+                num_lumps,
+                directory_offset,
+            } => write!(
+                f,
+                "the header claims {num_lumps} lumps at offset {directory_offset}, which doesn't \
+                 fit the file; it may have been written big-endian"
+            ),
+        }
+    }
+}
+
+/// The size of a directory entry: offset, size and an eight-character name.
+const DIRECTORY_ENTRY_SIZE: usize = 16;
+
 /// The header of a WAD file. Contains overview information about the file.
 #[derive(Debug)]
 struct WadHeader {
@@ -55,13 +280,41 @@ struct WadHeader {
     directory_offset: i32,
 }
 
+/// Guesses what kind of file starts with `magic`, for the files most often mistaken for WADs:
+/// zips, gzipped files, HTML pages saved from failed downloads, and text.
+fn detect_non_wad(magic: &[u8; 4]) -> Option<&'static str> {
+    const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+    if magic.starts_with(b"PK") {
+        return Some("a zip archive");
+    }
+    if magic.starts_with(&[0x1F, 0x8B]) {
+        return Some("a gzip archive");
+    }
+    let text = magic.strip_prefix(UTF8_BOM).unwrap_or(magic);
+    let text = text.trim_ascii_start();
+    if text.starts_with(b"<") {
+        return Some("HTML");
+    }
+    if magic.starts_with(UTF8_BOM)
+        || text
+            .iter()
+            .all(|&c| c.is_ascii_graphic() || c.is_ascii_whitespace())
+    {
+        return Some("a text file");
+    }
+    None
+}
+
 impl WadHeader {
     fn new(f: &mut dyn FileLike) -> WadResult<Self> {
         let mut identification = [0; 4];
         f.read_exact(&mut identification)
             .map_err(WadError::CouldntReadHeader)?;
         if ![b"IWAD", b"PWAD"].contains(&&identification) {
-            return Err(WadError::InvalidMagicNumber(identification));
+            return Err(match detect_non_wad(&identification) {
+                Some(detected) => WadError::NotAWad { detected },
+                None => WadError::InvalidMagicNumber(identification),
+            });
         }
         let num_lumps = f
             .read_i32::<LittleEndian>()
@@ -75,6 +328,27 @@ impl WadHeader {
         })
     }
 
+    /// Whether the directory the header describes fits in a WAD `file_len` bytes long.
+    fn fits(num_lumps: i32, directory_offset: i32, file_len: u64) -> bool {
+        num_lumps >= 0
+            && directory_offset >= 12
+            && directory_offset as u64 + num_lumps as u64 * DIRECTORY_ENTRY_SIZE as u64 <= file_len
+    }
+
+    /// Warns if the header only makes sense read big-endian, for a WAD `file_len` bytes long.
+    fn byte_swap_warning(&self, file_len: u64) -> Option<ValidationWarning> {
+        (!Self::fits(self.num_lumps, self.directory_offset, file_len)
+            && Self::fits(
+                self.num_lumps.swap_bytes(),
+                self.directory_offset.swap_bytes(),
+                file_len,
+            ))
+        .then_some(ValidationWarning::PossiblyByteSwapped {
+            num_lumps: self.num_lumps,
+            directory_offset: self.directory_offset,
+        })
+    }
+
     fn write(&self, f: &mut dyn Write) -> WadResult<()> {
         f.write_all(b"PWAD").map_err(WadError::CouldntWriteHeader)?;
         f.write_i32::<LittleEndian>(self.num_lumps)
@@ -89,7 +363,94 @@ impl WadHeader {
 pub struct Directory(Vec<DirectoryEntry>);
 
 impl Directory {
-    pub fn iter(&self) -> DirectoryIter {
+    /// Reads just the header and directory of a WAD, leaving the lump data alone.
+    pub fn from_reader<R: Read + Seek>(mut r: R) -> WadResult<Self> {
+        Self::read(&mut r, 0)
+    }
+
+    fn read(f: &mut dyn FileLike, base_offset: u64) -> WadResult<Self> {
+        f.seek(SeekFrom::Start(base_offset))
+            .map_err(WadError::CouldntReadHeader)?;
+        let header = WadHeader::new(f)?;
+        let file_len = f
+            .seek(SeekFrom::End(0))
+            .map_err(WadError::CouldntReadHeader)?;
+        if let Some(warning) = header.byte_swap_warning(file_len.saturating_sub(base_offset)) {
+            log::warn!("{warning}");
+        }
+        if header.num_lumps < 0 || header.directory_offset < 0 {
+            return Err(WadError::CorruptHeader {
+                num_lumps: header.num_lumps,
+                directory_offset: header.directory_offset,
+            });
+        }
+        // a corrupt header shouldn't be able to make us allocate gigabytes up front
+        let mut directory = Vec::with_capacity(header.num_lumps.min(4096) as usize);
+        f.seek(SeekFrom::Start(
+            base_offset + header.directory_offset as u64,
+        ))
+        .map_err(WadError::CouldntReadHeader)?;
+        // one read for the whole directory is much faster than one per field; it's read into a
+        // buffer that grows as needed, for the same reason as above
+        let len = header.num_lumps as u64 * DIRECTORY_ENTRY_SIZE as u64;
+        let mut raw = Vec::new();
+        f.take(len)
+            .read_to_end(&mut raw)
+            .map_err(WadError::CouldntReadEntry)?;
+        for (index, raw) in raw.chunks_exact(DIRECTORY_ENTRY_SIZE).enumerate() {
+            let entry = DirectoryEntry::parse(raw)?;
+            if entry.offset < 0 || entry.size < 0 {
+                return Err(WadError::CorruptDirectory {
+                    index,
+                    name: entry.name,
+                });
+            }
+            directory.push(entry);
+        }
+        if (raw.len() as u64) < len {
+            return Err(WadError::CouldntReadEntry(
+                std::io::ErrorKind::UnexpectedEof.into(),
+            ));
+        }
+        Ok(Directory(directory))
+    }
+
+    /// Checks that every entry lies within a WAD `file_len` bytes long.
+    fn validate_sizes_against(&self, file_len: u64) -> WadResult<()> {
+        for (index, entry) in self.0.iter().enumerate() {
+            let (offset, size) = (entry.offset as u64, entry.size as u64);
+            if offset + size > file_len {
+                return Err(WadError::LumpOutOfBounds {
+                    index,
+                    name: entry.name.clone(),
+                    offset,
+                    size,
+                    file_len,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the first entry with the given name, and its position.
+    pub fn find(&self, name: &str) -> Option<(usize, &DirectoryEntry)> {
+        self.0
+            .iter()
+            .enumerate()
+            .find(|(_, entry)| entry.name == name)
+    }
+
+    /// The positions of every entry with the given name.
+    pub fn position_all(&self, name: &str) -> Vec<usize> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.name == name)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn iter(&self) -> DirectoryIter<'_> {
         DirectoryIter {
             inner: self.0.iter(),
         }
@@ -116,6 +477,7 @@ impl<'a> Iterator for DirectoryIter<'a> {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct DirectoryEntry {
     pub offset: i32,
     pub size: i32,
@@ -123,18 +485,12 @@ pub struct DirectoryEntry {
 }
 
 impl DirectoryEntry {
-    fn new(f: &mut dyn FileLike) -> WadResult<Self> {
-        let offset = f
-            .read_i32::<LittleEndian>()
-            .map_err(WadError::CouldntReadEntry)?;
-        let size = f
-            .read_i32::<LittleEndian>()
-            .map_err(WadError::CouldntReadEntry)?;
-        let mut name = [0; 8];
-        f.read_exact(&mut name)
-            .map_err(WadError::CouldntReadEntry)?;
-        let name = String::from_utf8(name.into_iter().filter(|c| *c != b'\0').collect())
-            .map_err(WadError::InvalidLumpName)?;
+    /// Reads an entry from the bytes it's stored in.
+    fn parse(raw: &[u8]) -> WadResult<Self> {
+        let offset = LittleEndian::read_i32(&raw[0..]);
+        let size = LittleEndian::read_i32(&raw[4..]);
+        let name = raw[8..16].iter().copied().filter(|c| *c != b'\0').collect();
+        let name = String::from_utf8(name).map_err(WadError::InvalidLumpName)?;
         Ok(DirectoryEntry { offset, size, name })
     }
 
@@ -153,18 +509,34 @@ impl DirectoryEntry {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Lump {
     pub name: String,
     pub data: Vec<u8>,
 }
 
+/// Checks that a name fits in a directory entry: one to eight ASCII characters, none of them NUL.
+pub(crate) fn validate_name(name: &str) -> WadResult<()> {
+    if (1..=8).contains(&name.len()) && name.bytes().all(|c| c.is_ascii() && c != b'\0') {
+        Ok(())
+    } else {
+        Err(WadError::BadLumpName(name.to_string()))
+    }
+}
+
 impl Lump {
-    fn new(f: &mut dyn FileLike, entry: &DirectoryEntry) -> Result<Lump, WadError> {
-        let mut bytes = vec![0; entry.size as usize];
-        f.seek(SeekFrom::Start(entry.offset as u64))
+    fn new(
+        f: &mut dyn FileLike,
+        base_offset: u64,
+        entry: &DirectoryEntry,
+    ) -> Result<Lump, WadError> {
+        f.seek(SeekFrom::Start(base_offset + entry.offset as u64))
             .map_err(WadError::CouldntReadLump)?;
-        f.read_exact(&mut bytes)
+        // Read rather than allocating the whole size first, in case it's bogus. A lump running
+        // past the end of the file is cut short; see `Wad::validate_sizes_against`.
+        let mut bytes = Vec::new();
+        f.take(entry.size as u64)
+            .read_to_end(&mut bytes)
             .map_err(WadError::CouldntReadLump)?;
 
         Ok(Lump {
@@ -185,114 +557,1237 @@ impl Lump {
 pub struct Wad {
     pub directory: Directory,
     pub lumps: Vec<Lump>,
-    pub lump_index: HashMap<String, usize>,
+    /// The indices of the lumps with each name, in directory order.
+    name_index: HashMap<String, Vec<usize>>,
     pub was_zip: bool,
+    /// The indices of the lumps changed or added since the WAD was loaded.
+    touched: BTreeSet<usize>,
+}
+
+/// Whether a source starts with a zip archive's signature. The source is left where it was.
+/// Only the signature of a file entry, `PK\x03\x04`, counts: empty archives (`PK\x05\x06`) and
+/// spanned ones (`PK\x07\x08`) hold no WAD to read, so they're left to fail as WADs.
+fn is_zip(f: &mut dyn FileLike) -> WadResult<bool> {
+    let start = f.stream_position().map_err(WadError::CouldntReadHeader)?;
+    let mut magic = Vec::with_capacity(4);
+    f.take(4)
+        .read_to_end(&mut magic)
+        .map_err(WadError::CouldntReadHeader)?;
+    f.seek(SeekFrom::Start(start))
+        .map_err(WadError::CouldntReadHeader)?;
+    Ok(magic == b"PK\x03\x04")
 }
 
+/// How many zip archives deep [`Wad::from_reader`] looks for a WAD.
+pub const MAX_ZIP_DEPTH: usize = 4;
+
+/// Reads out the first file in a zip archive with a name ending in `.wad`, in any case.
+fn first_wad_in_zip<R: Read + Seek>(r: R) -> WadResult<Vec<u8>> {
+    let mut archive = ZipArchive::new(r).map_err(|e| WadError::CouldntReadHeader(e.into()))?;
+    // by index, since the names come out in no particular order
+    for i in 0..archive.len() {
+        let mut member = archive
+            .by_index(i)
+            .map_err(|e| WadError::CouldntReadHeader(e.into()))?;
+        if member.name().to_ascii_lowercase().ends_with(".wad") {
+            let mut bytes = Vec::new();
+            member
+                .read_to_end(&mut bytes)
+                .map_err(WadError::CouldntReadHeader)?;
+            return Ok(bytes);
+        }
+    }
+    Err(WadError::NoWadInArchive)
+}
+
+/// Settings for [`Wad::write_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Store identical lumps once, with every directory entry for them pointing at the same
+    /// data, as WADPTR does. Vanilla Doom handles this fine.
+    pub share_identical: bool,
+    /// Like `share_identical`, but only for lumps in the sprite namespace, where the same frame
+    /// often turns up under different names in different mods.
+    pub share_identical_sprites: bool,
+    /// Start each lump's data at a multiple of this many bytes, padding with zeroes in between.
+    /// 0 and 1 leave no gaps.
+    pub alignment: u64,
+}
+
+/// Lists the maps of every WAD in a zip archive, as (file name, map names) pairs in archive order.
+/// Only the WADs' directories are read.
+pub fn catalog_archive<P: AsRef<Path>>(path: P) -> WadResult<Vec<(String, Vec<String>)>> {
+    let f = File::open(path).map_err(WadError::CouldntReadHeader)?;
+    let mut archive = ZipArchive::new(f).map_err(|e| WadError::CouldntReadHeader(e.into()))?;
+    let mut catalog = Vec::new();
+    for i in 0..archive.len() {
+        let mut member = archive
+            .by_index(i)
+            .map_err(|e| WadError::CouldntReadHeader(e.into()))?;
+        if !member.name().to_ascii_lowercase().ends_with(".wad") {
+            continue;
+        }
+        let name = member.name().to_string();
+        // zip members can't seek, so the WAD has to be buffered
+        let mut bytes = Vec::new();
+        member
+            .read_to_end(&mut bytes)
+            .map_err(WadError::CouldntReadHeader)?;
+        let directory = Directory::from_reader(Cursor::new(bytes))?;
+        let names = directory
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<_>>();
+        let maps = map::group_maps(&names)
+            .into_iter()
+            .map(|group| names[group.range.start].to_string())
+            .collect();
+        catalog.push((name, maps));
+    }
+    Ok(catalog)
+}
+
+/// WADs are equal when they hold the same lumps in the same order. Where the lumps would sit in a
+/// file, and whether the WAD came from a zip, don't matter.
+impl PartialEq for Wad {
+    fn eq(&self, other: &Self) -> bool {
+        self.lumps == other.lumps
+    }
+}
+
+impl Eq for Wad {}
+
 impl Wad {
     pub fn new_empty(was_zip: bool) -> Self {
         Self {
             directory: Directory(Vec::new()),
             lumps: Vec::new(),
-            lump_index: HashMap::new(),
+            name_index: HashMap::new(),
             was_zip,
+            touched: BTreeSet::new(),
         }
     }
 
+    /// Creates an empty WAD with room for `n` lumps.
+    pub fn with_capacity(n: usize) -> Self {
+        Self {
+            directory: Directory(Vec::with_capacity(n)),
+            lumps: Vec::with_capacity(n),
+            name_index: HashMap::with_capacity(n),
+            was_zip: false,
+            touched: BTreeSet::new(),
+        }
+    }
+
+    /// Creates a WAD holding `lumps`, in order, the reverse of [`Wad::into_lumps`]. Where they'll
+    /// sit in the file is worked out when writing. Fails if any name can't be stored in a
+    /// directory entry.
+    pub fn from_lumps(lumps: Vec<Lump>) -> WadResult<Self> {
+        let mut wad = Self::with_capacity(lumps.len());
+        for lump in lumps {
+            validate_name(&lump.name)?;
+            wad.add_lump(lump);
+        }
+        Ok(wad)
+    }
+
+    /// Frees the spare capacity left behind after removing lumps.
+    pub fn shrink_to_fit(&mut self) {
+        self.directory.0.shrink_to_fit();
+        self.lumps.shrink_to_fit();
+        self.name_index.shrink_to_fit();
+    }
+
+    /// Takes the WAD apart into its lumps, in directory order.
+    pub fn into_lumps(self) -> Vec<Lump> {
+        self.lumps
+    }
+
     pub fn add_lump(&mut self, lump: Lump) {
-        self.lump_index.insert(lump.name.clone(), self.lumps.len());
-        self.lumps.push(lump);
+        self.touched.insert(self.lumps.len());
+        self.name_index
+            .entry(lump.name.clone())
+            .or_default()
+            .push(self.lumps.len());
         self.directory.0.push(DirectoryEntry {
-            // dummy entry. this is recomputed when writing anyway.
+            // the offset is worked out when writing
             offset: 0,
-            size: 0,
-            name: String::new(),
+            size: lump.data.len() as i32,
+            name: lump.name.clone(),
         });
+        self.lumps.push(lump);
+    }
+
+    /// Adds a lump with the given name and contents to the end of the WAD. Fails if the name
+    /// can't be stored in a directory entry.
+    pub fn append_lump_bytes(&mut self, name: impl Into<String>, data: Vec<u8>) -> WadResult<()> {
+        let name = name.into();
+        validate_name(&name)?;
+        self.add_lump(Lump { name, data });
+        Ok(())
+    }
+
+    /// Adds a lump holding the contents of a file to the end of the WAD. Fails if the name
+    /// can't be stored in a directory entry.
+    pub fn append_raw_file(&mut self, name: &str, path: &Path) -> WadResult<()> {
+        validate_name(name)?;
+        let data = std::fs::read(path).map_err(WadError::CouldntReadLump)?;
+        self.append_lump_bytes(name, data)
+    }
+
+    /// Removes every lump `keep` returns false for, given each lump and its index.
+    pub fn retain_lumps<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(usize, &Lump) -> bool,
+    {
+        let keep = self
+            .lumps
+            .iter()
+            .enumerate()
+            .map(|(i, lump)| keep(i, lump))
+            .collect::<Vec<_>>();
+        let mut kept = keep.iter();
+        self.lumps.retain(|_| *kept.next().unwrap());
+        let mut kept = keep.iter();
+        self.directory.0.retain(|_| *kept.next().unwrap());
+        // the lumps after a removed one move up
+        let mut new_index = 0..;
+        let new_indices = keep
+            .iter()
+            .map(|&kept| if kept { new_index.next() } else { None })
+            .collect::<Vec<_>>();
+        self.touched = self
+            .touched
+            .iter()
+            .filter_map(|&i| new_indices[i])
+            .collect();
+        self.reindex();
+    }
+
+    /// Removes every lump with one of the given names, ignoring case, and gives how many were
+    /// removed.
+    pub fn strip_lumps_named(&mut self, names: &[&str]) -> usize {
+        let before = self.lumps.len();
+        self.retain_lumps(|_, lump| !names.iter().any(|n| n.eq_ignore_ascii_case(&lump.name)));
+        before - self.lumps.len()
+    }
+
+    /// Rebuilds the name lookup after the lumps have been changed directly.
+    pub fn reindex(&mut self) {
+        self.name_index.clear();
+        for (i, lump) in self.lumps.iter().enumerate() {
+            self.name_index
+                .entry(lump.name.clone())
+                .or_default()
+                .push(i);
+        }
+    }
+
+    /// The indices of the lumps with each name, in directory order. Unlike [`Wad::lump`], this
+    /// doesn't lose any lumps whose names are shared.
+    pub fn name_to_indices(&self) -> &HashMap<String, Vec<usize>> {
+        &self.name_index
     }
 
-    /// Opens a WAD file.
+    /// Checks that the directory and the name index agree with the lumps, which every mutating
+    /// method should keep true.
+    pub fn assert_valid(&self) -> WadResult<()> {
+        let inconsistent = |what: String| Err(WadError::Inconsistent(what));
+        if self.lumps.len() != self.directory.0.len() {
+            return inconsistent(format!(
+                "{} lumps but {} directory entries",
+                self.lumps.len(),
+                self.directory.0.len()
+            ));
+        }
+        for (i, (lump, entry)) in self.lumps.iter().zip(&self.directory.0).enumerate() {
+            if lump.name != entry.name {
+                return inconsistent(format!(
+                    "lump {i} is {} but its directory entry says {}",
+                    lump.name, entry.name
+                ));
+            }
+            if !self
+                .name_index
+                .get(&lump.name)
+                .is_some_and(|indices| indices.contains(&i))
+            {
+                return inconsistent(format!("lump {i} ({}) isn't indexed", lump.name));
+            }
+        }
+        for (name, indices) in &self.name_index {
+            for &i in indices {
+                if self.lumps.get(i).map(|lump| &lump.name) != Some(name) {
+                    return inconsistent(format!("the index puts {name} at {i}, where it isn't"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the contents of a lump, leaving its name and position alone.
+    pub fn replace_lump_data(&mut self, index: usize, data: Vec<u8>) -> WadResult<()> {
+        let lump = self
+            .lumps
+            .get_mut(index)
+            .ok_or(WadError::InvalidLumpIndex(index))?;
+        self.directory.0[index].size = data.len() as i32;
+        lump.data = data;
+        self.touched.insert(index);
+        Ok(())
+    }
+
+    /// Renames a lump, leaving its data and position alone. Fails if the name can't be stored in
+    /// a directory entry.
+    pub fn rename_lump(&mut self, index: usize, name: impl Into<String>) -> WadResult<()> {
+        let name = name.into();
+        validate_name(&name)?;
+        let lump = self
+            .lumps
+            .get_mut(index)
+            .ok_or(WadError::InvalidLumpIndex(index))?;
+        self.directory.0[index].name.clone_from(&name);
+        lump.name = name;
+        self.touched.insert(index);
+        self.reindex();
+        Ok(())
+    }
+
+    /// Like [`Wad::rename_lump`], but also returns the index of another lump which already has
+    /// the new name, if there is one. That's sometimes intended, as with lumps in separate
+    /// namespaces, but usually a mistake, since [`Wad::lump`] only finds the last of them.
+    pub fn set_lump_name(&mut self, index: usize, name: &str) -> WadResult<Option<usize>> {
+        self.rename_lump(index, name)?;
+        Ok(self.name_index[name].iter().copied().find(|&i| i != index))
+    }
+
+    /// The indices of the lumps which have been changed or added since the WAD was loaded.
+    /// Lumps handed out by [`Wad::iter_mut`] all count as changed.
+    pub fn touched_lumps(&self) -> &BTreeSet<usize> {
+        &self.touched
+    }
+
+    /// Finds a lump by name. If several share the name, the last one is returned.
+    pub fn lump(&self, name: &str) -> Option<&Lump> {
+        self.name_index
+            .get(name)
+            .and_then(|indices| indices.last())
+            .map(|&i| &self.lumps[i])
+    }
+
+    /// How many lumps have the given name.
+    pub fn count_lumps_named(&self, name: &str) -> usize {
+        self.name_index.get(name).map_or(0, Vec::len)
+    }
+
+    /// Iterates over the lumps in directory order.
+    pub fn iter(&self) -> impl Iterator<Item = &Lump> {
+        self.lumps.iter()
+    }
+
+    /// Iterates over the lumps for editing them in place. Changing their data is fine, but
+    /// after renaming any, call [`Wad::reindex`] so lookups by name find them again.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Lump> {
+        self.touched.extend(0..self.lumps.len());
+        self.lumps.iter_mut()
+    }
+
+    /// Opens a WAD file. A WAD whose directory points past the end of the file is rejected.
     pub fn new<P>(path: P) -> WadResult<Self>
     where
         P: AsRef<Path>,
     {
-        let path = path.as_ref();
-        let (mut f, was_zip): (Box<dyn FileLike>, bool) = 'check_and_unzip: {
-            let f = File::open(path).map_err(WadError::CouldntReadHeader)?;
-            'check_zip: {
-                if let Ok(mut archive) = ZipArchive::new(f) {
-                    let wadname = 'find_wad: {
-                        for name in archive.file_names() {
-                            if name.ends_with(".wad") {
-                                break 'find_wad name.to_string();
-                            }
-                        }
-                        break 'check_zip;
-                    };
-                    let mut bytes = Vec::new();
-                    archive
-                        .by_name(&wadname)
-                        .unwrap()
-                        .read_to_end(&mut bytes)
-                        .map_err(WadError::CouldntReadHeader)?;
-                    break 'check_and_unzip (Box::new(Cursor::new(bytes)), true);
-                }
+        let f = File::open(path).map_err(WadError::CouldntReadHeader)?;
+        Self::from_reader(f)
+    }
+
+    /// Opens a WAD file without checking its directory against the file's length, for
+    /// salvaging what's left of a truncated WAD. Lumps running past the end are cut short.
+    pub fn open_unchecked<P>(path: P) -> WadResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut f = File::open(path).map_err(WadError::CouldntReadHeader)?;
+        Self::read(&mut f, 0, false, false)
+    }
+
+    /// Reads a WAD from any seekable source. If the source is a zip archive, the first WAD inside
+    /// it is read instead. That WAD may itself turn out to be a zip, and so on, up to
+    /// [`MAX_ZIP_DEPTH`] archives deep.
+    pub fn from_reader<R: Read + Seek>(mut r: R) -> WadResult<Self> {
+        if !is_zip(&mut r)? {
+            return Self::read(&mut r, 0, false, true);
+        }
+        let mut bytes = Cursor::new(first_wad_in_zip(r)?);
+        let mut depth = 1;
+        while is_zip(&mut bytes)? {
+            if depth == MAX_ZIP_DEPTH {
+                return Err(WadError::TooDeeplyNested);
             }
-            (Box::new(File::open(path).unwrap()), false)
-        };
-        let header = WadHeader::new(f.as_mut())?;
-        let mut directory = Vec::with_capacity(header.num_lumps as usize);
-        f.seek(SeekFrom::Start(header.directory_offset as u64))
-            .map_err(WadError::CouldntReadHeader)?;
-        for _ in 0..header.num_lumps {
-            directory.push(DirectoryEntry::new(f.as_mut())?);
+            bytes = Cursor::new(first_wad_in_zip(bytes)?);
+            depth += 1;
         }
+        Self::read(&mut bytes, 0, true, true)
+    }
+
+    /// Reads a WAD held in memory, like [`Wad::from_reader`].
+    pub fn from_bytes(bytes: &[u8]) -> WadResult<Self> {
+        Self::from_reader(Cursor::new(bytes))
+    }
+
+    /// Reads a WAD which starts `base_offset` bytes into the source, such as one glued onto the
+    /// end of an executable. All offsets in the WAD are relative to its own start.
+    pub fn from_reader_at<R: Read + Seek>(mut r: R, base_offset: u64) -> WadResult<Self> {
+        Self::read(&mut r, base_offset, false, true)
+    }
 
-        let mut lumps = Vec::with_capacity(header.num_lumps as usize);
-        let mut lump_index = HashMap::new();
-        for entry in &directory {
-            lump_index.insert(entry.name.clone(), lumps.len());
-            lumps.push(Lump::new(&mut f, entry)?);
+    fn read(
+        f: &mut dyn FileLike,
+        base_offset: u64,
+        was_zip: bool,
+        checked: bool,
+    ) -> WadResult<Self> {
+        let directory = Directory::read(f, base_offset)?;
+        if checked {
+            let file_len = f
+                .seek(SeekFrom::End(0))
+                .map_err(WadError::CouldntReadLump)?;
+            directory.validate_sizes_against(file_len.saturating_sub(base_offset))?;
+        }
+        let mut lumps = Vec::with_capacity(directory.0.len());
+        let mut name_index = HashMap::<String, Vec<usize>>::new();
+        for entry in directory.iter() {
+            name_index
+                .entry(entry.name.clone())
+                .or_default()
+                .push(lumps.len());
+            lumps.push(Lump::new(f, base_offset, entry)?);
         }
 
         Ok(Wad {
-            directory: Directory(directory),
+            directory,
             lumps,
-            lump_index,
+            name_index,
             was_zip,
+            touched: BTreeSet::new(),
         })
     }
 
+    /// Reads the header at the start of `r` and checks it against the length of `r`, without
+    /// reading the directory. Opening a WAD logs these warnings as well.
+    pub fn header_warnings<R: Read + Seek>(mut r: R) -> WadResult<Vec<ValidationWarning>> {
+        let header = WadHeader::new(&mut r)?;
+        let file_len = r
+            .seek(SeekFrom::End(0))
+            .map_err(WadError::CouldntReadHeader)?;
+        Ok(header.byte_swap_warning(file_len).into_iter().collect())
+    }
+
+    /// Checks that every lump in the directory lies within a WAD `file_len` bytes long. This is
+    /// done when a WAD is opened; the directory's offsets are those the WAD was read with.
+    pub fn validate_sizes_against(&self, file_len: u64) -> WadResult<()> {
+        self.directory.validate_sizes_against(file_len)
+    }
+
+    /// The size in bytes the WAD will have when written.
+    pub fn total_size(&self) -> u64 {
+        let data_size: u64 = self.lumps.iter().map(|lump| lump.data.len() as u64).sum();
+        12 + self.lumps.len() as u64 * 16 + data_size
+    }
+
     pub fn write<P: AsRef<Path>>(&self, path: P) -> WadResult<()> {
+        self.write_with_options(path, &WriteOptions::default())
+    }
+
+    /// Writes the WAD out, inside a zip if it was read from one. The file is synced to disk before
+    /// this returns.
+    pub fn write_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        opts: &WriteOptions,
+    ) -> WadResult<()> {
         let path = path.as_ref();
-        let f = File::create(path).map_err(WadError::CouldntWriteHeader)?;
-        let mut writer: Box<dyn Write> = if self.was_zip {
+        let mut f = File::create(path).map_err(WadError::CouldntWriteHeader)?;
+        if self.was_zip {
             let mut zw = ZipWriter::new(f);
             zw.start_file("output.wad", FileOptions::default()).unwrap();
-            Box::new(zw)
+            self.write_contents(&mut zw, opts)?;
+            f = zw
+                .finish()
+                .map_err(|e| WadError::CouldntWriteLump(e.into()))?;
         } else {
-            Box::new(f)
-        };
+            self.write_contents(&mut f, opts)?;
+        }
+        f.flush().map_err(WadError::CouldntSync)?;
+        f.sync_all().map_err(WadError::CouldntSync)
+    }
+
+    /// Serializes the WAD in memory. The result is always a plain WAD, even if this one was read
+    /// from a zip.
+    pub fn write_to_vec(&self) -> WadResult<Vec<u8>> {
+        let mut cursor = Cursor::new(Vec::with_capacity(self.total_size() as usize));
+        self.write_contents(&mut cursor, &WriteOptions::default())?;
+        Ok(cursor.into_inner())
+    }
+
+    /// Where each lump's data goes when the WAD is written with `opts`, as (offset, size) pairs
+    /// in directory order. Lumps sharing their data have the same offset.
+    pub fn layout(&self, opts: &WriteOptions) -> Vec<(u64, u64)> {
+        let mut offset = (12 + self.directory.0.len() * DIRECTORY_ENTRY_SIZE) as u64;
+        // the offset of each distinct piece of data laid out so far, when sharing
+        let mut written = HashMap::new();
+        let mut is_sprite = vec![false; self.lumps.len()];
+        if opts.share_identical_sprites {
+            let names = self
+                .lumps
+                .iter()
+                .map(|l| l.name.as_str())
+                .collect::<Vec<_>>();
+            for (_, range) in namespace::namespace_blocks(&names, &[Namespace::Sprites]) {
+                is_sprite[range].fill(true);
+            }
+        }
+        let mut regions = Vec::with_capacity(self.lumps.len());
+        for (lump, is_sprite) in self.lumps.iter().zip(is_sprite) {
+            let size = lump.data.len() as u64;
+            let share = opts.share_identical || is_sprite;
+            let shared = share.then(|| written.get(lump.data.as_slice())).flatten();
+            let lump_offset = match shared {
+                Some(&shared) => shared,
+                None => {
+                    // there's nothing to line up in an empty lump
+                    if size > 0 && opts.alignment > 1 {
+                        offset = offset.next_multiple_of(opts.alignment);
+                    }
+                    if share {
+                        written.insert(lump.data.as_slice(), offset);
+                    }
+                    let lump_offset = offset;
+                    offset += size;
+                    lump_offset
+                }
+            };
+            regions.push((lump_offset, size));
+        }
+        regions
+    }
+
+    /// Where a lump's data goes when the WAD is written with `opts`, as an offset and size, for
+    /// patching the lump in place afterwards. See [`Wad::layout`].
+    pub fn data_region(&self, index: usize, opts: &WriteOptions) -> Option<(u64, u64)> {
+        self.layout(opts).get(index).copied()
+    }
+
+    fn write_contents(&self, writer: &mut dyn Write, opts: &WriteOptions) -> WadResult<()> {
         let header = WadHeader {
             num_lumps: self.directory.0.len() as i32,
             directory_offset: 12,
         };
-        header.write(writer.as_mut())?;
+        let layout = self.layout(opts);
+        // the directory addresses lumps with i32s, so nothing can end past 2 GB
+        let size = layout.iter().map(|&(offset, size)| offset + size).max();
+        let limit = i32::MAX as u64;
+        if let Some(size) = size.filter(|&size| size > limit) {
+            return Err(WadError::OutputTooLarge { size, limit });
+        }
+        header.write(writer)?;
 
-        let mut offset = 12 + self.directory.0.len() * 16;
-        for lump in &self.lumps {
+        for (lump, &(offset, size)) in self.lumps.iter().zip(&layout) {
             let entry = DirectoryEntry {
-                offset: offset.try_into().unwrap(),
-                size: lump.data.len() as i32,
+                offset: offset as i32,
+                size: size as i32,
                 name: lump.name.clone(),
             };
-            entry.write(writer.as_mut())?;
-            offset += lump.data.len();
+            entry.write(writer)?;
         }
 
-        for lump in &self.lumps {
-            lump.write(writer.as_mut())?;
+        let mut pos = (12 + self.directory.0.len() * DIRECTORY_ENTRY_SIZE) as u64;
+        for (lump, &(offset, size)) in self.lumps.iter().zip(&layout) {
+            // lumps before this point share data written earlier
+            if size == 0 || offset < pos {
+                continue;
+            }
+            let padding = vec![0; (offset - pos) as usize];
+            writer
+                .write_all(&padding)
+                .map_err(WadError::CouldntWriteLump)?;
+            lump.write(writer)?;
+            pos = offset + size;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A WAD header with the given lump count and directory offset, and nothing after it.
+    fn header(num_lumps: i32, directory_offset: i32) -> Vec<u8> {
+        let mut data = b"PWAD".to_vec();
+        data.extend_from_slice(&num_lumps.to_le_bytes());
+        data.extend_from_slice(&directory_offset.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn negative_header_fields_are_an_error() {
+        for (num_lumps, directory_offset) in [(0, -1), (-1, 12), (1, i32::MIN)] {
+            // also inside a larger file, where a negative offset used to overflow
+            let padded = [vec![0; 16], header(num_lumps, directory_offset)].concat();
+            for (data, base_offset) in [(header(num_lumps, directory_offset), 0), (padded, 16)] {
+                assert!(matches!(
+                    Wad::from_reader_at(Cursor::new(data), base_offset),
+                    Err(WadError::CorruptHeader { .. })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn set_lump_name_reports_collisions() {
+        let mut wad = Wad::from_lumps(
+            ["A", "B", "C"]
+                .map(|name| Lump {
+                    name: name.to_string(),
+                    data: Vec::new(),
+                })
+                .to_vec(),
+        )
+        .unwrap();
+        assert_eq!(wad.set_lump_name(2, "A").unwrap(), Some(0));
+        assert_eq!(wad.set_lump_name(1, "D").unwrap(), None);
+        assert!(matches!(
+            wad.set_lump_name(1, "TOOLONGNAME"),
+            Err(WadError::BadLumpName(_))
+        ));
+        assert_eq!(wad.name_to_indices()["A"], [0, 2]);
+        wad.assert_valid().unwrap();
+    }
+
+    #[test]
+    fn wad_equals_itself_reloaded() {
+        let mut wad = Wad::new_empty(false);
+        wad.append_lump_bytes("FIRST", b"one".to_vec()).unwrap();
+        wad.append_lump_bytes("EMPTY", Vec::new()).unwrap();
+        wad.append_lump_bytes("FIRST", b"two".to_vec()).unwrap();
+        let reloaded = Wad::from_bytes(&wad.write_to_vec().unwrap()).unwrap();
+        assert_eq!(reloaded, wad);
+        wad.replace_lump_data(1, b"changed".to_vec()).unwrap();
+        assert_ne!(reloaded, wad);
+    }
+
+    /// A WAD with a few lumps, written out and loaded back.
+    fn loaded() -> Wad {
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in [("FIRST", &b"one"[..]), ("SECOND", b"two"), ("THIRD", b"")] {
+            wad.append_lump_bytes(name, data.to_vec()).unwrap();
+        }
+        Wad::from_bytes(&wad.write_to_vec().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn touched_lumps_are_the_ones_changed_since_loading() {
+        let mut wad = loaded();
+        assert!(wad.touched_lumps().is_empty());
+        wad.replace_lump_data(2, b"three".to_vec()).unwrap();
+        wad.rename_lump(0, "ONE").unwrap();
+        wad.replace_lump_data(0, b"1".to_vec()).unwrap();
+        assert_eq!(
+            wad.touched_lumps().iter().copied().collect::<Vec<_>>(),
+            [0, 2]
+        );
+        wad.append_lump_bytes("FOURTH", Vec::new()).unwrap();
+        assert_eq!(
+            wad.touched_lumps().iter().copied().collect::<Vec<_>>(),
+            [0, 2, 3]
+        );
+    }
+
+    #[test]
+    fn assert_valid_catches_desynced_wads() {
+        let inconsistent = |wad: &Wad| matches!(wad.assert_valid(), Err(WadError::Inconsistent(_)));
+        let wad = loaded();
+        wad.assert_valid().unwrap();
+
+        let mut extra_lump = loaded();
+        extra_lump.lumps.push(Lump {
+            name: String::from("EXTRA"),
+            data: Vec::new(),
+        });
+        assert!(inconsistent(&extra_lump));
+
+        let mut renamed_entry = loaded();
+        renamed_entry.directory.0[1].name = String::from("OTHER");
+        assert!(inconsistent(&renamed_entry));
+
+        // the directory agrees, but the name index is stale until reindexed
+        let mut renamed_lump = loaded();
+        renamed_lump.lumps[1].name = String::from("OTHER");
+        renamed_lump.directory.0[1].name = String::from("OTHER");
+        assert!(inconsistent(&renamed_lump));
+        renamed_lump.reindex();
+        renamed_lump.assert_valid().unwrap();
+    }
+
+    #[test]
+    fn directory_from_reader_matches_a_full_load() {
+        let bytes = loaded().write_to_vec().unwrap();
+        let directory = Directory::from_reader(Cursor::new(&bytes)).unwrap();
+        let wad = Wad::from_bytes(&bytes).unwrap();
+        let entries = |directory: &Directory| {
+            directory
+                .iter()
+                .map(|entry| (entry.name.clone(), entry.offset, entry.size))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(entries(&directory), entries(&wad.directory));
+        assert_eq!(
+            directory.iter().map(|entry| entry.size).collect::<Vec<_>>(),
+            [3, 3, 0]
+        );
+
+        // cut off partway through the last entry
+        let truncated = &bytes[..12 + 2 * DIRECTORY_ENTRY_SIZE + 4];
+        assert!(matches!(
+            Directory::from_reader(Cursor::new(truncated)),
+            Err(WadError::CouldntReadEntry(_))
+        ));
+    }
+
+    /// A zip archive holding `files`, each given as a name and its contents.
+    fn zipped(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            zw.start_file(*name, FileOptions::default()).unwrap();
+            zw.write_all(data).unwrap();
+        }
+        zw.finish().unwrap().into_inner()
+    }
+
+    /// A WAD holding empty maps named `maps`, with a lump that isn't a map first.
+    fn wad_with_maps(maps: &[&str]) -> Vec<u8> {
+        let mut wad = Wad::new_empty(false);
+        wad.append_lump_bytes("PLAYPAL", vec![0; 768]).unwrap();
+        for map in maps {
+            wad.append_lump_bytes(*map, Vec::new()).unwrap();
+            wad.append_lump_bytes("THINGS", Vec::new()).unwrap();
+        }
+        wad.write_to_vec().unwrap()
+    }
+
+    #[test]
+    fn catalog_lists_the_maps_of_each_wad() {
+        let archive = zipped(&[
+            ("readme.txt", b"not a wad"),
+            ("ONE.WAD", &wad_with_maps(&["MAP01", "MAP02"])),
+            ("two.wad", &wad_with_maps(&["E1M1"])),
+        ]);
+        let path = std::env::temp_dir().join(format!("wad-{}-catalog.zip", std::process::id()));
+        std::fs::write(&path, &archive).unwrap();
+        let catalog = catalog_archive(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            catalog.unwrap(),
+            [
+                (
+                    String::from("ONE.WAD"),
+                    vec![String::from("MAP01"), String::from("MAP02")]
+                ),
+                (String::from("two.wad"), vec![String::from("E1M1")]),
+            ]
+        );
+        let wad = Wad::from_bytes(&archive).unwrap();
+        assert_eq!(wad.maps().len(), 2);
+        assert!(wad.was_zip);
+    }
+
+    #[test]
+    fn outputs_past_2_gb_are_an_error() {
+        let mut wad = Wad::new_empty(false);
+        wad.append_lump_bytes("FIRST", b"one".to_vec()).unwrap();
+        let opts = WriteOptions {
+            alignment: 1 << 31,
+            ..WriteOptions::default()
+        };
+        let mut out = Vec::new();
+        assert!(matches!(
+            wad.write_contents(&mut out, &opts),
+            Err(WadError::OutputTooLarge { size, limit })
+                if size == (1 << 31) + 3 && limit == i32::MAX as u64
+        ));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn wads_are_read_from_their_base_offset() {
+        let wad = loaded();
+        let embedded = [vec![0xcc; 100], wad.write_to_vec().unwrap(), vec![0xdd; 8]].concat();
+        let read = Wad::from_reader_at(Cursor::new(&embedded), 100).unwrap();
+        assert_eq!(read, wad);
+        assert_eq!(read.lump("SECOND").unwrap().data, b"two");
+        assert!(Wad::from_reader_at(Cursor::new(&embedded), 0).is_err());
+    }
+
+    #[test]
+    fn replacing_lump_data_updates_the_directory() {
+        let mut wad = loaded();
+        wad.replace_lump_data(1, b"second".to_vec()).unwrap();
+        assert_eq!(wad.lumps[1].name, "SECOND");
+        assert_eq!(wad.lumps[1].data, b"second");
+        assert_eq!(wad.directory.0[1].size, 6);
+        assert_eq!(wad.directory.0[1].name, "SECOND");
+        wad.assert_valid().unwrap();
+        assert!(matches!(
+            wad.replace_lump_data(3, Vec::new()),
+            Err(WadError::InvalidLumpIndex(3))
+        ));
+        let reloaded = Wad::from_bytes(&wad.write_to_vec().unwrap()).unwrap();
+        assert_eq!(reloaded.lump("SECOND").unwrap().data, b"second");
+    }
+
+    #[test]
+    fn with_capacity_holds_n_lumps_without_growing() {
+        let n = 100;
+        let mut wad = Wad::with_capacity(n);
+        let capacities = |wad: &Wad| {
+            (
+                wad.lumps.capacity(),
+                wad.directory.0.capacity(),
+                wad.name_index.capacity(),
+            )
+        };
+        let before = capacities(&wad);
+        assert!(before.0 >= n && before.1 >= n && before.2 >= n);
+        for i in 0..n {
+            wad.append_lump_bytes(format!("LUMP{i}"), Vec::new())
+                .unwrap();
+        }
+        assert_eq!(capacities(&wad), before);
+        wad.assert_valid().unwrap();
+    }
+
+    #[test]
+    fn only_zip_signatures_are_read_as_zips() {
+        let wad = loaded();
+        let bytes = wad.write_to_vec().unwrap();
+        let mut plain = Cursor::new(&bytes);
+        assert!(!is_zip(&mut plain).unwrap());
+        assert_eq!(plain.position(), 0);
+        let read = Wad::from_reader(plain).unwrap();
+        assert!(!read.was_zip);
+        assert_eq!(read, wad);
+
+        let mut archive = Cursor::new(zipped(&[("maps.wad", &bytes)]));
+        assert!(is_zip(&mut archive).unwrap());
+        assert_eq!(archive.position(), 0);
+        let read = Wad::from_reader(archive).unwrap();
+        assert!(read.was_zip);
+        assert_eq!(read.lumps, wad.lumps);
+
+        let empty = zipped(&[]);
+        assert!(empty.starts_with(b"PK\x05\x06"));
+        assert!(!is_zip(&mut Cursor::new(&empty)).unwrap());
+        assert!(!is_zip(&mut Cursor::new(b"PK")).unwrap());
+    }
+
+    #[test]
+    fn appended_lumps_survive_a_reload() {
+        let mut wad = loaded();
+        wad.append_lump_bytes("FOURTH", b"four".to_vec()).unwrap();
+        wad.append_lump_bytes(String::from("F_START"), Vec::new())
+            .unwrap();
+        for name in ["NINECHARS", "", "CAFÉ", "NUL\0"] {
+            assert!(matches!(
+                wad.append_lump_bytes(name, Vec::new()),
+                Err(WadError::BadLumpName(n)) if n == name
+            ));
+        }
+        let reloaded = Wad::from_bytes(&wad.write_to_vec().unwrap()).unwrap();
+        assert_eq!(reloaded.lumps.len(), 5);
+        assert_eq!(reloaded.lump("FOURTH").unwrap().data, b"four");
+        assert_eq!(reloaded.lumps[4].name, "F_START");
+    }
+
+    #[test]
+    fn identical_lumps_can_share_their_data() {
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in [("A", &b"same"[..]), ("B", b"other"), ("C", b"same")] {
+            wad.append_lump_bytes(name, data.to_vec()).unwrap();
+        }
+        let write = |opts: &WriteOptions| {
+            let mut out = Vec::new();
+            wad.write_contents(&mut out, opts).unwrap();
+            out
+        };
+        let opts = WriteOptions {
+            share_identical: true,
+            ..WriteOptions::default()
+        };
+        let shared = write(&opts);
+        let directory_end = 12 + 3 * 16;
+        assert_eq!(shared.len(), directory_end + 9);
+        assert_eq!(write(&WriteOptions::default()).len(), directory_end + 13);
+        let directory = Directory::from_reader(Cursor::new(&shared)).unwrap();
+        let entries = directory
+            .iter()
+            .map(|e| (e.name.as_str(), e.offset, e.size))
+            .collect::<Vec<_>>();
+        assert_eq!(entries, [("A", 60, 4), ("B", 64, 5), ("C", 60, 4)]);
+        assert_eq!(Wad::from_bytes(&shared).unwrap().lumps, wad.lumps);
+    }
+
+    #[test]
+    fn directories_can_be_searched_without_their_lumps() {
+        let mut wad = loaded();
+        wad.append_lump_bytes("SECOND", b"again".to_vec()).unwrap();
+        let bytes = wad.write_to_vec().unwrap();
+        let directory = Directory::from_reader(Cursor::new(&bytes)).unwrap();
+        let (index, entry) = directory.find("SECOND").unwrap();
+        assert_eq!(index, 1);
+        assert_eq!((entry.offset, entry.size), (12 + 4 * 16 + 3, 3));
+        assert_eq!(directory.position_all("SECOND"), [1, 3]);
+        assert!(directory.find("FOURTH").is_none());
+        assert!(directory.position_all("FOURTH").is_empty());
+    }
+
+    #[test]
+    fn edits_through_iter_mut_are_written() {
+        let mut wad = loaded();
+        for lump in wad.iter_mut() {
+            lump.data.push(b'!');
+        }
+        let reloaded = Wad::from_bytes(&wad.write_to_vec().unwrap()).unwrap();
+        let data = reloaded
+            .iter()
+            .map(|l| l.data.as_slice())
+            .collect::<Vec<_>>();
+        assert_eq!(data, [&b"one!"[..], b"two!", b"!"]);
+        assert_eq!(wad.touched_lumps().len(), 3);
+
+        wad.iter_mut().next().unwrap().name = String::from("ONE");
+        assert!(wad.lump("ONE").is_none());
+        wad.reindex();
+        assert_eq!(wad.lump("ONE").unwrap().data, b"one!");
+        assert!(wad.lump("FIRST").is_none());
+    }
+
+    #[test]
+    fn written_files_are_complete_on_return() {
+        let wad = loaded();
+        let path = std::env::temp_dir().join(format!("wad-{}-sync.wad", std::process::id()));
+        let written = wad.write(&path);
+        let len = std::fs::metadata(&path).map(|m| m.len());
+        let reloaded = Wad::new(&path);
+        std::fs::remove_file(&path).unwrap();
+        written.unwrap();
+        assert_eq!(len.unwrap(), wad.total_size());
+        assert_eq!(reloaded.unwrap(), wad);
+        let missing_dir = std::env::temp_dir().join("wad-missing-dir").join("out.wad");
+        assert!(matches!(
+            wad.write(missing_dir),
+            Err(WadError::CouldntWriteHeader(_))
+        ));
+    }
+
+    #[test]
+    fn lumps_are_counted_by_name() {
+        let mut wad = Wad::new_empty(false);
+        for name in ["MAP01", "SECTORS", "MAP02", "SECTORS", "MAP03", "SECTORS"] {
+            wad.append_lump_bytes(name, Vec::new()).unwrap();
+        }
+        assert_eq!(wad.count_lumps_named("SECTORS"), 3);
+        assert_eq!(wad.count_lumps_named("MAP02"), 1);
+        assert_eq!(wad.count_lumps_named("THINGS"), 0);
+        wad.retain_lumps(|i, _| i != 3);
+        assert_eq!(wad.count_lumps_named("SECTORS"), 2);
+    }
+
+    #[test]
+    fn nested_zips_are_unwrapped_to_a_limit() {
+        let wad = loaded();
+        // `depth` zips deep
+        let nested = |depth: usize| {
+            (0..depth).fold(wad.write_to_vec().unwrap(), |inner, _| {
+                zipped(&[("inner.wad", &inner)])
+            })
+        };
+        let read = Wad::from_bytes(&nested(2)).unwrap();
+        assert!(read.was_zip);
+        assert_eq!(read.lumps, wad.lumps);
+        assert!(Wad::from_bytes(&nested(MAX_ZIP_DEPTH)).is_ok());
+        for depth in [MAX_ZIP_DEPTH + 1, 10] {
+            assert!(matches!(
+                Wad::from_bytes(&nested(depth)),
+                Err(WadError::TooDeeplyNested)
+            ));
+        }
+    }
+
+    #[test]
+    fn read_errors_chain_to_the_io_error() {
+        use std::error::Error;
+
+        let missing = std::env::temp_dir().join("wad-missing-input.wad");
+        let err = Wad::new(missing).unwrap_err();
+        assert!(matches!(err, WadError::CouldntReadHeader(_)));
+        let source = err.source().unwrap().downcast_ref::<std::io::Error>();
+        assert_eq!(source.unwrap().kind(), std::io::ErrorKind::NotFound);
+
+        // the directory is cut short
+        let bytes = loaded().write_to_vec().unwrap();
+        let err = Wad::from_bytes(&bytes[..12 + 16 + 4]).unwrap_err();
+        let source = err.source().unwrap().downcast_ref::<std::io::Error>();
+        assert_eq!(source.unwrap().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(err.to_string().ends_with(&source.unwrap().to_string()));
+    }
+
+    #[test]
+    fn raw_files_are_appended_as_lumps() {
+        let path = std::env::temp_dir().join(format!("wad-{}-readme.txt", std::process::id()));
+        std::fs::write(&path, "Read me!\n").unwrap();
+        let mut wad = loaded();
+        let appended = wad.append_raw_file("README", &path);
+        let bad_name = wad.append_raw_file("TOOLONGNAME", &path);
+        std::fs::remove_file(&path).unwrap();
+        appended.unwrap();
+        assert!(matches!(bad_name, Err(WadError::BadLumpName(_))));
+        assert!(matches!(
+            wad.append_raw_file("MISSING", &path),
+            Err(WadError::CouldntReadLump(_))
+        ));
+        let reloaded = Wad::from_bytes(&wad.write_to_vec().unwrap()).unwrap();
+        assert_eq!(reloaded.lumps.len(), 4);
+        assert_eq!(reloaded.lump("README").unwrap().data, b"Read me!\n");
+    }
+
+    #[test]
+    fn negative_directory_fields_are_an_error() {
+        let bytes = loaded().write_to_vec().unwrap();
+        // the size, then the offset, of the second entry
+        for field in [12 + 16 + 4, 12 + 16] {
+            let mut corrupt = bytes.clone();
+            corrupt[field..field + 4].copy_from_slice(&(-1i32).to_le_bytes());
+            for read in [
+                Wad::from_bytes(&corrupt).map(drop),
+                Directory::from_reader(Cursor::new(&corrupt)).map(drop),
+            ] {
+                assert!(matches!(
+                    read,
+                    Err(WadError::CorruptDirectory { index: 1, name }) if name == "SECOND"
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn write_to_vec_matches_the_written_file() {
+        let mut wad = loaded();
+        wad.was_zip = true;
+        let bytes = wad.write_to_vec().unwrap();
+        // always a plain WAD
+        assert!(bytes.starts_with(b"PWAD"));
+        assert_eq!(bytes.len() as u64, wad.total_size());
+        let mut written = Vec::new();
+        wad.write_contents(&mut written, &WriteOptions::default())
+            .unwrap();
+        assert_eq!(bytes, written);
+        assert_eq!(Wad::from_bytes(&bytes).unwrap().lumps, wad.lumps);
+    }
+
+    #[test]
+    fn lumps_past_the_end_of_the_file_are_an_error() {
+        let mut bytes = loaded().write_to_vec().unwrap();
+        let file_len = bytes.len() as u64;
+        // the size of the second entry
+        let field = 12 + 16 + 4;
+        bytes[field..field + 4].copy_from_slice(&1000i32.to_le_bytes());
+        assert!(matches!(
+            Wad::from_bytes(&bytes),
+            Err(WadError::LumpOutOfBounds { index: 1, name, size: 1000, file_len: len, .. })
+                if name == "SECOND" && len == file_len
+        ));
+        let directory = Directory::from_reader(Cursor::new(&bytes)).unwrap();
+        assert!(directory.validate_sizes_against(file_len).is_err());
+        assert!(directory.validate_sizes_against(file_len + 1000).is_ok());
+    }
+
+    #[test]
+    fn identical_sprites_can_share_their_data() {
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in [
+            ("FRAME", &b"frame"[..]),
+            ("S_START", b""),
+            ("TROOA1", b"frame"),
+            ("TROOB1", b"frame"),
+            ("S_END", b""),
+        ] {
+            wad.append_lump_bytes(name, data.to_vec()).unwrap();
+        }
+        let mut out = Vec::new();
+        let opts = WriteOptions {
+            share_identical_sprites: true,
+            ..WriteOptions::default()
+        };
+        wad.write_contents(&mut out, &opts).unwrap();
+        // the sprites share one copy, but the lump outside the namespace keeps its own
+        let directory_end = 12 + 5 * 16;
+        assert_eq!(out.len(), directory_end + 10);
+        let directory = Directory::from_reader(Cursor::new(&out)).unwrap();
+        let offsets = directory.iter().map(|e| e.offset).collect::<Vec<_>>();
+        assert_ne!(offsets[0], offsets[2]);
+        assert_eq!(offsets[2], offsets[3]);
+        assert_eq!(Wad::from_bytes(&out).unwrap().lumps, wad.lumps);
+    }
+
+    #[test]
+    fn shrinking_frees_the_space_of_removed_lumps() {
+        let mut wad = Wad::new_empty(false);
+        for i in 0..100 {
+            wad.append_lump_bytes(format!("LUMP{i}"), Vec::new())
+                .unwrap();
+        }
+        wad.retain_lumps(|i, _| i < 10);
+        assert!(wad.lumps.capacity() >= 100);
+        wad.shrink_to_fit();
+        assert!(wad.lumps.capacity() < 100);
+        assert!(wad.directory.0.capacity() < 100);
+        assert!(wad.name_index.capacity() < 100);
+        assert_eq!(wad.lumps.len(), 10);
+        assert!(wad.lump("LUMP9").is_some());
+        wad.assert_valid().unwrap();
+    }
+
+    #[test]
+    fn every_lump_of_a_shared_name_is_indexed() {
+        let mut wad = Wad::new_empty(false);
+        for name in ["MAP01", "THINGS", "MAP02", "THINGS", "PLAYPAL"] {
+            wad.append_lump_bytes(name, Vec::new()).unwrap();
+        }
+        let mut wad = Wad::from_bytes(&wad.write_to_vec().unwrap()).unwrap();
+        let index = wad.name_to_indices();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index["THINGS"], [1, 3]);
+        assert_eq!(index["PLAYPAL"], [4]);
+
+        wad.retain_lumps(|i, _| i != 0);
+        wad.rename_lump(0, "ANOTHER").unwrap();
+        let index = wad.name_to_indices();
+        assert_eq!(index["THINGS"], [2]);
+        assert_eq!(index["ANOTHER"], [0]);
+        assert!(!index.contains_key("MAP01"));
+        assert_eq!(index["PLAYPAL"], [3]);
+    }
+
+    #[test]
+    fn common_non_wads_are_recognized() {
+        let html = Wad::from_bytes(b"<!DOCTYPE html>\n<html></html>\n").unwrap_err();
+        assert!(matches!(html, WadError::NotAWad { detected: "HTML" }));
+        assert_eq!(html.to_string(), "not a WAD file; it looks like HTML");
+        for (data, expected) in [
+            (&b"\xEF\xBB\xBF<html>"[..], "HTML"),
+            (b"\r\n <a>", "HTML"),
+            (b"\x1F\x8B\x08\0\0\0\0\0\0\0\0\0", "a gzip archive"),
+            (b"Thing 1 (Player)\n", "a text file"),
+        ] {
+            assert!(
+                matches!(
+                    Wad::from_bytes(data),
+                    Err(WadError::NotAWad { detected }) if detected == expected
+                ),
+                "{expected}"
+            );
+        }
+        assert!(matches!(
+            Wad::from_bytes(b"\0\x01\x02\x03\0\0\0\0\0\0\0\0"),
+            Err(WadError::InvalidMagicNumber([0, 1, 2, 3]))
+        ));
+    }
+
+    #[test]
+    fn lumps_are_stripped_by_name_in_any_case() {
+        let mut wad = Wad::new_empty(false);
+        for name in ["DEMO1", "PLAYPAL", "demo1", "ENDOOM", "DEMO1"] {
+            wad.add_lump(Lump {
+                name: name.to_string(),
+                data: Vec::new(),
+            });
+        }
+        assert_eq!(wad.strip_lumps_named(&["Demo1", "endoom"]), 4);
+        assert_eq!(wad.lumps.len(), 1);
+        assert_eq!(wad.lumps[0].name, "PLAYPAL");
+        assert_eq!(wad.strip_lumps_named(&["DEMO1"]), 0);
+        wad.assert_valid().unwrap();
+    }
+
+    #[test]
+    fn data_regions_point_at_the_written_data() {
+        let mut wad = loaded();
+        wad.append_lump_bytes("AGAIN", b"two".to_vec()).unwrap();
+        for opts in [
+            WriteOptions::default(),
+            WriteOptions {
+                share_identical: true,
+                ..WriteOptions::default()
+            },
+        ] {
+            let mut bytes = Vec::new();
+            wad.write_contents(&mut bytes, &opts).unwrap();
+            for (index, lump) in wad.lumps.iter().enumerate() {
+                let (offset, size) = wad.data_region(index, &opts).unwrap();
+                let region = &bytes[offset as usize..(offset + size) as usize];
+                assert_eq!(region, lump.data, "{}", lump.name);
+            }
+        }
+        let shared = WriteOptions {
+            share_identical: true,
+            ..WriteOptions::default()
+        };
+        assert_eq!(wad.data_region(1, &shared), wad.data_region(3, &shared));
+        assert_eq!(wad.data_region(4, &shared), None);
+    }
+
+    #[test]
+    fn big_endian_headers_are_warned_about() {
+        let bytes = loaded().write_to_vec().unwrap();
+        assert!(Wad::header_warnings(Cursor::new(&bytes))
+            .unwrap()
+            .is_empty());
+        let mut swapped = bytes;
+        swapped[4..8].copy_from_slice(&3i32.to_be_bytes());
+        swapped[8..12].copy_from_slice(&12i32.to_be_bytes());
+        assert_eq!(
+            Wad::header_warnings(Cursor::new(&swapped)).unwrap(),
+            [ValidationWarning::PossiblyByteSwapped {
+                num_lumps: 3i32.swap_bytes(),
+                directory_offset: 12i32.swap_bytes(),
+            }]
+        );
+        // a header that fits neither way isn't put down to byte order
+        let mut corrupt = swapped;
+        corrupt[4..8].copy_from_slice(&1000i32.to_le_bytes());
+        assert!(Wad::header_warnings(Cursor::new(&corrupt))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn from_lumps_undoes_into_lumps() {
+        let lumps = loaded().into_lumps();
+        let wad = Wad::from_lumps(lumps.clone()).unwrap();
+        wad.assert_valid().unwrap();
+        assert_eq!(wad.lump("SECOND").unwrap().data, b"two");
+        let reloaded = Wad::from_bytes(&wad.write_to_vec().unwrap()).unwrap();
+        assert_eq!(reloaded.into_lumps(), lumps);
+
+        let too_long = Lump {
+            name: String::from("NINECHARS"),
+            data: Vec::new(),
+        };
+        assert!(matches!(
+            Wad::from_lumps(vec![too_long]),
+            Err(WadError::BadLumpName(name)) if name == "NINECHARS"
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+use linked_hash_map::LinkedHashMap;
+
+use crate::namespace::is_marker;
+use crate::namespace::namespace_blocks;
+use crate::Namespace;
+use crate::Wad;
+
+/// The lump a port ends up using for a name. See [`resolve_load_order`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ResolvedLump {
+    pub name: String,
+    /// The namespace the lump was found in. Names in different namespaces don't clash.
+    pub namespace: Option<Namespace>,
+    /// The WAD providing the lump: 0 for the IWAD, then 1 for the first PWAD, and so on.
+    pub wad: usize,
+    /// The lump's index in that WAD.
+    pub index: usize,
+}
+
+/// The map a port ends up playing for a marker name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ResolvedMap {
+    pub name: String,
+    /// The WAD providing the map, numbered like [`ResolvedLump::wad`].
+    pub wad: usize,
+}
+
+/// What a port sees after loading an IWAD and some PWADs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ResolvedView {
+    /// Every lump outside the maps, in the order their names were first seen.
+    pub lumps: Vec<ResolvedLump>,
+    /// Every map, in the order their names were first seen.
+    pub maps: Vec<ResolvedMap>,
+}
+
+impl ResolvedView {
+    /// The lump a port uses for a name, outside any namespace.
+    pub fn lump(&self, name: &str) -> Option<&ResolvedLump> {
+        self.lumps
+            .iter()
+            .find(|lump| lump.namespace.is_none() && lump.name == name)
+    }
+
+    /// The map a port plays for a marker name.
+    pub fn map(&self, name: &str) -> Option<&ResolvedMap> {
+        self.maps.iter().find(|map| map.name == name)
+    }
+}
+
+/// Works out which lumps a source port would use after loading `iwad` and then `pwads`, without
+/// merging anything. Later WADs override earlier ones' lumps of the same name within the same
+/// namespace, and a map replaces an earlier map of the same name as a whole, lumps and all.
+pub fn resolve_load_order(iwad: &Wad, pwads: &[Wad]) -> ResolvedView {
+    let mut lumps = LinkedHashMap::<(Option<Namespace>, &str), ResolvedLump>::new();
+    let mut maps = LinkedHashMap::new();
+    for (wad_index, wad) in std::iter::once(iwad).chain(pwads).enumerate() {
+        let names = wad
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        let mut namespace_of = vec![None; names.len()];
+        for (namespace, range) in namespace_blocks(&names, Namespace::ALL) {
+            namespace_of[range].fill(Some(namespace));
+        }
+        let mut in_map = vec![false; names.len()];
+        for map in wad.maps() {
+            in_map[map.range.clone()].fill(true);
+            *maps.entry(map.name.to_string()).or_default() = wad_index;
+        }
+        for (index, name) in names.into_iter().enumerate() {
+            if in_map[index] || (namespace_of[index].is_some() && is_marker(name)) {
+                continue;
+            }
+            let lump = ResolvedLump {
+                name: name.to_string(),
+                namespace: namespace_of[index],
+                wad: wad_index,
+                index,
+            };
+            // overriding a lump leaves it where it was first seen
+            match lumps.get_mut(&(namespace_of[index], name)) {
+                Some(existing) => *existing = lump,
+                None => {
+                    lumps.insert((namespace_of[index], name), lump);
+                }
+            }
+        }
+    }
+    ResolvedView {
+        lumps: lumps.into_iter().map(|(_, lump)| lump).collect(),
+        maps: maps
+            .into_iter()
+            .map(|(name, wad)| ResolvedMap { name, wad })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(names: &[&str]) -> Wad {
+        let mut wad = Wad::new_empty(false);
+        for name in names {
+            wad.append_lump_bytes(*name, Vec::new()).unwrap();
+        }
+        wad
+    }
+
+    #[test]
+    fn later_wads_override_lumps_and_whole_maps() {
+        let iwad = named(&[
+            "PLAYPAL", "MAP01", "THINGS", "LINEDEFS", "MAP02", "THINGS", "S_START", "TROOA1",
+            "S_END",
+        ]);
+        let pwad = named(&["MAP01", "THINGS", "TROOA1", "PLAYPAL"]);
+        let view = resolve_load_order(&iwad, &[pwad]);
+
+        assert_eq!(view.map("MAP01").unwrap().wad, 1);
+        assert_eq!(view.map("MAP02").unwrap().wad, 0);
+        let maps = view
+            .maps
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(maps, ["MAP01", "MAP02"]);
+
+        // the overriding PLAYPAL stays where the name was first seen
+        assert_eq!(view.lumps[0].name, "PLAYPAL");
+        assert_eq!((view.lumps[0].wad, view.lumps[0].index), (1, 3));
+        // a sprite outside the namespace doesn't replace the one in it
+        let sprites = view
+            .lumps
+            .iter()
+            .filter(|l| l.name == "TROOA1")
+            .map(|l| (l.namespace, l.wad))
+            .collect::<Vec<_>>();
+        assert_eq!(sprites, [(Some(Namespace::Sprites), 0), (None, 1)]);
+        assert_eq!(view.lump("TROOA1").unwrap().wad, 1);
+        // map lumps and markers aren't lumps of their own
+        assert_eq!(view.lumps.len(), 3);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::Path;
+
+use crate::validate_name;
+use crate::Lump;
+use crate::Wad;
+use crate::WadError;
+use crate::WadResult;
+
+/// The file in a lump directory which lists the order of its lumps.
+const MANIFEST: &str = "order.txt";
+
+/// The lump name for a file: its name without the extension, in upper case.
+pub(crate) fn lump_name(file_name: &str) -> String {
+    let stem = match file_name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => file_name,
+    };
+    stem.to_ascii_uppercase()
+}
+
+impl Wad {
+    /// Builds a WAD out of a directory of loose lump files, like `E1M1.lmp` and `THINGS.lmp`.
+    /// Each file becomes a lump named after it, minus the extension and in upper case.
+    ///
+    /// If the directory has an `order.txt`, it lists the files to use, one per line and in
+    /// order. Blank lines and lines starting with `#` are skipped. Otherwise every file is used,
+    /// sorted by name.
+    pub fn from_lump_dir<P: AsRef<Path>>(path: P) -> WadResult<Self> {
+        let path = path.as_ref();
+        let manifest = path.join(MANIFEST);
+        let files = if manifest.is_file() {
+            fs::read_to_string(&manifest)
+                .map_err(WadError::CouldntReadLump)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect()
+        } else {
+            let mut files = Vec::new();
+            for entry in fs::read_dir(path).map_err(WadError::CouldntReadLump)? {
+                let entry = entry.map_err(WadError::CouldntReadLump)?;
+                let is_file = entry
+                    .file_type()
+                    .map_err(WadError::CouldntReadLump)?
+                    .is_file();
+                if is_file {
+                    files.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+            files.sort();
+            files
+        };
+
+        let mut wad = Wad::with_capacity(files.len());
+        for file in files {
+            let name = lump_name(&file);
+            validate_name(&name)?;
+            let data = fs::read(path.join(&file)).map_err(WadError::CouldntReadLump)?;
+            wad.add_lump(Lump { name, data });
+        }
+        Ok(wad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(wad: &Wad) -> Vec<&str> {
+        wad.lumps.iter().map(|l| l.name.as_str()).collect()
+    }
+
+    #[test]
+    fn lump_dirs_are_read_in_manifest_order() {
+        let dir = std::env::temp_dir().join(format!("wad-{}-lumpdir", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, data) in [
+            ("e1m1.lmp", &b""[..]),
+            ("things.lmp", b"things"),
+            ("LINEDEFS.lmp", b"linedefs"),
+        ] {
+            fs::write(dir.join(file), data).unwrap();
+        }
+        let sorted = Wad::from_lump_dir(&dir).unwrap();
+        fs::write(
+            dir.join(MANIFEST),
+            "# the map\ne1m1.lmp\n\n  things.lmp\nLINEDEFS.lmp\n",
+        )
+        .unwrap();
+        let ordered = Wad::from_lump_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(names(&sorted), ["LINEDEFS", "E1M1", "THINGS"]);
+        let ordered = ordered.unwrap();
+        assert_eq!(names(&ordered), ["E1M1", "THINGS", "LINEDEFS"]);
+        assert_eq!(ordered.lumps[1].data, b"things");
+        assert_eq!(ordered.maps().len(), 1);
+    }
+}
@@ -0,0 +1,693 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::parse_textmap;
+use crate::Game;
+use crate::Lump;
+use crate::Wad;
+use crate::WadError;
+use crate::WadResult;
+
+/// The lumps that may follow a binary (Doom or Hexen format) map marker.
+const BINARY_MAP_LUMPS: &[&str] = &[
+    "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES", "SECTORS", "REJECT",
+    "BLOCKMAP", "BEHAVIOR", "SCRIPTS",
+];
+
+/// Whether a lump may be among those following a `GL_<map>` marker, like `GL_VERT` or
+/// `GL_PVS`. Any `GL_` lump may, except for another map's marker.
+fn is_gl_map_lump(name: &str) -> bool {
+    let Some(rest) = name.strip_prefix("GL_") else {
+        return false;
+    };
+    let is_marker = rest == "LEVEL"
+        || rest
+            .strip_prefix("MAP")
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit()))
+        || matches!(rest.as_bytes(), [b'E', b'0'..=b'9', b'M', b'0'..=b'9']);
+    !is_marker
+}
+
+pub(crate) const SECTOR_SIZE: usize = 26;
+
+/// Vanilla engine limits, as (lump, maximum record count). Most of these come from the engine
+/// referencing records through signed 16-bit indices.
+const VANILLA_LIMITS: &[(&str, usize)] = &[
+    ("VERTEXES", i16::MAX as usize),
+    ("LINEDEFS", i16::MAX as usize),
+    ("SIDEDEFS", i16::MAX as usize),
+    ("SECTORS", i16::MAX as usize),
+    ("SEGS", i16::MAX as usize),
+    ("SSECTORS", i16::MAX as usize),
+    ("NODES", i16::MAX as usize),
+];
+
+/// How [`Wad::rename_all_maps`] names maps, by their position in the WAD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapScheme {
+    /// `MAP01`, `MAP02` and so on.
+    Linear,
+    /// `E1M1` through `E1M9`, then `E2M1` and so on.
+    Episodic,
+    /// The given names, in order. Maps past the end of the list keep their names.
+    Custom(Vec<String>),
+}
+
+impl MapScheme {
+    /// The name of the map at a position, counting from 1.
+    fn name(&self, position: usize) -> Option<String> {
+        match self {
+            Self::Linear => Some(Game::Doom2.slot_name(position)),
+            Self::Episodic => Some(Game::Doom.slot_name(position)),
+            Self::Custom(names) => names.get(position - 1).cloned(),
+        }
+    }
+}
+
+/// The way a map's data is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapFormat {
+    /// The original binary format.
+    Doom,
+    /// The binary format extended by Hexen. Recognized by its BEHAVIOR lump.
+    Hexen,
+    /// The text-based Universal Doom Map Format, stored in a TEXTMAP lump.
+    Udmf,
+}
+
+impl MapFormat {
+    /// The size of one record in the THINGS lump.
+    pub fn thing_size(self) -> Option<usize> {
+        match self {
+            Self::Doom => Some(10),
+            Self::Hexen => Some(20),
+            Self::Udmf => None,
+        }
+    }
+
+    /// The size of one record in the LINEDEFS lump.
+    pub fn linedef_size(self) -> Option<usize> {
+        match self {
+            Self::Doom => Some(14),
+            Self::Hexen => Some(16),
+            Self::Udmf => None,
+        }
+    }
+
+    /// The size of one record in a binary map lump, for lumps made of fixed-size records.
+    pub fn record_size(self, lump: &str) -> Option<usize> {
+        match lump {
+            "THINGS" => self.thing_size(),
+            "LINEDEFS" => self.linedef_size(),
+            _ if self == Self::Udmf => None,
+            "SIDEDEFS" => Some(30),
+            "VERTEXES" | "SSECTORS" => Some(4),
+            "SEGS" => Some(12),
+            "NODES" => Some(28),
+            "SECTORS" => Some(SECTOR_SIZE),
+            _ => None,
+        }
+    }
+}
+
+/// The position of a map within a list of lump names.
+#[derive(Debug, Clone)]
+pub(crate) struct MapGroup {
+    pub(crate) format: MapFormat,
+    /// Covers the marker and every lump belonging to the map.
+    pub(crate) range: Range<usize>,
+}
+
+/// Finds every map in a sequence of lump names. A map is a marker lump followed by either
+/// binary map lumps (starting with THINGS) or a TEXTMAP and everything up to its ENDMAP.
+pub(crate) fn group_maps(names: &[&str]) -> Vec<MapGroup> {
+    let mut groups = Vec::new();
+    let mut i = 0;
+    while i + 1 < names.len() {
+        let marker = names[i];
+        let (format, mut end) = match names[i + 1] {
+            "THINGS" => {
+                let mut seen = HashSet::new();
+                let mut end = i + 1;
+                while end < names.len()
+                    && BINARY_MAP_LUMPS.contains(&names[end])
+                    && seen.insert(names[end])
+                {
+                    end += 1;
+                }
+                let format = if seen.contains("BEHAVIOR") {
+                    MapFormat::Hexen
+                } else {
+                    MapFormat::Doom
+                };
+                (format, end)
+            }
+            "TEXTMAP" => {
+                // Anything may come between TEXTMAP and ENDMAP. Without an ENDMAP before the
+                // next map's TEXTMAP, the map is just the TEXTMAP.
+                let end = names[i + 2..]
+                    .iter()
+                    .take_while(|name| **name != "TEXTMAP")
+                    .position(|name| *name == "ENDMAP")
+                    .map_or(i + 2, |pos| i + 2 + pos + 1);
+                (MapFormat::Udmf, end)
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        if names.get(end).copied() == Some(format!("GL_{marker}").as_str()) {
+            end += 1;
+            while end < names.len() && is_gl_map_lump(names[end]) {
+                end += 1;
+            }
+        }
+        groups.push(MapGroup {
+            format,
+            range: i..end,
+        });
+        i = end;
+    }
+    groups
+}
+
+/// A view of one map's lumps within a WAD.
+#[derive(Debug, Clone)]
+pub struct MapSlice<'wad> {
+    /// The name of the map marker, e.g. `MAP01` or `E1M1`.
+    pub name: &'wad str,
+    pub format: MapFormat,
+    /// The indices of the map's lumps in the WAD, marker included.
+    pub range: Range<usize>,
+    /// The map's lumps, marker included.
+    pub lumps: &'wad [Lump],
+}
+
+impl<'wad> MapSlice<'wad> {
+    /// Finds one of the map's data lumps by name.
+    pub fn lump(&self, name: &str) -> Option<&'wad Lump> {
+        self.lumps[1..].iter().find(|lump| lump.name == name)
+    }
+
+    /// The slot the map occupies in the original game, e.g. 12 for `MAP12`. Episodic maps count
+    /// nine slots per episode, so `E2M3` is slot 12.
+    pub fn slot(&self) -> Option<usize> {
+        if let Some(num) = self.name.strip_prefix("MAP") {
+            return num.parse().ok();
+        }
+        match self.name.as_bytes() {
+            [b'E', e @ b'1'..=b'9', b'M', m @ b'1'..=b'9'] => {
+                Some((e - b'1') as usize * 9 + (m - b'0') as usize)
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks the map's record counts against the limits of the vanilla engine.
+    pub fn vanilla_limit_report(&self) -> Vec<LimitViolation> {
+        if self.format == MapFormat::Udmf {
+            return Vec::new();
+        }
+        let mut violations = Vec::new();
+        for &(lump, limit) in VANILLA_LIMITS {
+            let size = self.format.record_size(lump).unwrap();
+            let count = self.lump(lump).map_or(0, |l| l.data.len() / size);
+            if count > limit {
+                violations.push(LimitViolation { lump, count, limit });
+            }
+        }
+        violations
+    }
+
+    /// Summarizes the map. Binary record counts are derived from lump sizes, and UDMF ones
+    /// from counting the TEXTMAP's blocks. A TEXTMAP which can't be read counts as empty.
+    pub fn summary(&self) -> MapSummary {
+        if self.format == MapFormat::Udmf {
+            let textmap = self
+                .lump("TEXTMAP")
+                .and_then(|lump| parse_textmap(&String::from_utf8_lossy(&lump.data)).ok())
+                .unwrap_or_default();
+            return MapSummary {
+                name: self.name.to_string(),
+                format: self.format,
+                namespace: textmap.namespace,
+                things: textmap.things,
+                linedefs: textmap.linedefs,
+                sectors: textmap.sectors,
+                limit_violations: Vec::new(),
+            };
+        }
+        let count = |lump: &str, size: Option<usize>| match (self.lump(lump), size) {
+            (Some(lump), Some(size)) => lump.data.len() / size,
+            _ => 0,
+        };
+        MapSummary {
+            name: self.name.to_string(),
+            format: self.format,
+            namespace: None,
+            things: count("THINGS", self.format.thing_size()),
+            linedefs: count("LINEDEFS", self.format.linedef_size()),
+            sectors: count("SECTORS", Some(SECTOR_SIZE)),
+            limit_violations: self.vanilla_limit_report(),
+        }
+    }
+}
+
+/// A map lump holding more records than the vanilla engine supports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitViolation {
+    pub lump: &'static str,
+    pub count: usize,
+    pub limit: usize,
+}
+
+/// Zero padding removed from the end of a map lump by [`Wad::trim_padding`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrimmedPadding {
+    pub map: String,
+    pub lump: String,
+    /// How many bytes were removed.
+    pub bytes: usize,
+}
+
+/// A WAD split into its resources and its maps. See [`Wad::split_maps`].
+#[derive(Debug)]
+pub struct SplitWad {
+    pub resources: Wad,
+    pub maps: Vec<Wad>,
+}
+
+/// Overview information about a map.
+#[derive(Debug, Clone)]
+pub struct MapSummary {
+    pub name: String,
+    pub format: MapFormat,
+    /// The namespace a UDMF map declares.
+    pub namespace: Option<String>,
+    pub things: usize,
+    pub linedefs: usize,
+    pub sectors: usize,
+    pub limit_violations: Vec<LimitViolation>,
+}
+
+impl Wad {
+    /// Lists the maps in the WAD, in directory order.
+    pub fn maps(&self) -> Vec<MapSlice<'_>> {
+        let names = self
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        group_maps(&names)
+            .into_iter()
+            .map(|group| MapSlice {
+                name: &self.lumps[group.range.start].name,
+                format: group.format,
+                lumps: &self.lumps[group.range.clone()],
+                range: group.range,
+            })
+            .collect()
+    }
+
+    /// The indices of a map's lumps, from its marker through its last lump (GL nodes included).
+    pub fn map_range(&self, name: &str) -> Option<Range<usize>> {
+        let names = self
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        group_maps(&names)
+            .into_iter()
+            .map(|group| group.range)
+            .find(|range| names[range.start] == name)
+    }
+
+    /// Finds a map by its marker name.
+    pub fn find_map(&self, name: &str) -> Option<MapSlice<'_>> {
+        self.maps().into_iter().find(|map| map.name == name)
+    }
+
+    /// Finds the map in a Doom II style slot, e.g. `MAP07` for slot 7.
+    pub fn map_at_slot(&self, slot: u32) -> Option<MapSlice<'_>> {
+        self.find_map(&Game::Doom2.slot_name(slot as usize))
+    }
+
+    /// Removes a map: its marker and every lump belonging to it, GL nodes included.
+    pub fn remove_map(&mut self, name: &str) -> WadResult<()> {
+        let range = self
+            .map_range(name)
+            .ok_or_else(|| WadError::MissingMap(name.to_string()))?;
+        self.retain_lumps(|i, _| !range.contains(&i));
+        Ok(())
+    }
+
+    /// Splits the WAD into one holding everything but the maps and several holding
+    /// `maps_per_file` maps each, in order. Loading the resource WAD first and then the map WADs
+    /// gives the same result as loading this one. The parts are always plain WADs.
+    pub fn split_maps(&self, maps_per_file: usize) -> SplitWad {
+        let maps = self.maps();
+        let mut in_map = vec![false; self.lumps.len()];
+        for map in &maps {
+            in_map[map.range.clone()].fill(true);
+        }
+        let mut resources = Wad::new_empty(false);
+        for (lump, _) in self.lumps.iter().zip(in_map).filter(|(_, in_map)| !in_map) {
+            resources.add_lump(lump.clone());
+        }
+        let maps = maps
+            .chunks(maps_per_file.max(1))
+            .map(|chunk| {
+                let mut wad = Wad::new_empty(false);
+                for lump in chunk.iter().flat_map(|map| map.lumps) {
+                    wad.add_lump(lump.clone());
+                }
+                wad
+            })
+            .collect();
+        SplitWad { resources, maps }
+    }
+
+    /// Renames every map after its position in the WAD, following `scheme`, along with its GL
+    /// nodes. The rest of a map's lumps, like BEHAVIOR, have fixed names and stay with it.
+    /// Renaming again with the same scheme changes nothing. Returns each map's old name with
+    /// its new one.
+    pub fn rename_all_maps(&mut self, scheme: &MapScheme) -> WadResult<HashMap<String, String>> {
+        let maps = self
+            .maps()
+            .into_iter()
+            .map(|map| (map.name.to_string(), map.range))
+            .collect::<Vec<_>>();
+        let mut renamed = HashMap::new();
+        for (position, (old, range)) in (1..).zip(maps) {
+            let new = scheme.name(position).unwrap_or_else(|| old.clone());
+            if new != old {
+                self.rename_lump(range.start, new.as_str())?;
+                let gl_marker = format!("GL_{old}");
+                if let Some(gl) = range.clone().find(|&i| self.lumps[i].name == gl_marker) {
+                    self.rename_lump(gl, format!("GL_{new}"))?;
+                }
+            }
+            renamed.insert(old, new);
+        }
+        Ok(renamed)
+    }
+
+    /// Opens a WAD file, then trims padding from its map lumps as [`Wad::trim_padding`] does.
+    pub fn open_trim_padding<P: AsRef<Path>>(path: P) -> WadResult<(Self, Vec<TrimmedPadding>)> {
+        let mut wad = Self::new(path)?;
+        let trimmed = wad.trim_padding()?;
+        Ok((wad, trimmed))
+    }
+
+    /// Removes the padding some tools leave after a map lump's records: up to three trailing
+    /// bytes past the last whole record, and only if they're all zero. Only lumps made of
+    /// fixed-size records are touched. Each trimmed lump is logged.
+    pub fn trim_padding(&mut self) -> WadResult<Vec<TrimmedPadding>> {
+        let mut to_trim = Vec::new();
+        for map in self.maps() {
+            for (k, lump) in map.lumps.iter().enumerate().skip(1) {
+                let Some(size) = map.format.record_size(&lump.name) else {
+                    continue;
+                };
+                let excess = lump.data.len() % size;
+                let padding = &lump.data[lump.data.len() - excess..];
+                if (1..=3).contains(&excess) && padding.iter().all(|&b| b == 0) {
+                    to_trim.push((map.range.start + k, excess, map.name.to_string()));
+                }
+            }
+        }
+        let mut trimmed = Vec::new();
+        for (index, bytes, map) in to_trim {
+            let mut data = std::mem::take(&mut self.lumps[index].data);
+            data.truncate(data.len() - bytes);
+            self.replace_lump_data(index, data)?;
+            log::info!(
+                "trimmed {bytes} bytes of padding from {} in {map}",
+                self.lumps[index].name
+            );
+            trimmed.push(TrimmedPadding {
+                map,
+                lump: self.lumps[index].name.clone(),
+                bytes,
+            });
+        }
+        Ok(trimmed)
+    }
+
+    /// Summarizes every map in the WAD.
+    pub fn map_summaries(&self) -> Vec<MapSummary> {
+        self.maps().iter().map(MapSlice::summary).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summaries_count_records_from_lump_sizes() {
+        let mut wad = Wad::new_empty(false);
+        for (name, size) in [
+            ("MAP01", 0),
+            ("THINGS", 3 * 10),
+            ("LINEDEFS", 2 * 14),
+            ("SECTORS", SECTOR_SIZE),
+            ("MAP02", 0),
+            ("THINGS", 3 * 20),
+            ("BEHAVIOR", 0),
+        ] {
+            wad.append_lump_bytes(name, vec![0; size]).unwrap();
+        }
+        let summaries = wad.map_summaries();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].format, MapFormat::Doom);
+        assert_eq!(
+            (
+                summaries[0].things,
+                summaries[0].linedefs,
+                summaries[0].sectors
+            ),
+            (3, 2, 1)
+        );
+        assert_eq!(summaries[1].format, MapFormat::Hexen);
+        assert_eq!(summaries[1].things, 3);
+    }
+
+    #[test]
+    fn zero_padding_is_trimmed_from_record_lumps() {
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in [
+            ("MAP01", vec![]),
+            ("THINGS", [vec![1; 2 * 10], vec![0; 2]].concat()),
+            // not padding, since it isn't all zero
+            ("LINEDEFS", [vec![1; 14], vec![0, 1]].concat()),
+            ("SECTORS", vec![1; SECTOR_SIZE]),
+            // not made of records
+            ("REJECT", vec![0; 3]),
+        ] {
+            wad.append_lump_bytes(name, data).unwrap();
+        }
+        let bytes = wad.write_to_vec().unwrap();
+        let mut wad = Wad::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            wad.trim_padding().unwrap(),
+            [TrimmedPadding {
+                map: String::from("MAP01"),
+                lump: String::from("THINGS"),
+                bytes: 2,
+            }]
+        );
+        assert_eq!(wad.lumps[1].data, vec![1; 20]);
+        assert_eq!(wad.directory.iter().nth(1).unwrap().size, 20);
+        assert_eq!(wad.lumps[2].data.len(), 16);
+        assert_eq!(wad.lumps[4].data.len(), 3);
+        assert_eq!(wad.touched_lumps().iter().copied().collect::<Vec<_>>(), [1]);
+        assert!(wad.trim_padding().unwrap().is_empty());
+    }
+
+    /// A WAD holding lumps with the given names, all empty.
+    fn named(names: &[&str]) -> Wad {
+        let mut wad = Wad::new_empty(false);
+        for name in names {
+            wad.append_lump_bytes(*name, Vec::new()).unwrap();
+        }
+        wad
+    }
+
+    #[test]
+    fn map_ranges_run_from_the_marker_to_the_last_map_lump() {
+        let wad = named(&[
+            "PLAYPAL", "E1M1", "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS",
+            "NODES", "SECTORS", "REJECT", "BLOCKMAP", "GL_E1M1", "GL_VERT", "E1M2", "THINGS",
+            "COLORMAP",
+        ]);
+        assert_eq!(wad.map_range("E1M1"), Some(1..14));
+        assert_eq!(wad.lumps[11].name, "BLOCKMAP");
+        assert_eq!(wad.map_range("E1M2"), Some(14..16));
+        assert_eq!(wad.map_range("E1M3"), None);
+        assert_eq!(wad.map_range("PLAYPAL"), None);
+        let maps = wad.maps();
+        assert_eq!(maps[0].range, wad.map_range("E1M1").unwrap());
+    }
+
+    #[test]
+    fn udmf_maps_run_through_endmap() {
+        let wad = named(&[
+            "MAP01", "TEXTMAP", "ZNODES", "THINGS", "DIALOGUE", "SCRIPTS", "ENDMAP", "MAP02",
+            "THINGS", "LINEDEFS", "MAP03", "TEXTMAP", "MAP04", "TEXTMAP", "ENDMAP",
+        ]);
+        let maps = wad.maps();
+        let groups = maps
+            .iter()
+            .map(|map| (map.name, map.format, map.range.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            groups,
+            [
+                ("MAP01", MapFormat::Udmf, 0..7),
+                ("MAP02", MapFormat::Doom, 7..10),
+                // no ENDMAP of its own
+                ("MAP03", MapFormat::Udmf, 10..12),
+                ("MAP04", MapFormat::Udmf, 12..15),
+            ]
+        );
+    }
+
+    /// The lump names of a complete Doom-format map.
+    fn doom_map(name: &str) -> Vec<String> {
+        [
+            name, "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES",
+            "SECTORS", "REJECT", "BLOCKMAP",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    #[test]
+    fn removing_a_map_drops_just_its_lumps() {
+        let mut names = vec![String::from("PLAYPAL")];
+        for map in 1..=9 {
+            names.extend(doom_map(&format!("E1M{map}")));
+            if map == 5 {
+                names.extend(["GL_E1M5", "GL_VERT", "GL_SEGS"].map(String::from));
+            }
+        }
+        names.push(String::from("ENDOOM"));
+        let mut wad = named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        wad.remove_map("E1M5").unwrap();
+        assert_eq!(wad.lumps.len(), names.len() - 14);
+        let maps = wad.maps().iter().map(|m| m.name).collect::<Vec<_>>();
+        assert_eq!(
+            maps,
+            ["E1M1", "E1M2", "E1M3", "E1M4", "E1M6", "E1M7", "E1M8", "E1M9"]
+        );
+        assert!(wad.maps().iter().all(|m| m.range.len() == 11));
+        assert!(wad.lump("GL_VERT").is_none());
+        assert_eq!(wad.lumps.last().unwrap().name, "ENDOOM");
+        wad.assert_valid().unwrap();
+        assert!(matches!(
+            wad.remove_map("E1M5"),
+            Err(WadError::MissingMap(name)) if name == "E1M5"
+        ));
+    }
+
+    #[test]
+    fn maps_are_found_by_slot() {
+        let mut names = Vec::new();
+        for slot in 1..=8 {
+            names.extend(doom_map(&format!("MAP{slot:02}")));
+        }
+        let wad = named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        let map = wad.map_at_slot(7).unwrap();
+        assert_eq!(map.name, "MAP07");
+        assert_eq!(map.range, 66..77);
+        assert!(wad.map_at_slot(99).is_none());
+        assert!(wad.map_at_slot(0).is_none());
+        let episodic = named(
+            &doom_map("E1M1")
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+        );
+        assert!(episodic.map_at_slot(1).is_none());
+    }
+
+    #[test]
+    fn maps_are_split_off_from_the_resources() {
+        let mut names = vec![String::from("PLAYPAL")];
+        for slot in 1..=5 {
+            names.extend(doom_map(&format!("MAP{slot:02}")));
+        }
+        names.push(String::from("TEXTURE1"));
+        let wad = named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+
+        let split = wad.split_maps(2);
+        let resources = split.resources.lumps.iter().map(|l| l.name.as_str());
+        assert_eq!(resources.collect::<Vec<_>>(), ["PLAYPAL", "TEXTURE1"]);
+        let maps = split
+            .maps
+            .iter()
+            .map(|w| {
+                w.maps()
+                    .iter()
+                    .map(|m| m.name.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            maps,
+            [
+                ["MAP01", "MAP02"].as_slice(),
+                &["MAP03", "MAP04"],
+                &["MAP05"]
+            ]
+        );
+        assert_eq!(split.maps[2].lumps.len(), 11);
+        // asking for no maps per file still gives one each
+        assert_eq!(wad.split_maps(0).maps.len(), 5);
+    }
+
+    #[test]
+    fn maps_are_renamed_by_position_with_their_gl_nodes() {
+        let mut names = doom_map("E1M1");
+        names.extend(["GL_E1M1", "GL_VERT"].map(String::from));
+        names.extend(doom_map("MAP05"));
+        let mut wad = named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+
+        let renamed = wad.rename_all_maps(&MapScheme::Linear).unwrap();
+        assert_eq!(renamed["E1M1"], "MAP01");
+        assert_eq!(renamed["MAP05"], "MAP02");
+        let maps = wad
+            .maps()
+            .iter()
+            .map(|m| m.name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(maps, ["MAP01", "MAP02"]);
+        assert_eq!(wad.lumps[11].name, "GL_MAP01");
+        assert_eq!(wad.lumps[12].name, "GL_VERT");
+
+        let again = wad.rename_all_maps(&MapScheme::Linear).unwrap();
+        assert!(again.iter().all(|(old, new)| old == new));
+
+        let custom = MapScheme::Custom(vec![String::from("START")]);
+        wad.rename_all_maps(&custom).unwrap();
+        let maps = wad
+            .maps()
+            .iter()
+            .map(|m| m.name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(maps, ["START", "MAP02"]);
+        assert_eq!(wad.lumps[11].name, "GL_START");
+
+        wad.rename_all_maps(&MapScheme::Episodic).unwrap();
+        let maps = wad
+            .maps()
+            .iter()
+            .map(|m| m.name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(maps, ["E1M1", "E1M2"]);
+    }
+}
@@ -0,0 +1,368 @@
+use std::fmt::Write;
+
+use crate::Game;
+
+/// The progression-related parts of one map's MAPINFO definition.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapInfoEntry {
+    /// The map's marker name, e.g. `MAP01`.
+    pub map: String,
+    /// The level name shown in game.
+    pub name: Option<String>,
+    /// The map the normal exit leads to.
+    pub next: Option<String>,
+    /// The map the secret exit leads to.
+    pub secret_next: Option<String>,
+    /// The music lump played in the map.
+    pub music: Option<String>,
+    /// The cluster the map belongs to. For Hexen, this is the map's hub.
+    pub cluster: Option<u32>,
+}
+
+/// Keywords which start a new top-level block in old-style MAPINFO. `cluster` isn't one, since
+/// it's also a map property there; see [`starts_cluster_block`].
+const BLOCK_KEYWORDS: &[&str] = &[
+    "map",
+    "defaultmap",
+    "adddefaultmap",
+    "gamedefaults",
+    "clusterdef",
+    "episode",
+    "clearepisodes",
+    "skill",
+    "clearskills",
+    "gameinfo",
+    "intermission",
+    "automap",
+    "include",
+];
+
+/// Whether the `cluster` at `tokens[i]` begins a new-style `cluster N { ... }` definition,
+/// rather than setting an old-style map's cluster.
+fn starts_cluster_block(tokens: &[String], i: usize) -> bool {
+    tokens[i].eq_ignore_ascii_case("cluster") && tokens.get(i + 2).is_some_and(|t| t == "{")
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                chars.find(|&c| c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = '\0';
+                chars.find(|&c| std::mem::replace(&mut prev, c) == '*' && c == '/');
+            }
+            ';' => {
+                // old-style comment
+                chars.find(|&c| c == '\n');
+            }
+            '"' => tokens.push(chars.by_ref().take_while(|&c| c != '"').collect()),
+            '{' | '}' | '=' | ',' => tokens.push(c.to_string()),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{}=,\";".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(word);
+            }
+        }
+    }
+    tokens
+}
+
+/// Reads the map definitions from a MAPINFO (or ZMAPINFO) lump, in either the old or the new
+/// brace-delimited syntax. Only the keys describing progression are kept.
+pub fn parse_mapinfo(text: &str) -> Vec<MapInfoEntry> {
+    let tokens = tokenize(text);
+    let mut entries = Vec::new();
+    let mut i = 0;
+    let mut depth = 0usize;
+    while i < tokens.len() {
+        let token = tokens[i].as_str();
+        i += 1;
+        match token {
+            "{" => depth += 1,
+            "}" => depth = depth.saturating_sub(1),
+            _ if depth == 0 && token.eq_ignore_ascii_case("map") && i < tokens.len() => {
+                let mut entry = MapInfoEntry {
+                    map: tokens[i].to_ascii_uppercase(),
+                    ..Default::default()
+                };
+                i += 1;
+                if tokens
+                    .get(i)
+                    .is_some_and(|t| t.eq_ignore_ascii_case("lookup"))
+                {
+                    i += 1;
+                }
+                if let Some(name) = tokens.get(i).filter(|t| *t != "{") {
+                    entry.name = Some(name.clone());
+                    i += 1;
+                }
+                let braced = tokens.get(i).is_some_and(|t| t == "{");
+                if braced {
+                    i += 1;
+                }
+                let mut inner_depth = 0usize;
+                while i < tokens.len() {
+                    let key = tokens[i].as_str();
+                    if braced {
+                        match key {
+                            "{" => inner_depth += 1,
+                            "}" if inner_depth == 0 => {
+                                i += 1;
+                                break;
+                            }
+                            "}" => inner_depth -= 1,
+                            _ => {}
+                        }
+                    } else if BLOCK_KEYWORDS.iter().any(|k| key.eq_ignore_ascii_case(k))
+                        || starts_cluster_block(&tokens, i)
+                    {
+                        break;
+                    }
+                    i += 1;
+                    let value = if tokens.get(i).is_some_and(|t| t == "=") {
+                        tokens.get(i + 1)
+                    } else if braced {
+                        None
+                    } else {
+                        tokens.get(i)
+                    };
+                    if key.eq_ignore_ascii_case("cluster") {
+                        entry.cluster = value.and_then(|v| v.parse().ok());
+                        continue;
+                    }
+                    let slot = if key.eq_ignore_ascii_case("next") {
+                        &mut entry.next
+                    } else if key.eq_ignore_ascii_case("secretnext") {
+                        &mut entry.secret_next
+                    } else if key.eq_ignore_ascii_case("music") {
+                        &mut entry.music
+                    } else {
+                        continue;
+                    };
+                    if let Some(value) = value {
+                        *slot = Some(value.to_ascii_uppercase());
+                    }
+                }
+                entries.push(entry);
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// Writes map definitions out in the new MAPINFO syntax. For games with episodes, the maps are
+/// grouped into an episode, and a cluster, for each episode their slots fall in. The first map
+/// of each episode is where starting it from the menu leads. For Hexen, each map's cluster is
+/// written out as a hub, so that the maps in it keep their state between visits.
+pub fn build_mapinfo(entries: &[MapInfoEntry], game: Game) -> String {
+    let episode_of = |entry: &MapInfoEntry| game.episode(game.parse_slot(&entry.map)?);
+    let cluster_of = |entry: &MapInfoEntry| match game {
+        Game::Hexen => entry.cluster,
+        _ => episode_of(entry).and_then(|e| u32::try_from(e).ok()),
+    };
+    let mut text = String::new();
+    let mut episodes = Vec::new();
+    for entry in entries {
+        if let Some(episode) = episode_of(entry) {
+            if !episodes.iter().any(|&(e, _)| e == episode) {
+                episodes.push((episode, &entry.map));
+            }
+        }
+    }
+    if !episodes.is_empty() {
+        writeln!(text, "clearepisodes").unwrap();
+        writeln!(text).unwrap();
+    }
+    for (episode, first) in episodes {
+        writeln!(text, "episode {first}").unwrap();
+        writeln!(text, "{{").unwrap();
+        writeln!(text, "    name = \"Episode {episode}\"").unwrap();
+        writeln!(text, "}}").unwrap();
+        writeln!(text).unwrap();
+    }
+    if game == Game::Hexen {
+        let mut hubs = entries.iter().filter_map(|e| e.cluster).collect::<Vec<_>>();
+        hubs.sort_unstable();
+        hubs.dedup();
+        for hub in hubs {
+            writeln!(text, "cluster {hub}").unwrap();
+            writeln!(text, "{{").unwrap();
+            writeln!(text, "    hub").unwrap();
+            writeln!(text, "}}").unwrap();
+            writeln!(text).unwrap();
+        }
+    }
+    for entry in entries {
+        let name = entry.name.as_deref().unwrap_or(&entry.map);
+        writeln!(text, "map {} \"{}\"", entry.map, name.replace('"', "'")).unwrap();
+        writeln!(text, "{{").unwrap();
+        if let Some(next) = &entry.next {
+            writeln!(text, "    next = \"{next}\"").unwrap();
+        }
+        if let Some(secret_next) = &entry.secret_next {
+            writeln!(text, "    secretnext = \"{secret_next}\"").unwrap();
+        }
+        if let Some(music) = &entry.music {
+            writeln!(text, "    music = \"{music}\"").unwrap();
+        }
+        if let Some(cluster) = cluster_of(entry) {
+            writeln!(text, "    cluster = {cluster}").unwrap();
+        }
+        writeln!(text, "}}").unwrap();
+        writeln!(text).unwrap();
+    }
+    text
+}
+
+/// Draws the progression between maps as a Graphviz DOT graph, with an edge along each normal
+/// exit and a dashed one along each secret exit. Each map is labeled with its level name, if it
+/// has one.
+pub fn progression_dot(entries: &[MapInfoEntry]) -> String {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let quote = |s: &str| format!("\"{}\"", escape(s));
+    let mut text = String::from("digraph progression {\n");
+    for entry in entries {
+        let label = match &entry.name {
+            Some(name) if *name != entry.map => {
+                format!("{}\\n{}", escape(&entry.map), escape(name))
+            }
+            _ => escape(&entry.map),
+        };
+        writeln!(text, "    {} [label=\"{label}\"];", quote(&entry.map)).unwrap();
+    }
+    for entry in entries {
+        if let Some(next) = &entry.next {
+            writeln!(text, "    {} -> {};", quote(&entry.map), quote(next)).unwrap();
+        }
+        if let Some(secret_next) = &entry.secret_next {
+            writeln!(
+                text,
+                "    {} -> {} [style=dashed];",
+                quote(&entry.map),
+                quote(secret_next)
+            )
+            .unwrap();
+        }
+    }
+    text.push_str("}\n");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(map: &str, name: &str, next: &str, secret_next: Option<&str>) -> MapInfoEntry {
+        MapInfoEntry {
+            map: map.to_string(),
+            name: Some(name.to_string()),
+            next: Some(next.to_string()),
+            secret_next: secret_next.map(String::from),
+            music: None,
+            cluster: None,
+        }
+    }
+
+    #[test]
+    fn old_style_cluster_is_a_map_property() {
+        let text = "map map01 \"Entryway\"\ncluster 1\nmusic d_runnin\nnext map02\n\n\
+                    clusterdef 1\nexittext \"Done\"\n\nmap MAP02 \"Underhalls\"\nsky1 SKY1 0\n";
+        let entries = parse_mapinfo(text);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].music.as_deref(), Some("D_RUNNIN"));
+        assert_eq!(entries[0].next.as_deref(), Some("MAP02"));
+        assert_eq!(entries[1].name.as_deref(), Some("Underhalls"));
+    }
+
+    #[test]
+    fn new_style_maps_and_clusters() {
+        let text = "map MAP01 lookup \"HUSTR_1\" {\n  next = \"MAP02\"\n  \
+                    secretnext = \"MAP31\" // a comment\n  cluster = 1\n  music = \"D_RUNNIN\"\n}\n\
+                    cluster 1 { exittext = \"next = MAP99\" }\nmap MAP02 \"Two\" { next = \"EndGame1\" }\n";
+        let entries = parse_mapinfo(text);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name.as_deref(), Some("HUSTR_1"));
+        assert_eq!(entries[0].secret_next.as_deref(), Some("MAP31"));
+        assert_eq!(entries[0].music.as_deref(), Some("D_RUNNIN"));
+        assert_eq!(entries[1].next.as_deref(), Some("ENDGAME1"));
+    }
+
+    #[test]
+    fn built_mapinfo_parses_back() {
+        let entries = [
+            entry("MAP01", "One", "MAP02", Some("MAP31")),
+            entry("MAP02", "Two \"quoted\"", "EndGame1", None),
+            entry("MAP31", "Secret", "MAP02", None),
+        ];
+        let parsed = parse_mapinfo(&build_mapinfo(&entries, Game::Doom2));
+        assert_eq!(parsed[0], entries[0]);
+        assert_eq!(parsed[1].name.as_deref(), Some("Two 'quoted'"));
+        assert_eq!(parsed[1].next.as_deref(), Some("ENDGAME1"));
+        assert_eq!(parsed[2], entries[2]);
+    }
+
+    #[test]
+    fn progression_dot_draws_both_exits() {
+        let dot = progression_dot(&[entry("MAP01", "One", "MAP02", Some("MAP31"))]);
+        assert!(dot.contains("\"MAP01\" [label=\"MAP01\\nOne\"];"));
+        assert!(dot.contains("\"MAP01\" -> \"MAP02\";"));
+        assert!(dot.contains("\"MAP01\" -> \"MAP31\" [style=dashed];"));
+    }
+
+    #[test]
+    fn heretic_maps_are_grouped_by_episode() {
+        let entries = [
+            entry("E1M1", "One", "E1M2", Some("E1M9")),
+            entry("E1M2", "Two", "E2M1", None),
+            entry("E2M1", "Three", "EndGame", None),
+        ];
+        let text = build_mapinfo(&entries, Game::Heretic);
+        assert!(text.starts_with("clearepisodes\n"));
+        assert!(text.contains("episode E1M1\n{\n    name = \"Episode 1\"\n}"));
+        assert!(text.contains("episode E2M1\n{\n    name = \"Episode 2\"\n}"));
+        assert!(!text.contains("episode E1M2"));
+        let parsed = parse_mapinfo(&text);
+        let clusters = parsed.iter().map(|e| e.cluster).collect::<Vec<_>>();
+        assert_eq!(clusters, [Some(1), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn doom2_maps_have_no_episodes_or_clusters() {
+        let text = build_mapinfo(&[entry("MAP01", "One", "MAP02", None)], Game::Doom2);
+        assert!(!text.contains("episode"));
+        assert!(!text.contains("cluster"));
+    }
+
+    #[test]
+    fn hexen_clusters_are_hubs() {
+        let mut entries = [
+            entry("MAP01", "One", "MAP02", None),
+            entry("MAP02", "Two", "MAP03", None),
+            entry("MAP03", "Three", "ENDTITLE", None),
+        ];
+        entries[0].cluster = Some(1);
+        entries[1].cluster = Some(1);
+        entries[2].cluster = Some(2);
+        let text = build_mapinfo(&entries, Game::Hexen);
+        assert!(!text.contains("episode"));
+        assert!(text.contains("cluster 1\n{\n    hub\n}"));
+        assert!(text.contains("cluster 2\n{\n    hub\n}"));
+        assert_eq!(text.matches("hub").count(), 2);
+        let parsed = parse_mapinfo(&text);
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed, entries);
+    }
+}
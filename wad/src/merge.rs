@@ -0,0 +1,2405 @@
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use linked_hash_map::LinkedHashMap;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::build_mapinfo;
+use crate::build_pnames;
+use crate::build_textures;
+use crate::cap_things;
+use crate::definitions::definition_names;
+use crate::language_keys;
+use crate::merge_dmxgus;
+use crate::namespace::is_marker;
+use crate::namespace::namespace_blocks;
+use crate::parse_dehacked_things;
+use crate::parse_pnames;
+use crate::parse_sndinfo;
+use crate::parse_textures;
+use crate::validate_name;
+use crate::DehackedThing;
+use crate::Game;
+use crate::LimitViolation;
+use crate::Lump;
+use crate::MapFormat;
+use crate::MapInfoEntry;
+use crate::MapSlice;
+use crate::Namespace;
+use crate::SecretDetector;
+use crate::Texture;
+use crate::Thing;
+use crate::ThingCap;
+use crate::Wad;
+use crate::WadError;
+use crate::WadResult;
+
+/// Text lumps whose contents are joined together when several WADs provide them, rather than the
+/// last one winning.
+const CONCATENATED_LUMPS: &[&str] = &["SNDINFO", "MENUDEF", "SBARINFO", "GLDEFS", "LANGUAGE"];
+
+/// GUS instrument configs, which are merged by instrument number.
+const GUS_CONFIG_LUMPS: &[&str] = &["DMXGUS", "DMXGUSC"];
+
+/// Concatenated lumps whose later definitions silently override earlier same-named ones.
+pub const DEFINITION_LUMPS: &[&str] = &["MENUDEF", "SBARINFO", "GLDEFS"];
+
+/// The default for [`MergeOptions::level_name_format`].
+pub const DEFAULT_LEVEL_NAME_FORMAT: &str = "{slot}: was {input}/{map}";
+
+/// Lumps defining wall textures, which refer to patches through PNAMES.
+const TEXTURE_LUMPS: &[&str] = &["TEXTURE1", "TEXTURE2"];
+
+/// How the maps of the merged WADs are ordered in the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapOrder {
+    /// Each WAD's maps in turn, in their original order.
+    #[default]
+    Concat,
+    /// Full randomization.
+    Chaos,
+    /// Each map is kept close to its original slot. (MAP01, MAP01, MAP02, ...)
+    Slog,
+    /// Each WAD's maps stay together in their original order, but the WADs come in a random
+    /// order.
+    Shuffle,
+    /// Easiest maps first, going by [`MapSlice::difficulty_score`]. Maps which score the same
+    /// keep their input order.
+    ByDifficulty,
+}
+
+/// How maps keep their music when they're renumbered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MusicStrategy {
+    /// Leave music lumps alone, so each map plays whatever its new slot plays.
+    #[default]
+    Keep,
+    /// Rename each map's music lump to the one the target game plays in the map's new slot.
+    /// Works in vanilla, but only for slots the game has music for.
+    Rename,
+    /// Give each map's music lump a name of its own, like `D_MAP07`, and point the map at it
+    /// from a generated MAPINFO.
+    Mapinfo,
+}
+
+/// How namespaced resources (flats, sprites, ...) from several WADs are combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NamespaceStrategy {
+    /// One block per namespace, holding every WAD's lumps. Later WADs replace same-named lumps.
+    #[default]
+    Unify,
+    /// One block per namespace per WAD, in input order. Ports resolve duplicates by taking the
+    /// last one, so this keeps conflicting resources apart without changing which one wins.
+    Isolate,
+}
+
+/// Gives a lump's new name, or `None` to keep the name it has.
+pub type RenameFn = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// Renames the lumps of one input WAD during a merge. Maps and namespace markers are left alone.
+#[derive(Clone)]
+pub struct RenameHook {
+    /// The index of the input WAD the hook applies to.
+    pub source: usize,
+    pub rename: Arc<RenameFn>,
+}
+
+impl RenameHook {
+    pub fn new<F>(source: usize, rename: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            source,
+            rename: Arc::new(rename),
+        }
+    }
+}
+
+impl fmt::Debug for RenameHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RenameHook")
+            .field("source", &self.source)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Two inputs' lumps sharing a name, only one of which can go in the output.
+#[derive(Debug, Clone, Copy)]
+pub struct LumpCollision<'a> {
+    /// The name the lumps are merged under.
+    pub name: &'a str,
+    /// The index of the input the lump kept so far came from.
+    pub existing_source: usize,
+    pub existing: &'a Lump,
+    /// The index of the input whose lump would replace it.
+    pub incoming_source: usize,
+    pub incoming: &'a Lump,
+}
+
+/// Which lump of a [`LumpCollision`] goes in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionChoice {
+    KeepExisting,
+    /// What happens without a [`CollisionResolver`]: the later input wins.
+    TakeIncoming,
+}
+
+/// Picks the lump to keep when two inputs' lumps collide.
+pub type CollisionFn = dyn Fn(&LumpCollision) -> CollisionChoice + Send + Sync;
+
+/// Decides each collision between inputs' differing lumps during a merge, in place of the later
+/// input winning. Lumps whose contents are joined together, like SNDINFO, never collide.
+#[derive(Clone)]
+pub struct CollisionResolver {
+    pub resolve: Arc<CollisionFn>,
+}
+
+impl CollisionResolver {
+    pub fn new<F>(resolve: F) -> Self
+    where
+        F: Fn(&LumpCollision) -> CollisionChoice + Send + Sync + 'static,
+    {
+        Self {
+            resolve: Arc::new(resolve),
+        }
+    }
+}
+
+impl fmt::Debug for CollisionResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CollisionResolver").finish_non_exhaustive()
+    }
+}
+
+/// Identifies one map of one input WAD.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceMapId {
+    /// The index of the input WAD.
+    pub source: usize,
+    /// The map's marker name in that WAD, e.g. `MAP03`.
+    pub map: String,
+}
+
+impl SourceMapId {
+    pub fn new(source: usize, map: impl Into<String>) -> Self {
+        Self {
+            source,
+            map: map.into(),
+        }
+    }
+}
+
+/// Settings for [`Wad::merge`].
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    pub order: MapOrder,
+    /// If set, merging fails rather than producing a WAD larger than this many bytes.
+    pub max_output_size: Option<u64>,
+    pub namespace_strategy: NamespaceStrategy,
+    /// The namespaces to recognize. Blocks of any other namespace are treated like loose lumps.
+    pub namespaces: Vec<Namespace>,
+    /// The definition lumps to scan for definitions which override one another. Defaults to
+    /// [`DEFINITION_LUMPS`].
+    pub duplicate_definition_checks: Vec<String>,
+    /// Decide which maps are secret levels. Secret maps are placed in the secret slots (`MAP31`,
+    /// `MAP32`, then after the last regular map) instead of the main progression, and a MAPINFO
+    /// is generated to link them up. With no detectors, every map is a regular map.
+    pub secret_detectors: Vec<Arc<dyn SecretDetector>>,
+    /// Replace the inputs' MAPINFO with one describing the merged progression.
+    pub generate_mapinfo: bool,
+    /// Regenerate every binary map's BLOCKMAP from its geometry, adding it where missing.
+    pub rebuild_blockmap: bool,
+    /// Replace every binary map's REJECT with an all-zero one, adding it where missing.
+    pub rebuild_reject: bool,
+    /// Seeds the random orderings, so the same seed always gives the same output. Without one,
+    /// the order is different every time.
+    pub seed: Option<u64>,
+    /// Rename input lumps before anything else happens to them. When several hooks apply to the
+    /// same input, each sees the name the previous one gave.
+    pub rename_hooks: Vec<RenameHook>,
+    /// Maps which must end up in a particular output slot (`MAP07`, ...), whatever the order.
+    /// The other maps fill the remaining slots around them.
+    pub pinned_slots: HashMap<SourceMapId, String>,
+    /// The game the output is for, which decides how maps are numbered (`MAP01` or `E1M1`),
+    /// which slots secret maps go in, and how a generated MAPINFO ends.
+    pub target_game: Game,
+    /// Keep maps playing their own music after renumbering them.
+    pub music: MusicStrategy,
+    /// Input lumps to leave out of the output, such as lumps the target port doesn't read.
+    pub excluded_lumps: Vec<String>,
+    /// Leave out the inputs' demos (`DEMO1`, ...), which desync once their maps are moved.
+    pub strip_demos: bool,
+    /// Warn about maps with more records than the vanilla engine supports.
+    pub warn_vanilla_limits: bool,
+    /// Decides which of two differing same-named lumps to keep. Without one, the later input's
+    /// lump wins.
+    pub collision_resolver: Option<CollisionResolver>,
+    /// Leave out every map, producing a WAD of the inputs' merged resources to be loaded
+    /// alongside map packs. Pinned slots and MAPINFO generation are ignored.
+    pub resources_only: bool,
+    /// Drop monsters, ammo and health beyond these caps from every Doom-format map. Hexen and
+    /// UDMF maps are left alone. Experimental.
+    pub thing_cap: Option<ThingCap>,
+    /// How a generated MAPINFO names each level, so players can tell where a renumbered map came
+    /// from. `{slot}` is replaced with the map's new slot, `{map}` with its original marker name,
+    /// `{input}` with its input's entry in `input_names`, and `{name}` with the name its input's
+    /// MAPINFO gives it (or the marker name). Without a format, levels are named after their
+    /// slot. Defaults to [`DEFAULT_LEVEL_NAME_FORMAT`].
+    pub level_name_format: Option<String>,
+    /// What to call each input in level names, such as its file name. Inputs without an entry
+    /// are called `input N`, counting from 0.
+    pub input_names: Vec<String>,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            order: MapOrder::default(),
+            max_output_size: None,
+            namespace_strategy: NamespaceStrategy::default(),
+            namespaces: Namespace::ALL.to_vec(),
+            duplicate_definition_checks: DEFINITION_LUMPS.iter().map(|s| s.to_string()).collect(),
+            secret_detectors: Vec::new(),
+            generate_mapinfo: false,
+            rebuild_blockmap: false,
+            rebuild_reject: false,
+            seed: None,
+            rename_hooks: Vec::new(),
+            pinned_slots: HashMap::new(),
+            target_game: Game::default(),
+            music: MusicStrategy::default(),
+            excluded_lumps: Vec::new(),
+            strip_demos: false,
+            warn_vanilla_limits: false,
+            collision_resolver: None,
+            resources_only: false,
+            thing_cap: None,
+            level_name_format: Some(DEFAULT_LEVEL_NAME_FORMAT.to_string()),
+            input_names: Vec::new(),
+        }
+    }
+}
+
+/// Something questionable noticed while merging.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeWarning {
+    /// A SNDINFO alias refers to a lump which isn't in the output.
+    DanglingSound { sound: String, lump: String },
+    /// A definition lump defines the same thing more than once, so only the last one counts.
+    DuplicateDefinition { lump: String, definition: String },
+    /// The merged PNAMES lists a patch which isn't in the output. Only checked when PNAMES is
+    /// rebuilt, which happens when two or more inputs have texture lumps.
+    MissingPatch { patch: String },
+    /// LANGUAGE sets the same string more than once for a language, so only the last one
+    /// counts.
+    DuplicateString { language: String, key: String },
+    /// A map places a thing type which the merged DEHACKED defines differently from the map's
+    /// own input, so it may spawn something else.
+    DehackedThingChanged {
+        map: String,
+        input: usize,
+        kind: u16,
+    },
+    /// A map has more records than the vanilla engine supports.
+    VanillaLimit {
+        map: String,
+        violation: LimitViolation,
+    },
+    /// Two inputs have differing lumps with the same name, and input `source`'s replaced input
+    /// `replaced`'s. Lumps a [`CollisionResolver`] keeps aren't reported.
+    Collision {
+        name: String,
+        source: usize,
+        replaced: usize,
+    },
+}
+
+impl fmt::Display for MergeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DanglingSound { sound, lump } => {
+                write!(f, "sound {sound} refers to missing lump {lump}")
+            }
+            Self::DuplicateDefinition { lump, definition } => {
+                write!(f, "{lump} defines `{definition}` more than once")
+            }
+            Self::MissingPatch { patch } => write!(f, "PNAMES lists missing patch {patch}"),
+            Self::DuplicateString { language, key } => {
+                write!(f, "LANGUAGE sets {key} for [{language}] more than once")
+            }
+            Self::DehackedThingChanged { map, input, kind } => write!(
+                f,
+                "{map} (from input {input}) places thing type {kind}, which the merged DEHACKED \
+                 defines differently"
+            ),
+            Self::VanillaLimit { map, violation } => write!(
+                f,
+                "{map} has {} {}, more than vanilla's limit of {}",
+                violation.count, violation.lump, violation.limit
+            ),
+            Self::Collision {
+                name,
+                source,
+                replaced,
+            } => write!(
+                f,
+                "{name} from input {source} replaces the one from input {replaced}"
+            ),
+        }
+    }
+}
+
+/// The music lump a map plays in its source: the one its MAPINFO names, or else the one the
+/// original game plays in its slot.
+fn source_music(infos: &[MapInfoEntry], map: &str) -> Option<String> {
+    infos
+        .iter()
+        .rev()
+        .find(|entry| entry.map == map)
+        .and_then(|entry| entry.music.clone())
+        .or_else(|| {
+            [Game::Doom2, Game::Doom]
+                .into_iter()
+                .find_map(|game| game.vanilla_music(game.parse_slot(map)?))
+        })
+}
+
+/// What happened during a merge, besides the output itself. Every warning is also logged as it
+/// happens.
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+    pub warnings: Vec<MergeWarning>,
+}
+
+impl MergeReport {
+    fn warn(&mut self, warning: MergeWarning) {
+        log::warn!("{warning}");
+        self.warnings.push(warning);
+    }
+}
+
+/// A map's place in the merged output.
+#[derive(Debug, Clone)]
+struct Placement<'wad> {
+    /// The index of the WAD the map comes from.
+    source: usize,
+    map: MapSlice<'wad>,
+    slot: usize,
+    secret: bool,
+}
+
+/// Takes the pinned maps out of `maps`, giving each its slot. Pins are dealt with in input
+/// order, so the same maps are reported when several are pinned to a slot, every time.
+fn take_pinned<'wad>(
+    maps: &mut Vec<(usize, MapSlice<'wad>)>,
+    pins: &HashMap<SourceMapId, String>,
+    secrets: &[HashSet<String>],
+    game: Game,
+) -> WadResult<Vec<Placement<'wad>>> {
+    let find = |maps: &[(usize, MapSlice)], id: &SourceMapId| {
+        maps.iter().position(|(source, map)| {
+            *source == id.source && map.name.eq_ignore_ascii_case(&id.map)
+        })
+    };
+    let mut pins = pins.iter().collect::<Vec<_>>();
+    pins.sort_by_cached_key(|(id, _)| {
+        let start = find(maps, id).map(|i| maps[i].1.range.start);
+        (id.source, start, id.map.to_ascii_uppercase())
+    });
+    let mut taken = HashMap::new();
+    let mut placements = Vec::new();
+    for (id, slot_name) in pins {
+        let slot = game
+            .parse_slot(slot_name)
+            .ok_or_else(|| WadError::InvalidSlot(slot_name.clone()))?;
+        if let Some(other) = taken.insert(slot, id) {
+            return Err(WadError::PinnedSlotConflict {
+                slot: slot_name.to_ascii_uppercase(),
+                first: other.map.clone(),
+                second: id.map.clone(),
+            });
+        }
+        let i = find(maps, id).ok_or_else(|| WadError::PinnedMapMissing {
+            input: id.source,
+            map: id.map.clone(),
+        })?;
+        let (source, map) = maps.remove(i);
+        placements.push(Placement {
+            source,
+            secret: secrets[source].contains(map.name),
+            map,
+            slot,
+        });
+    }
+    Ok(placements)
+}
+
+/// Numbers the maps in order, sorted by slot. Pinned maps go in their own slots, and the rest
+/// fill the slots around them. Secret maps are pulled out of the sequence and put in the secret
+/// slots, with any extras after the last regular map.
+fn place_maps<'wad>(
+    mut maps: Vec<(usize, MapSlice<'wad>)>,
+    secrets: &[HashSet<String>],
+    pins: &HashMap<SourceMapId, String>,
+    game: Game,
+) -> WadResult<Vec<Placement<'wad>>> {
+    let mut placements = take_pinned(&mut maps, pins, secrets, game)?;
+    let pinned = placements.iter().map(|p| p.slot).collect::<HashSet<_>>();
+    let (secret_maps, regular_maps): (Vec<_>, Vec<_>) = maps
+        .into_iter()
+        .partition(|(source, map)| secrets[*source].contains(map.name));
+    let reserve_secret_slots = !secret_maps.is_empty();
+    let mut slots = (1..)
+        .filter(|slot| !pinned.contains(slot))
+        .filter(|slot| !reserve_secret_slots || !game.secret_slots().contains(slot));
+    for (source, map) in regular_maps {
+        placements.push(Placement {
+            source,
+            map,
+            slot: slots.next().unwrap(),
+            secret: false,
+        });
+    }
+    let mut slots = game
+        .secret_slots()
+        .iter()
+        .copied()
+        .filter(|slot| !pinned.contains(slot))
+        .chain(slots);
+    for (source, map) in secret_maps {
+        placements.push(Placement {
+            source,
+            map,
+            slot: slots.next().unwrap(),
+            secret: true,
+        });
+    }
+    placements.sort_by_key(|p| p.slot);
+    Ok(placements)
+}
+
+/// Finds the map whose secret exit should lead to a secret map: the one the source's MAPINFO
+/// says leads there, or else the last map before it in the source with a secret exit, or else
+/// simply the last map before it in the source.
+fn secret_entry(
+    placements: &[Placement],
+    secret: usize,
+    source_infos: &[MapInfoEntry],
+) -> Option<usize> {
+    let target = &placements[secret];
+    let candidates = || {
+        placements
+            .iter()
+            .enumerate()
+            .filter(move |(i, p)| *i != secret && p.source == target.source)
+    };
+    let preceding_regular = || {
+        candidates()
+            .filter(|(_, p)| !p.secret && p.map.range.start < target.map.range.start)
+            .max_by_key(|(_, p)| p.map.range.start)
+    };
+    let by_mapinfo = candidates().find(|(_, p)| {
+        source_infos
+            .iter()
+            .any(|e| e.map == p.map.name && e.secret_next.as_deref() == Some(target.map.name))
+    });
+    by_mapinfo
+        .or_else(|| {
+            candidates()
+                .filter(|(_, p)| !p.secret && p.map.range.start < target.map.range.start)
+                .filter(|(_, p)| p.map.has_secret_exit())
+                .max_by_key(|(_, p)| p.map.range.start)
+        })
+        .or_else(preceding_regular)
+        .map(|(i, _)| i)
+}
+
+/// Links the placed maps together: regular maps in slot order, and secret maps off the maps that
+/// led to them in their source.
+fn build_progression(placements: &[Placement], wads: &[Wad], game: Game) -> Vec<MapInfoEntry> {
+    let source_infos = wads.iter().map(Wad::mapinfo).collect::<Vec<_>>();
+    let mut entries = placements
+        .iter()
+        .map(|p| MapInfoEntry {
+            map: game.slot_name(p.slot),
+            // each input's maps make up one hub
+            cluster: (game == Game::Hexen).then(|| p.source as u32 + 1),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+    let regular = (0..placements.len())
+        .filter(|&i| !placements[i].secret)
+        .collect::<Vec<_>>();
+    for (k, &i) in regular.iter().enumerate() {
+        entries[i].next = Some(match regular.get(k + 1) {
+            Some(&next) => game.slot_name(placements[next].slot),
+            None => String::from(game.ending()),
+        });
+    }
+    let mut entry_of = vec![None; placements.len()];
+    for i in (0..placements.len()).filter(|&i| placements[i].secret) {
+        let infos = &source_infos[placements[i].source];
+        entry_of[i] = secret_entry(placements, i, infos);
+        if let Some(entry) = entry_of[i] {
+            entries[entry]
+                .secret_next
+                .get_or_insert_with(|| game.slot_name(placements[i].slot));
+        }
+    }
+    // a secret map carries on to wherever the regular map that led to it would have gone
+    for i in (0..placements.len()).filter(|&i| placements[i].secret) {
+        let mut root = entry_of[i];
+        for _ in 0..placements.len() {
+            match root {
+                Some(r) if placements[r].secret => root = entry_of[r],
+                _ => break,
+            }
+        }
+        entries[i].next = root
+            .filter(|&r| !placements[r].secret)
+            .and_then(|r| entries[r].next.clone());
+    }
+    entries
+}
+
+/// One lump of the merged output.
+#[derive(Debug)]
+enum Planned {
+    /// A lump taken from one of the inputs, possibly under a new name.
+    Input {
+        source: usize,
+        index: usize,
+        rename: Option<String>,
+    },
+    /// A lump made up during the merge.
+    New(Lump),
+}
+
+impl Planned {
+    fn new(name: &str) -> Self {
+        Self::New(Lump {
+            name: name.to_string(),
+            data: vec![],
+        })
+    }
+
+    /// The name the lump will have in the output.
+    fn name<'a>(&'a self, wads: &'a [Wad]) -> &'a str {
+        match self {
+            Self::Input {
+                rename: Some(name), ..
+            } => name,
+            Self::Input { source, index, .. } => &wads[*source].lumps[*index].name,
+            Self::New(lump) => &lump.name,
+        }
+    }
+
+    /// The lump's contents.
+    fn data<'a>(&'a self, wads: &'a [Wad]) -> &'a [u8] {
+        match self {
+            Self::Input { source, index, .. } => &wads[*source].lumps[*index].data,
+            Self::New(lump) => &lump.data,
+        }
+    }
+
+    /// Gets the lump for modification, copying it out of its input first if need be.
+    fn to_mut<'a>(&'a mut self, wads: &[Wad]) -> &'a mut Lump {
+        if let Self::Input {
+            source,
+            index,
+            rename,
+        } = self
+        {
+            let mut lump = wads[*source].lumps[*index].clone();
+            if let Some(name) = rename.take() {
+                lump.name = name;
+            }
+            *self = Self::New(lump);
+        }
+        match self {
+            Self::New(lump) => lump,
+            Self::Input { .. } => unreachable!(),
+        }
+    }
+}
+
+/// Finds the SNDINFO aliases whose lump won't be in the output, like
+/// [`Wad::dangling_sound_refs`] does once it's built.
+fn dangling_sounds(plan: &[Planned], wads: &[Wad]) -> Vec<MergeWarning> {
+    let names = plan
+        .iter()
+        .map(|planned| planned.name(wads).to_ascii_uppercase())
+        .collect::<HashSet<_>>();
+    plan.iter()
+        .filter(|planned| planned.name(wads).eq_ignore_ascii_case("SNDINFO"))
+        .flat_map(|planned| parse_sndinfo(&String::from_utf8_lossy(planned.data(wads))))
+        .filter(|alias| !names.contains(&alias.lump.to_ascii_uppercase()))
+        .map(|alias| MergeWarning::DanglingSound {
+            sound: alias.name,
+            lump: alias.lump,
+        })
+        .collect()
+}
+
+/// Finds the Doom-format maps which place a thing type the merged DEHACKED defines differently
+/// from the map's own input. Each type is reported once per map.
+fn dehacked_conflicts(
+    placements: &[Placement],
+    wads: &[Wad],
+    merged: Option<&[u8]>,
+    game: Game,
+) -> Vec<MergeWarning> {
+    let things = |data: Option<&[u8]>| {
+        data.map(|data| parse_dehacked_things(&String::from_utf8_lossy(data)))
+            .unwrap_or_default()
+    };
+    let merged = things(merged);
+    let mut warnings = Vec::new();
+    for p in placements
+        .iter()
+        .filter(|p| p.map.format == MapFormat::Doom)
+    {
+        let own = wads[p.source]
+            .lumps
+            .iter()
+            .rfind(|l| l.name.eq_ignore_ascii_case("DEHACKED"));
+        let own = things(own.map(|l| l.data.as_slice()));
+        let mut changed = BTreeSet::new();
+        for number in own.keys().chain(merged.keys()) {
+            let (a, b) = (own.get(number), merged.get(number));
+            if a != b {
+                changed.extend(a.and_then(DehackedThing::editor_number));
+                changed.extend(b.and_then(DehackedThing::editor_number));
+            }
+        }
+        if changed.is_empty() {
+            continue;
+        }
+        let used = p
+            .map
+            .lump("THINGS")
+            .map(|l| Thing::read_all(&l.data))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|thing| thing.kind)
+            .collect::<BTreeSet<_>>();
+        for &kind in changed.intersection(&used) {
+            warnings.push(MergeWarning::DehackedThingChanged {
+                map: game.slot_name(p.slot),
+                input: p.source,
+                kind,
+            });
+        }
+    }
+    warnings
+}
+
+/// Replaces each `{key}` in `format` with its value. Unknown keys are left as they are.
+fn fill_in(format: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::new();
+    let mut rest = format;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let value = rest.find('}').and_then(|close| {
+            let (_, value) = values.iter().find(|(key, _)| *key == &rest[1..close])?;
+            Some((value, close))
+        });
+        match value {
+            Some((value, close)) => {
+                out.push_str(value);
+                rest = &rest[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Whether a lump is a demo, one of `DEMO1`, `DEMO2` and so on.
+fn is_demo(name: &str) -> bool {
+    name.strip_prefix("DEMO")
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Runs a source's rename hooks over one of its lump names.
+fn rename<'wad>(opts: &MergeOptions, source: usize, name: &'wad str) -> WadResult<Cow<'wad, str>> {
+    let mut name = Cow::Borrowed(name);
+    for hook in opts
+        .rename_hooks
+        .iter()
+        .filter(|hook| hook.source == source)
+    {
+        if let Some(new_name) = (hook.rename)(&name) {
+            validate_name(&new_name)?;
+            name = Cow::Owned(new_name);
+        }
+    }
+    Ok(name)
+}
+
+/// Whether input `incoming`'s lump `name` replaces the one kept so far from `existing`, each
+/// given as an input and a lump index. Identical lumps are left to the later input.
+fn replaces(
+    opts: &MergeOptions,
+    wads: &[Wad],
+    name: &str,
+    existing: (usize, usize),
+    incoming: (usize, usize),
+) -> bool {
+    let existing_lump = &wads[existing.0].lumps[existing.1];
+    let incoming_lump = &wads[incoming.0].lumps[incoming.1];
+    let Some(resolver) = &opts.collision_resolver else {
+        return true;
+    };
+    if existing_lump.data == incoming_lump.data {
+        return true;
+    }
+    let choice = (resolver.resolve)(&LumpCollision {
+        name,
+        existing_source: existing.0,
+        existing: existing_lump,
+        incoming_source: incoming.0,
+        incoming: incoming_lump,
+    });
+    choice == CollisionChoice::TakeIncoming
+}
+
+/// Whether `name` is one of `names`, ignoring case like the engine does.
+fn is_one_of(name: &str, names: &[&str]) -> bool {
+    names.iter().any(|n| n.eq_ignore_ascii_case(name))
+}
+
+fn add_resource<'wad>(
+    resources: &mut LinkedHashMap<Cow<'wad, str>, Planned>,
+    report: &mut MergeReport,
+    opts: &MergeOptions,
+    wads: &'wad [Wad],
+    source: usize,
+    index: usize,
+    name: Cow<'wad, str>,
+) {
+    let lump = &wads[source].lumps[index];
+    if is_one_of(&name, CONCATENATED_LUMPS) {
+        if let Some(existing) = resources.get_mut(name.as_ref()) {
+            let data = &mut existing.to_mut(wads).data;
+            if !data.ends_with(b"\n") {
+                data.push(b'\n');
+            }
+            data.extend_from_slice(&lump.data);
+            return;
+        }
+    }
+    if is_one_of(&name, GUS_CONFIG_LUMPS) {
+        if let Some(existing) = resources.get_mut(name.as_ref()) {
+            let existing = existing.to_mut(wads);
+            existing.data = merge_dmxgus(
+                &String::from_utf8_lossy(&existing.data),
+                &String::from_utf8_lossy(&lump.data),
+            )
+            .into_bytes();
+            return;
+        }
+    }
+    if let Some(&Planned::Input {
+        source: old,
+        index: i,
+        ..
+    }) = resources.get(name.as_ref())
+    {
+        if !replaces(opts, wads, &name, (old, i), (source, index)) {
+            log::info!("keeping {name} from input {old} over the one from input {source}");
+            return;
+        }
+    }
+    let rename = match &name {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(name) => Some(name.clone()),
+    };
+    let replaced = resources.insert(
+        name.clone(),
+        Planned::Input {
+            source,
+            index,
+            rename,
+        },
+    );
+    if let Some(Planned::Input {
+        source: old,
+        index: i,
+        ..
+    }) = replaced
+    {
+        if wads[old].lumps[i].data != lump.data {
+            report.warn(MergeWarning::Collision {
+                name: name.to_string(),
+                source,
+                replaced: old,
+            });
+        }
+    }
+}
+
+/// One input's texture definitions.
+struct TextureInput<'wad> {
+    pnames: Option<&'wad Lump>,
+    /// The texture lumps, each with the name it's merged under.
+    textures: Vec<(&'wad Lump, Cow<'wad, str>)>,
+}
+
+/// Texture definitions from several WADs, combined onto one PNAMES.
+struct MergedTextures<'wad> {
+    pnames: Vec<String>,
+    /// The contents of each texture lump.
+    lumps: Vec<(Cow<'wad, str>, Vec<u8>)>,
+}
+
+/// Combines the inputs' texture definitions.
+/// Later inputs replace same-named textures, which keep their original position.
+fn merge_texture_defs<'wad>(inputs: &[TextureInput<'wad>]) -> WadResult<MergedTextures<'wad>> {
+    let mut pnames = Vec::new();
+    let mut merged = LinkedHashMap::<Cow<str>, LinkedHashMap<String, Texture>>::new();
+    for input in inputs {
+        let names = input
+            .pnames
+            .map(|lump| parse_pnames(&lump.data))
+            .transpose()?
+            .unwrap_or_default();
+        // the engine matches patch names case-insensitively, so `wall03_7` is `WALL03_7`
+        for name in &names {
+            if !pnames.iter().any(|p: &String| p.eq_ignore_ascii_case(name)) {
+                pnames.push(name.clone());
+            }
+        }
+        for (lump, name) in &input.textures {
+            let defs = merged.entry(name.clone()).or_default();
+            for texture in parse_textures(&lump.data, &names)? {
+                match defs.get_mut(&texture.name) {
+                    Some(existing) => *existing = texture,
+                    None => {
+                        defs.insert(texture.name.clone(), texture);
+                    }
+                }
+            }
+        }
+    }
+    let lumps = merged
+        .into_iter()
+        .map(|(name, defs)| {
+            let defs = defs.into_iter().map(|(_, t)| t).collect::<Vec<_>>();
+            (name, build_textures(&defs, &mut pnames))
+        })
+        .collect();
+    Ok(MergedTextures { pnames, lumps })
+}
+
+/// The inputs' maps in the order a merge with `order` places them, each with the index of its
+/// input, before pinned and secret maps are moved to their own slots. The random orders are
+/// decided by `seed`, like with [`MergeOptions::seed`]. Nothing is renamed or rebuilt.
+pub fn ordered_maps(wads: &[Wad], order: MapOrder, seed: u64) -> Vec<(usize, MapSlice<'_>)> {
+    let mut maps = wads
+        .iter()
+        .enumerate()
+        .flat_map(|(source, wad)| wad.maps().into_iter().map(move |map| (source, map)))
+        .collect::<Vec<_>>();
+    let mut rng = StdRng::seed_from_u64(seed);
+    match order {
+        MapOrder::Concat => {} // already in this order
+        MapOrder::Chaos => maps.shuffle(&mut rng),
+        MapOrder::Slog => maps.sort_by_key(|(_, map)| map.slot().unwrap_or(usize::MAX)),
+        MapOrder::Shuffle => {
+            let mut sources = (0..wads.len()).collect::<Vec<_>>();
+            sources.shuffle(&mut rng);
+            maps.sort_by_key(|(source, _)| sources.iter().position(|s| s == source));
+        }
+        MapOrder::ByDifficulty => maps.sort_by_key(|(_, map)| map.difficulty_score()),
+    }
+    maps
+}
+
+/// Works out what the merged WAD will contain, without copying any input lumps.
+fn plan(wads: &[Wad], opts: &MergeOptions) -> WadResult<(Vec<Planned>, MergeReport)> {
+    let mut report = MergeReport::default();
+    let mut resource_lumps = LinkedHashMap::new();
+    let mut definitions = HashSet::new();
+    let mut strings = HashSet::new();
+    let mut first_names = HashMap::<String, String>::new();
+    // (namespace, source, index, name), in input order.
+    let mut namespaced_lumps = Vec::new();
+    // Texture definitions only need reconciling when several inputs bring their own.
+    let is_texture_lump =
+        |name: &str| name.eq_ignore_ascii_case("PNAMES") || is_one_of(name, TEXTURE_LUMPS);
+    let merge_textures = wads
+        .iter()
+        .filter(|wad| wad.lumps.iter().any(|l| is_texture_lump(&l.name)))
+        .count()
+        > 1;
+    let mut texture_inputs = Vec::new();
+    for (source, wad) in wads.iter().enumerate() {
+        let mut pnames = None;
+        let mut textures = Vec::new();
+        let wad_maps = wad.maps();
+        let names = wad
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        let mut namespace_of = vec![None; names.len()];
+        for (namespace, range) in namespace_blocks(&names, &opts.namespaces) {
+            namespace_of[range].fill(Some(namespace));
+        }
+        let mut in_map = vec![false; names.len()];
+        for map in &wad_maps {
+            in_map[map.range.clone()].fill(true);
+        }
+        for (i, lump) in wad.lumps.iter().enumerate() {
+            if in_map[i] || (namespace_of[i].is_some() && is_marker(&lump.name)) {
+                continue;
+            }
+            let name = rename(opts, source, &lump.name)?;
+            if opts
+                .excluded_lumps
+                .iter()
+                .any(|excluded| excluded.eq_ignore_ascii_case(&name))
+                || (opts.strip_demos && is_demo(&name))
+            {
+                log::debug!("leaving {name} from input {source} out");
+                continue;
+            }
+            // the engine doesn't tell names apart by case, so a lump takes the casing of the
+            // first one with its name, which it then replaces like any other
+            let name = match first_names.entry(name.to_ascii_uppercase()) {
+                Entry::Occupied(first) if *first.get() != name => Cow::Owned(first.get().clone()),
+                Entry::Occupied(_) => name,
+                Entry::Vacant(first) => {
+                    first.insert(name.to_string());
+                    name
+                }
+            };
+            match namespace_of[i] {
+                Some(namespace) => namespaced_lumps.push((namespace, source, i, name)),
+                None if merge_textures && is_texture_lump(&name) => {
+                    // hold the lump's place; its contents are filled in below
+                    if name.eq_ignore_ascii_case("PNAMES") {
+                        pnames = Some(lump);
+                    } else {
+                        textures.push((lump, name.clone()));
+                    }
+                    resource_lumps
+                        .entry(name.clone())
+                        .or_insert_with(|| Planned::new(&name));
+                }
+                None => {
+                    if opts
+                        .duplicate_definition_checks
+                        .iter()
+                        .any(|check| check.eq_ignore_ascii_case(&name))
+                    {
+                        let text = String::from_utf8_lossy(&lump.data);
+                        for definition in definition_names(&text) {
+                            let key = (name.clone(), definition.to_ascii_uppercase());
+                            if !definitions.insert(key) {
+                                report.warn(MergeWarning::DuplicateDefinition {
+                                    lump: name.to_string(),
+                                    definition,
+                                });
+                            }
+                        }
+                    }
+                    if name.eq_ignore_ascii_case("LANGUAGE") {
+                        let text = String::from_utf8_lossy(&lump.data);
+                        for (language, key) in language_keys(&text) {
+                            if !strings.insert((language.clone(), key.clone())) {
+                                report.warn(MergeWarning::DuplicateString { language, key });
+                            }
+                        }
+                    }
+                    add_resource(
+                        &mut resource_lumps,
+                        &mut report,
+                        opts,
+                        wads,
+                        source,
+                        i,
+                        name,
+                    );
+                }
+            }
+        }
+        texture_inputs.push(TextureInput { pnames, textures });
+    }
+    // resources are kept under the casing of the first lump with their name
+    let canonical = |name: &str| {
+        first_names
+            .get(&name.to_ascii_uppercase())
+            .cloned()
+            .unwrap_or(name.to_string())
+    };
+    if merge_textures {
+        let MergedTextures { pnames, lumps } = merge_texture_defs(&texture_inputs)?;
+        for (name, data) in lumps {
+            *resource_lumps.get_mut(name.as_ref()).unwrap() = Planned::New(Lump {
+                name: name.to_string(),
+                data,
+            });
+        }
+        let name = canonical("PNAMES");
+        if let Some(lump) = resource_lumps.get_mut(name.as_str()) {
+            *lump = Planned::New(Lump {
+                name,
+                data: build_pnames(&pnames),
+            });
+        }
+        // Patches may be placed loose as well as in the patch namespace.
+        let available = namespaced_lumps
+            .iter()
+            .filter(|(ns, _, _, _)| *ns == Namespace::Patches)
+            .map(|(_, _, _, name)| name.as_ref())
+            .chain(resource_lumps.keys().map(|name| name.as_ref()))
+            .map(str::to_ascii_uppercase)
+            .collect::<HashSet<_>>();
+        for patch in pnames {
+            if !available.contains(&patch.to_ascii_uppercase()) {
+                report.warn(MergeWarning::MissingPatch { patch });
+            }
+        }
+    }
+
+    let maps = if opts.resources_only {
+        Vec::new()
+    } else {
+        ordered_maps(wads, opts.order, opts.seed.unwrap_or_else(rand::random))
+    };
+    let secrets = wads
+        .iter()
+        .map(|wad| {
+            opts.secret_detectors
+                .iter()
+                .flat_map(|detector| detector.secret_maps(wad))
+                .collect::<HashSet<_>>()
+        })
+        .collect::<Vec<_>>();
+    let game = opts.target_game;
+    let no_pins = HashMap::new();
+    let pins = if opts.resources_only {
+        &no_pins
+    } else {
+        &opts.pinned_slots
+    };
+    let placements = place_maps(maps, &secrets, pins, game)?;
+    for p in &placements {
+        log::debug!(
+            "{} from input {} goes in {}",
+            p.map.name,
+            p.source,
+            game.slot_name(p.slot)
+        );
+        if opts.warn_vanilla_limits {
+            for violation in p.map.vanilla_limit_report() {
+                report.warn(MergeWarning::VanillaLimit {
+                    map: game.slot_name(p.slot),
+                    violation,
+                });
+            }
+        }
+    }
+
+    let merged_dehacked = resource_lumps
+        .get(canonical("DEHACKED").as_str())
+        .map(|l| l.data(wads));
+    for warning in dehacked_conflicts(&placements, wads, merged_dehacked, game) {
+        report.warn(warning);
+    }
+
+    // the music each placed map plays, if it's known
+    let mut map_music = vec![None; placements.len()];
+    if opts.music != MusicStrategy::Keep {
+        let source_infos = wads.iter().map(Wad::mapinfo).collect::<Vec<_>>();
+        let mut moved = HashSet::new();
+        let mut renamed = Vec::new();
+        for (i, p) in placements.iter().enumerate() {
+            let Some(music) = source_music(&source_infos[p.source], p.map.name) else {
+                continue;
+            };
+            // Music the source doesn't bring along comes from the IWAD and can't be moved.
+            let index = wads[p.source]
+                .lumps
+                .iter()
+                .rposition(|l| l.name.eq_ignore_ascii_case(&music));
+            let Some(index) = index else {
+                map_music[i] = Some(music);
+                continue;
+            };
+            let target = match opts.music {
+                MusicStrategy::Keep => unreachable!(),
+                MusicStrategy::Rename => game.vanilla_music(p.slot),
+                MusicStrategy::Mapinfo => Some(format!("D_{}", game.slot_name(p.slot))),
+            };
+            let Some(target) = target else {
+                log::warn!(
+                    "{} has no music of its own, so {music} from input {} keeps its name",
+                    game.slot_name(p.slot),
+                    p.source
+                );
+                map_music[i] = Some(music);
+                continue;
+            };
+            renamed.push((target.clone(), p.source, index));
+            moved.insert(music);
+            map_music[i] = Some(target);
+        }
+        for name in &moved {
+            resource_lumps.remove(canonical(name).as_str());
+        }
+        for (name, source, index) in renamed {
+            resource_lumps.remove(canonical(&name).as_str());
+            resource_lumps.insert(
+                Cow::Owned(name.clone()),
+                Planned::Input {
+                    source,
+                    index,
+                    rename: Some(name),
+                },
+            );
+        }
+    }
+    let needs_mapinfo = !opts.resources_only
+        && (opts.generate_mapinfo
+            || opts.music == MusicStrategy::Mapinfo
+            || placements.iter().any(|p| p.secret));
+    let mapinfo = needs_mapinfo.then(|| {
+        let mut entries = build_progression(&placements, wads, game);
+        for (entry, music) in entries.iter_mut().zip(map_music) {
+            entry.music = music;
+        }
+        if let Some(format) = &opts.level_name_format {
+            let source_infos = wads.iter().map(Wad::mapinfo).collect::<Vec<_>>();
+            for (entry, p) in entries.iter_mut().zip(&placements) {
+                let input = opts
+                    .input_names
+                    .get(p.source)
+                    .cloned()
+                    .unwrap_or_else(|| format!("input {}", p.source));
+                let name = source_infos[p.source]
+                    .iter()
+                    .find(|e| e.map.eq_ignore_ascii_case(p.map.name))
+                    .and_then(|e| e.name.as_deref())
+                    .unwrap_or(p.map.name);
+                entry.name = Some(fill_in(
+                    format,
+                    &[
+                        ("slot", &entry.map),
+                        ("map", p.map.name),
+                        ("input", &input),
+                        ("name", name),
+                    ],
+                ));
+            }
+        }
+        entries
+    });
+    if mapinfo.is_some() {
+        resource_lumps.remove(canonical("MAPINFO").as_str());
+        resource_lumps.remove(canonical("ZMAPINFO").as_str());
+    }
+
+    let mut out = resource_lumps
+        .into_iter()
+        .map(|(_, planned)| planned)
+        .collect::<Vec<_>>();
+    if let Some(mapinfo) = mapinfo {
+        out.push(Planned::New(Lump {
+            name: String::from("MAPINFO"),
+            data: build_mapinfo(&mapinfo, game).into_bytes(),
+        }));
+    }
+    for &namespace in &opts.namespaces {
+        let lumps = namespaced_lumps
+            .iter()
+            .filter(|(ns, _, _, _)| *ns == namespace);
+        let blocks = match opts.namespace_strategy {
+            NamespaceStrategy::Unify => {
+                let mut unified = LinkedHashMap::new();
+                for (_, source, index, name) in lumps {
+                    if let Some(&(old, i, _)) = unified.get(name.as_ref()) {
+                        if !replaces(opts, wads, name, (old, i), (*source, *index)) {
+                            continue;
+                        }
+                        if wads[old].lumps[i].data != wads[*source].lumps[*index].data {
+                            report.warn(MergeWarning::Collision {
+                                name: name.to_string(),
+                                source: *source,
+                                replaced: old,
+                            });
+                        }
+                    }
+                    unified.insert(name.as_ref(), (*source, *index, name));
+                }
+                vec![unified
+                    .into_iter()
+                    .map(|(_, lump)| lump)
+                    .collect::<Vec<_>>()]
+            }
+            NamespaceStrategy::Isolate => (0..wads.len())
+                .map(|source| {
+                    lumps
+                        .clone()
+                        .filter(|(_, s, _, _)| *s == source)
+                        .map(|(_, source, index, name)| (*source, *index, name))
+                        .collect()
+                })
+                .collect(),
+        };
+        let (start, end) = namespace.markers();
+        for block in blocks.into_iter().filter(|block| !block.is_empty()) {
+            out.push(Planned::new(start));
+            for (source, index, name) in block {
+                out.push(Planned::Input {
+                    source,
+                    index,
+                    rename: match name {
+                        Cow::Borrowed(_) => None,
+                        Cow::Owned(name) => Some(name.clone()),
+                    },
+                });
+            }
+            out.push(Planned::new(end));
+        }
+    }
+    for placement in &placements {
+        let map = &placement.map;
+        let name = game.slot_name(placement.slot);
+        let gl_name = format!("GL_{}", map.name);
+        // slots past MAP99 have names too long for a GL marker
+        let new_gl_name = format!("GL_{name}");
+        // Rebuilt lumps go in their usual place, right after SECTORS, or at the end of a map
+        // without one.
+        let mut rebuilt = Vec::new();
+        if map.format != MapFormat::Udmf {
+            if opts.rebuild_reject {
+                rebuilt.push(Lump {
+                    name: String::from("REJECT"),
+                    data: map.build_reject(),
+                });
+            }
+            if opts.rebuild_blockmap {
+                rebuilt.push(Lump {
+                    name: String::from("BLOCKMAP"),
+                    data: map.build_blockmap()?,
+                });
+            }
+        }
+        let mut capped = opts
+            .thing_cap
+            .filter(|_| map.format == MapFormat::Doom)
+            .and_then(|cap| {
+                let things = Thing::read_all(&map.lump("THINGS")?.data);
+                Some(Lump {
+                    name: String::from("THINGS"),
+                    data: Thing::write_all(&cap_things(&things, &cap)),
+                })
+            });
+        out.push(Planned::new(&name));
+        let replaced = rebuilt.iter().map(|l| l.name.clone()).collect::<Vec<_>>();
+        for (k, lump) in map.lumps.iter().enumerate().skip(1) {
+            if replaced.contains(&lump.name) {
+                continue;
+            }
+            if lump.name == "THINGS" {
+                if let Some(things) = capped.take() {
+                    out.push(Planned::New(things));
+                    continue;
+                }
+            }
+            out.push(Planned::Input {
+                source: placement.source,
+                index: map.range.start + k,
+                rename: if lump.name == gl_name {
+                    validate_name(&new_gl_name)?;
+                    Some(new_gl_name.clone())
+                } else {
+                    None
+                },
+            });
+            if lump.name == "SECTORS" {
+                out.extend(rebuilt.drain(..).map(Planned::New));
+            }
+        }
+        out.extend(rebuilt.into_iter().map(Planned::New));
+        if map.format == MapFormat::Udmf && map.lumps.last().unwrap().name != "ENDMAP" {
+            out.push(Planned::new("ENDMAP"));
+        }
+    }
+    for warning in dangling_sounds(&out, wads) {
+        report.warn(warning);
+    }
+    Ok((out, report))
+}
+
+/// Builds the merged WAD from its plan, getting input lumps from `take`, then checks its size.
+fn assemble(
+    plan: Vec<Planned>,
+    report: MergeReport,
+    was_zip: bool,
+    opts: &MergeOptions,
+    mut take: impl FnMut(usize, usize) -> Lump,
+) -> WadResult<(Wad, MergeReport)> {
+    let mut out = Wad::with_capacity(plan.len());
+    out.was_zip = was_zip;
+    for planned in plan {
+        out.add_lump(match planned {
+            Planned::Input {
+                source,
+                index,
+                rename,
+            } => {
+                let mut lump = take(source, index);
+                if let Some(name) = rename {
+                    lump.name = name;
+                }
+                lump
+            }
+            Planned::New(lump) => lump,
+        });
+    }
+
+    if let Some(limit) = opts.max_output_size {
+        let size = out.total_size();
+        if size > limit {
+            return Err(WadError::OutputTooLarge { size, limit });
+        }
+    }
+    Ok((out, report))
+}
+
+impl Wad {
+    /// Smashes several WADs together. Resource lumps are gathered in front, with later WADs
+    /// replacing same-named lumps from earlier ones, then the namespace blocks, followed by every
+    /// map renumbered from the first slot (`MAP01` or `E1M1`) in the chosen order. A few text
+    /// lumps, like SNDINFO, are concatenated instead of replaced, GUS configs are merged by
+    /// instrument, and TEXTURE1/TEXTURE2 from several WADs are combined onto one PNAMES.
+    pub fn merge(wads: &[Wad], opts: &MergeOptions) -> WadResult<(Wad, MergeReport)> {
+        let (plan, report) = plan(wads, opts)?;
+        let was_zip = wads.iter().any(|w| w.was_zip);
+        assemble(plan, report, was_zip, opts, |source, index| {
+            wads[source].lumps[index].clone()
+        })
+    }
+
+    /// Works out everything [`Wad::merge`] would warn about or fail on, without building the
+    /// output. The only check left out is [`MergeOptions::max_output_size`], which needs the
+    /// output's layout. Every warning, down to those about the output's SNDINFO, is worked out
+    /// while planning, so the report is the one a merge would give.
+    pub fn check_merge(wads: &[Wad], opts: &MergeOptions) -> WadResult<MergeReport> {
+        let (_, report) = plan(wads, opts)?;
+        Ok(report)
+    }
+
+    /// Like [`Wad::merge`], but consumes the inputs, moving their lumps into the output instead
+    /// of copying them.
+    pub fn merge_owned(wads: Vec<Wad>, opts: &MergeOptions) -> WadResult<(Wad, MergeReport)> {
+        let (plan, report) = plan(&wads, opts)?;
+        let was_zip = wads.iter().any(|w| w.was_zip);
+        // a lump can be planned more than once, like music shared by several maps, so it's
+        // copied for all but its last use
+        let mut uses = HashMap::<(usize, usize), usize>::new();
+        for planned in &plan {
+            if let Planned::Input { source, index, .. } = planned {
+                *uses.entry((*source, *index)).or_default() += 1;
+            }
+        }
+        let mut inputs = wads
+            .into_iter()
+            .map(|wad| wad.into_lumps().into_iter().map(Some).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assemble(plan, report, was_zip, opts, |source, index| {
+            let left = uses.get_mut(&(source, index)).unwrap();
+            *left -= 1;
+            let lump = &mut inputs[source][index];
+            if *left == 0 {
+                lump.take()
+            } else {
+                lump.clone()
+            }
+            .expect("a lump is only taken on its last use")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MapinfoSecrets, SlotSecrets};
+
+    /// A WAD holding `lumps`, each given as a name and its contents.
+    fn wad(lumps: &[(&str, &[u8])]) -> Wad {
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in lumps {
+            wad.append_lump_bytes(*name, data.to_vec()).unwrap();
+        }
+        wad
+    }
+
+    #[test]
+    fn maps_sharing_music_get_a_copy_each() {
+        let input = || {
+            wad(&[
+                (
+                    "MAPINFO",
+                    b"map MAP01 \"One\"\nmusic SHARED\nmap MAP02 \"Two\"\nmusic SHARED\n",
+                ),
+                ("SHARED", b"MUS\x1a"),
+                ("MAP01", b""),
+                ("THINGS", b""),
+                ("MAP02", b""),
+                ("THINGS", b""),
+            ])
+        };
+        let opts = MergeOptions {
+            music: MusicStrategy::Rename,
+            ..MergeOptions::default()
+        };
+        let (borrowed, _) = Wad::merge(&[input()], &opts).unwrap();
+        let (owned, _) = Wad::merge_owned(vec![input()], &opts).unwrap();
+        assert_eq!(borrowed, owned);
+        assert_eq!(owned.lump("D_RUNNIN").unwrap().data, b"MUS\x1a");
+        assert_eq!(owned.lump("D_STALKS").unwrap().data, b"MUS\x1a");
+        assert!(owned.lump("SHARED").is_none());
+    }
+
+    #[test]
+    fn check_merge_reports_dangling_sounds() {
+        let input = wad(&[
+            ("SNDINFO", b"misc/boom DSBOOM\nmisc/here dshere\n"),
+            ("DSHERE", b""),
+        ]);
+        let report = Wad::check_merge(&[input], &MergeOptions::default()).unwrap();
+        assert_eq!(
+            report.warnings,
+            [MergeWarning::DanglingSound {
+                sound: String::from("misc/boom"),
+                lump: String::from("DSBOOM"),
+            }]
+        );
+    }
+
+    /// Adds an empty Doom-format map.
+    fn add_map(wad: &mut Wad, name: &str) {
+        wad.append_lump_bytes(name, Vec::new()).unwrap();
+        for lump in [
+            "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES", "SECTORS",
+            "REJECT", "BLOCKMAP",
+        ] {
+            wad.append_lump_bytes(lump, Vec::new()).unwrap();
+        }
+    }
+
+    /// A WAD laid out like the shareware doom1.wad: an IWAD with only the first episode's maps
+    /// and a reduced set of resources.
+    fn shareware_iwad() -> Wad {
+        let mut wad = wad(&[
+            ("PLAYPAL", &[0; 768]),
+            ("COLORMAP", &[0; 256]),
+            ("ENDOOM", &[0; 4000]),
+            ("DEMO1", b""),
+            ("D_E1M1", b"MUS\x1a"),
+        ]);
+        for map in 1..=9 {
+            add_map(&mut wad, &format!("E1M{map}"));
+        }
+        for lump in ["S_START", "TROOA1", "S_END", "F_START", "FLOOR0_1", "F_END"] {
+            wad.append_lump_bytes(lump, Vec::new()).unwrap();
+        }
+        let mut bytes = wad.write_to_vec().unwrap();
+        bytes[..4].copy_from_slice(b"IWAD");
+        Wad::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn shareware_iwad_loads_and_merges() {
+        let shareware = shareware_iwad();
+        let maps = shareware.maps();
+        assert_eq!(
+            maps.iter().map(|m| m.name).collect::<Vec<_>>(),
+            (1..=9).map(|m| format!("E1M{m}")).collect::<Vec<_>>()
+        );
+        assert!(maps.iter().all(|m| m.lumps.len() == 11));
+
+        let mut pwad = Wad::new_empty(false);
+        add_map(&mut pwad, "MAP01");
+        for (game, last) in [(Game::Doom, "E2M1"), (Game::Doom2, "MAP10")] {
+            let opts = MergeOptions {
+                target_game: game,
+                ..MergeOptions::default()
+            };
+            let (merged, _) = Wad::merge(
+                &[
+                    shareware_iwad(),
+                    Wad::from_lumps(pwad.lumps.clone()).unwrap(),
+                ],
+                &opts,
+            )
+            .unwrap();
+            let names = merged
+                .maps()
+                .iter()
+                .map(|m| m.name.to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(names.len(), 10);
+            assert_eq!(names.last().unwrap(), last);
+            assert!(merged.lump("TROOA1").is_some() && merged.lump("FLOOR0_1").is_some());
+        }
+    }
+
+    /// Like [`wad`], but without checking the names, so they can be lowercase.
+    fn lowercase(lumps: &[(&str, &[u8])]) -> Wad {
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in lumps {
+            wad.add_lump(Lump {
+                name: name.to_string(),
+                data: data.to_vec(),
+            });
+        }
+        wad
+    }
+
+    #[test]
+    fn special_lumps_are_recognised_in_any_case() {
+        let inputs = [
+            wad(&[("SNDINFO", b"misc/one DSONE"), ("DSONE", b"")]),
+            lowercase(&[
+                ("sndinfo", b"misc/two DSTWO"),
+                ("dstwo", b""),
+                ("dehacked", b""),
+            ]),
+        ];
+        let opts = MergeOptions {
+            excluded_lumps: vec![String::from("DEHACKED")],
+            ..MergeOptions::default()
+        };
+        let (merged, report) = Wad::merge(&inputs, &opts).unwrap();
+        assert_eq!(
+            merged.lump("SNDINFO").unwrap().data,
+            b"misc/one DSONE\nmisc/two DSTWO"
+        );
+        assert!(merged
+            .lumps
+            .iter()
+            .all(|l| !l.name.eq_ignore_ascii_case("DEHACKED")));
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_order() {
+        let inputs = (0..8)
+            .map(|input| {
+                let mut wad = Wad::new_empty(false);
+                for map in 1..=3 {
+                    add_map(&mut wad, &format!("E{}M{map}", input + 1));
+                }
+                wad
+            })
+            .collect::<Vec<_>>();
+        let order = |order, seed| {
+            ordered_maps(&inputs, order, seed)
+                .into_iter()
+                .map(|(source, map)| (source, map.name.to_string()))
+                .collect::<Vec<_>>()
+        };
+        for mode in [MapOrder::Chaos, MapOrder::Shuffle] {
+            assert_eq!(order(mode, 42), order(mode, 42));
+            assert_ne!(order(mode, 42), order(MapOrder::Concat, 0));
+        }
+        let opts = MergeOptions {
+            order: MapOrder::Shuffle,
+            seed: Some(7),
+            ..MergeOptions::default()
+        };
+        let (first, _) = Wad::merge(&inputs, &opts).unwrap();
+        let (second, _) = Wad::merge(&inputs, &opts).unwrap();
+        assert_eq!(first, second);
+    }
+
+    /// Everything logged while the tests run, shared between them.
+    static LOGGED: std::sync::Mutex<Vec<(log::Level, String)>> = std::sync::Mutex::new(Vec::new());
+
+    struct TestLogger;
+
+    impl log::Log for TestLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            LOGGED
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Sends log messages to [`LOGGED`], for whichever test gets here first.
+    fn install_logger() {
+        static INSTALL: std::sync::Once = std::sync::Once::new();
+        INSTALL.call_once(|| {
+            log::set_logger(&TestLogger).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+    }
+
+    /// The messages logged at `level` which mention `needle`.
+    fn logged(level: log::Level, needle: &str) -> Vec<String> {
+        LOGGED
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(l, message)| *l == level && message.contains(needle))
+            .map(|(_, message)| message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn collisions_are_logged() {
+        install_logger();
+        let inputs = [
+            wad(&[("COLLIDE", b"first")]),
+            wad(&[("COLLIDE", b"second")]),
+            wad(&[("COLLIDE", b"second")]),
+        ];
+        let (merged, report) = Wad::merge(&inputs, &MergeOptions::default()).unwrap();
+        assert_eq!(merged.lump("COLLIDE").unwrap().data, b"second");
+        // the identical third lump replaces the second quietly
+        assert_eq!(
+            logged(log::Level::Warn, "COLLIDE"),
+            ["COLLIDE from input 1 replaces the one from input 0"]
+        );
+        let collision = MergeWarning::Collision {
+            name: String::from("COLLIDE"),
+            source: 1,
+            replaced: 0,
+        };
+        assert_eq!(report.warnings, std::slice::from_ref(&collision));
+        assert_eq!(
+            Wad::check_merge(&inputs, &MergeOptions::default())
+                .unwrap()
+                .warnings,
+            [collision]
+        );
+    }
+
+    #[test]
+    fn namespaced_collisions_are_reported() {
+        let inputs = [
+            wad(&[("F_START", b""), ("FLOOR0_1", b"first"), ("F_END", b"")]),
+            wad(&[("F_START", b""), ("FLOOR0_1", b"second"), ("F_END", b"")]),
+        ];
+        let (_, report) = Wad::merge(&inputs, &MergeOptions::default()).unwrap();
+        assert_eq!(
+            report.warnings,
+            [MergeWarning::Collision {
+                name: String::from("FLOOR0_1"),
+                source: 1,
+                replaced: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn the_collision_resolver_decides_each_collision() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let inputs = [
+            wad(&[
+                ("DEHACKED", b"a"),
+                ("COLORMAP", b"old"),
+                ("PLAYPAL", b"same"),
+            ]),
+            wad(&[
+                ("DEHACKED", b"b"),
+                ("COLORMAP", b"new"),
+                ("PLAYPAL", b"same"),
+            ]),
+        ];
+        for (choice, expected) in [
+            (CollisionChoice::KeepExisting, b"old"),
+            (CollisionChoice::TakeIncoming, b"new"),
+        ] {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let counter = Arc::clone(&calls);
+            let opts = MergeOptions {
+                collision_resolver: Some(CollisionResolver::new(move |collision| {
+                    assert_eq!(
+                        (collision.existing_source, collision.incoming_source),
+                        (0, 1)
+                    );
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    if collision.name == "COLORMAP" {
+                        choice
+                    } else {
+                        CollisionChoice::KeepExisting
+                    }
+                })),
+                ..MergeOptions::default()
+            };
+            let (merged, _) = Wad::merge(&inputs, &opts).unwrap();
+            // the matching PLAYPALs don't collide
+            assert_eq!(calls.load(Ordering::Relaxed), 2);
+            assert_eq!(merged.lump("COLORMAP").unwrap().data, expected);
+            assert_eq!(merged.lump("DEHACKED").unwrap().data, b"a");
+        }
+    }
+
+    /// A 64x128 texture made of the given patches.
+    fn texture(name: &str, patches: &[&str]) -> Texture {
+        Texture {
+            name: name.to_string(),
+            flags: 0,
+            width: 64,
+            height: 128,
+            patches: patches
+                .iter()
+                .map(|patch| crate::TexturePatch {
+                    origin_x: 0,
+                    origin_y: 0,
+                    patch: patch.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// A PNAMES and TEXTURE1 defining `textures`, each given as a name and its patches.
+    fn texture_lumps(textures: &[(&str, &[&str])]) -> (Vec<u8>, Vec<u8>) {
+        let textures = textures
+            .iter()
+            .map(|(name, patches)| texture(name, patches))
+            .collect::<Vec<_>>();
+        let mut pnames = Vec::new();
+        let texture1 = build_textures(&textures, &mut pnames);
+        (build_pnames(&pnames), texture1)
+    }
+
+    #[test]
+    fn lowercase_texture_lumps_are_rebuilt() {
+        let (pnames, texture1) = texture_lumps(&[("BRICK", &["WALL00"])]);
+        let (upper_pnames, upper_texture1) = texture_lumps(&[("STONE", &["WALL01"])]);
+        let inputs = [
+            lowercase(&[("pnames", &pnames), ("texture1", &texture1)]),
+            wad(&[("PNAMES", &upper_pnames), ("TEXTURE1", &upper_texture1)]),
+        ];
+        let (merged, _) = Wad::merge(&inputs, &MergeOptions::default()).unwrap();
+        let find = |name: &str| {
+            let matching = merged
+                .lumps
+                .iter()
+                .filter(|l| l.name.eq_ignore_ascii_case(name))
+                .collect::<Vec<_>>();
+            assert_eq!(matching.len(), 1, "{name}");
+            matching[0]
+        };
+        let pnames = parse_pnames(&find("PNAMES").data).unwrap();
+        assert_eq!(pnames, ["WALL00", "WALL01"]);
+        let textures = parse_textures(&find("TEXTURE1").data, &pnames).unwrap();
+        assert_eq!(
+            textures,
+            [texture("BRICK", &["WALL00"]), texture("STONE", &["WALL01"])]
+        );
+    }
+
+    #[test]
+    fn overlapping_texture_lumps_share_one_pnames() {
+        let (first_pnames, first_texture1) =
+            texture_lumps(&[("BRICK", &["WALL00", "WALL01"]), ("METAL", &["WALL01"])]);
+        // WALL01 is patch 0 here, and 1 in the first input
+        let (second_pnames, second_texture1) =
+            texture_lumps(&[("STONE", &["WALL01", "WALL02"]), ("BRICK", &["WALL02"])]);
+        let inputs = [
+            wad(&[
+                ("PNAMES", &first_pnames),
+                ("TEXTURE1", &first_texture1),
+                ("P_START", b""),
+                ("WALL00", b""),
+                ("WALL01", b""),
+                ("P_END", b""),
+            ]),
+            wad(&[("PNAMES", &second_pnames), ("TEXTURE1", &second_texture1)]),
+        ];
+        let (merged, report) = Wad::merge(&inputs, &MergeOptions::default()).unwrap();
+        let pnames = parse_pnames(&merged.lump("PNAMES").unwrap().data).unwrap();
+        assert_eq!(pnames, ["WALL00", "WALL01", "WALL02"]);
+        let textures = parse_textures(&merged.lump("TEXTURE1").unwrap().data, &pnames).unwrap();
+        assert_eq!(
+            textures,
+            [
+                texture("BRICK", &["WALL02"]),
+                texture("METAL", &["WALL01"]),
+                texture("STONE", &["WALL01", "WALL02"]),
+            ]
+        );
+        assert_eq!(
+            report.warnings,
+            [MergeWarning::MissingPatch {
+                patch: String::from("WALL02")
+            }]
+        );
+    }
+
+    /// Adds a Doom-format map placing `things`.
+    fn add_map_with_things(wad: &mut Wad, name: &str, things: &[Thing]) {
+        let marker = wad.lumps.len();
+        add_map(wad, name);
+        wad.replace_lump_data(marker + 1, Thing::write_all(things))
+            .unwrap();
+    }
+
+    /// A thing of type `kind` with the given flags, at the origin.
+    fn thing(kind: u16, flags: u16) -> Thing {
+        Thing {
+            x: 0,
+            y: 0,
+            angle: 0,
+            kind,
+            flags,
+        }
+    }
+
+    #[test]
+    fn by_difficulty_sorts_maps_by_score() {
+        // on every skill level, in single player
+        const ALL_SKILLS: u16 = 7;
+        let mut first = Wad::new_empty(false);
+        add_map_with_things(&mut first, "MAP01", &[thing(16, ALL_SKILLS)]);
+        add_map_with_things(
+            &mut first,
+            "MAP02",
+            &[
+                thing(3001, ALL_SKILLS),
+                thing(3001, 4),
+                thing(1, ALL_SKILLS),
+            ],
+        );
+        // only on the easy skills, and only in multiplayer
+        add_map_with_things(&mut first, "MAP03", &[thing(3003, 1), thing(3003, 4 | 16)]);
+        add_map(&mut first, "MAP04");
+        let mut second = Wad::new_empty(false);
+        add_map_with_things(&mut second, "MAP01", &[thing(3003, ALL_SKILLS)]);
+        let inputs = [first, second];
+        let ordered = ordered_maps(&inputs, MapOrder::ByDifficulty, 0);
+        assert_eq!(
+            ordered
+                .iter()
+                .map(|(source, map)| (*source, map.name, map.difficulty_score()))
+                .collect::<Vec<_>>(),
+            [
+                (0, "MAP03", 0),
+                (0, "MAP04", 0),
+                (0, "MAP02", 120),
+                (1, "MAP01", 1000),
+                (0, "MAP01", 4000),
+            ]
+        );
+    }
+
+    #[test]
+    fn rebuilt_lumps_are_added_where_missing() {
+        let input = wad(&[
+            ("MAP01", b""),
+            ("THINGS", b""),
+            ("LINEDEFS", b""),
+            ("SIDEDEFS", b""),
+            ("VERTEXES", b""),
+            ("SECTORS", &[0; 26]),
+            ("BLOCKMAP", b"old"),
+            ("MAP02", b""),
+            ("THINGS", b""),
+            ("LINEDEFS", b""),
+            ("VERTEXES", b""),
+        ]);
+        let opts = MergeOptions {
+            rebuild_reject: true,
+            rebuild_blockmap: true,
+            ..MergeOptions::default()
+        };
+        let (merged, _) = Wad::merge(&[input], &opts).unwrap();
+        let names = merged
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "MAP01", "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SECTORS", "REJECT",
+                "BLOCKMAP", "MAP02", "THINGS", "LINEDEFS", "VERTEXES", "REJECT", "BLOCKMAP",
+            ]
+        );
+        // one bit for the one pair of sectors
+        assert_eq!(merged.lumps[6].data, [0]);
+        assert_ne!(merged.lumps[7].data, b"old");
+    }
+
+    /// Adds GL nodes for the map just added, marked `GL_<map>`.
+    fn add_gl_nodes(wad: &mut Wad, map: &str) {
+        for lump in [&format!("GL_{map}"), "GL_VERT", "GL_SEGS", "GL_PVS"] {
+            wad.append_lump_bytes(lump, Vec::new()).unwrap();
+        }
+    }
+
+    #[test]
+    fn gl_nodes_are_renamed_with_their_map() {
+        let mut input = Wad::new_empty(false);
+        add_map(&mut input, "MAP05");
+        add_gl_nodes(&mut input, "MAP05");
+        let (merged, _) = Wad::merge(&[input], &MergeOptions::default()).unwrap();
+        let names = merged
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names[0], "MAP01");
+        assert_eq!(names[11..], ["GL_MAP01", "GL_VERT", "GL_SEGS", "GL_PVS"]);
+        assert_eq!(merged.maps().len(), 1);
+    }
+
+    #[test]
+    fn gl_markers_past_map99_are_an_error() {
+        let mut input = Wad::new_empty(false);
+        for slot in 1..=99 {
+            add_map(&mut input, &format!("MAP{slot:02}"));
+        }
+        // goes in MAP100
+        add_map(&mut input, "E1M1");
+        add_gl_nodes(&mut input, "E1M1");
+        assert!(matches!(
+            Wad::merge(&[input], &MergeOptions::default()),
+            Err(WadError::BadLumpName(name)) if name == "GL_MAP100"
+        ));
+    }
+
+    #[test]
+    fn maps_pinned_to_one_slot_conflict() {
+        let mut first = Wad::new_empty(false);
+        add_map(&mut first, "MAP01");
+        add_map(&mut first, "MAP02");
+        let mut second = Wad::new_empty(false);
+        add_map(&mut second, "MAP01");
+        let inputs = [first, second];
+        let pin = |pins: &[(usize, &str, &str)]| MergeOptions {
+            pinned_slots: pins
+                .iter()
+                .map(|&(source, map, slot)| (SourceMapId::new(source, map), slot.to_string()))
+                .collect(),
+            ..MergeOptions::default()
+        };
+
+        let (merged, _) = Wad::merge(&inputs, &pin(&[(0, "MAP02", "MAP03")])).unwrap();
+        let maps = merged.maps();
+        assert_eq!(maps.len(), 3);
+        assert_eq!(maps[2].name, "MAP03");
+        // a new map of pins each time, since each iterates in its own order
+        for _ in 0..16 {
+            let opts = pin(&[(1, "MAP01", "map05"), (0, "MAP02", "MAP05")]);
+            assert!(matches!(
+                Wad::merge(&inputs, &opts),
+                Err(WadError::PinnedSlotConflict { slot, first, second })
+                    if slot == "MAP05" && first == "MAP02" && second == "MAP01"
+            ));
+        }
+    }
+
+    #[test]
+    fn secret_maps_land_in_the_secret_slots() {
+        let mut first = wad(&[(
+            "MAPINFO",
+            b"map MAP02 \"Two\" { next = \"MAP03\" secretnext = \"MAP04\" }\n",
+        )]);
+        for map in ["MAP01", "MAP02", "MAP03", "MAP04"] {
+            add_map(&mut first, map);
+        }
+        let mut second = Wad::new_empty(false);
+        add_map(&mut second, "MAP01");
+        let inputs = [first, second];
+        let opts = MergeOptions {
+            secret_detectors: vec![Arc::new(MapinfoSecrets), Arc::new(SlotSecrets)],
+            generate_mapinfo: true,
+            ..MergeOptions::default()
+        };
+        let (merged, _) = Wad::merge(&inputs, &opts).unwrap();
+        let names = merged.maps().iter().map(|m| m.name).collect::<Vec<_>>();
+        assert_eq!(names, ["MAP01", "MAP02", "MAP03", "MAP04", "MAP31"]);
+        let mapinfo = merged.mapinfo();
+        let entry = |map: &str| mapinfo.iter().find(|e| e.map == map).unwrap();
+        assert_eq!(entry("MAP02").secret_next.as_deref(), Some("MAP31"));
+        assert_eq!(entry("MAP31").next.as_deref(), Some("MAP03"));
+
+        let doom = MergeOptions {
+            target_game: Game::Doom,
+            ..opts
+        };
+        let (merged, _) = Wad::merge(&inputs, &doom).unwrap();
+        let names = merged.maps().iter().map(|m| m.name).collect::<Vec<_>>();
+        assert_eq!(names, ["E1M1", "E1M2", "E1M3", "E1M4", "E1M9"]);
+    }
+
+    #[test]
+    fn outputs_over_the_size_limit_are_an_error() {
+        let input = wad(&[("FIRST", b"one"), ("SECOND", b"two")]);
+        // a header, two directory entries and six bytes of data
+        let size = 12 + 2 * 16 + 6;
+        let limit = |limit| MergeOptions {
+            max_output_size: Some(limit),
+            ..MergeOptions::default()
+        };
+        assert!(Wad::merge(std::slice::from_ref(&input), &limit(size)).is_ok());
+        assert!(matches!(
+            Wad::merge(&[input], &limit(size - 1)),
+            Err(WadError::OutputTooLarge { size: s, limit: l }) if s == size && l == size - 1
+        ));
+    }
+
+    #[test]
+    fn isolated_namespaces_get_a_block_per_input() {
+        let inputs = [
+            wad(&[("F_START", b""), ("FLOOR1", b"one"), ("F_END", b"")]),
+            wad(&[
+                ("LOOSE", b""),
+                ("FF_START", b""),
+                ("FLOOR1", b"two"),
+                ("FF_END", b""),
+            ]),
+        ];
+        let names = |merged: &Wad| {
+            merged
+                .lumps
+                .iter()
+                .map(|l| l.name.clone())
+                .collect::<Vec<_>>()
+        };
+        let opts = MergeOptions {
+            namespace_strategy: NamespaceStrategy::Isolate,
+            ..MergeOptions::default()
+        };
+        let (merged, _) = Wad::merge(&inputs, &opts).unwrap();
+        assert_eq!(
+            names(&merged),
+            ["LOOSE", "F_START", "FLOOR1", "F_END", "F_START", "FLOOR1", "F_END"]
+        );
+        assert_eq!(merged.lumps[2].data, b"one");
+        assert_eq!(merged.lumps[5].data, b"two");
+
+        let (merged, _) = Wad::merge(&inputs, &MergeOptions::default()).unwrap();
+        assert_eq!(names(&merged), ["LOOSE", "F_START", "FLOOR1", "F_END"]);
+        assert_eq!(merged.lumps[2].data, b"two");
+    }
+
+    #[test]
+    fn definition_lumps_are_concatenated_with_duplicates_reported() {
+        let inputs = [
+            wad(&[
+                (
+                    "GLDEFS",
+                    b"pointlight LAMP\n{\n  color 1.0 1.0 1.0\n  size 64\n}",
+                ),
+                ("MENUDEF", b"ListMenu \"MainMenu\" { }\n"),
+            ]),
+            wad(&[
+                (
+                    "GLDEFS",
+                    b"// brighter\nPointLight lamp { size 96 }\nobject LAMP { }\n",
+                ),
+                ("MENUDEF", b"ListMenu \"Options\" { }\n"),
+            ]),
+        ];
+        let (merged, report) = Wad::merge(&inputs, &MergeOptions::default()).unwrap();
+        assert_eq!(
+            merged.lump("GLDEFS").unwrap().data,
+            b"pointlight LAMP\n{\n  color 1.0 1.0 1.0\n  size 64\n}\n\
+              // brighter\nPointLight lamp { size 96 }\nobject LAMP { }\n"
+        );
+        assert_eq!(
+            merged.lump("MENUDEF").unwrap().data,
+            b"ListMenu \"MainMenu\" { }\nListMenu \"Options\" { }\n"
+        );
+        assert_eq!(
+            report.warnings,
+            [MergeWarning::DuplicateDefinition {
+                lump: String::from("GLDEFS"),
+                definition: String::from("PointLight lamp"),
+            }]
+        );
+
+        let opts = MergeOptions {
+            duplicate_definition_checks: vec![String::from("MENUDEF")],
+            ..MergeOptions::default()
+        };
+        let (_, report) = Wad::merge(&inputs, &opts).unwrap();
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn rename_hooks_prefix_one_inputs_lumps() {
+        let first = wad(&[("CREDIT", b"first")]);
+        let mut second = wad(&[
+            ("CREDIT", b"second"),
+            ("F_START", b""),
+            ("FLOOR1", b""),
+            ("F_END", b""),
+        ]);
+        add_map(&mut second, "MAP01");
+        let prefix = |prefix: &'static str| MergeOptions {
+            rename_hooks: vec![RenameHook {
+                source: 1,
+                rename: Arc::new(move |name| Some(format!("{prefix}{name}"))),
+            }],
+            ..MergeOptions::default()
+        };
+        let inputs = [first, second];
+        let (merged, report) = Wad::merge(&inputs, &prefix("B")).unwrap();
+        assert!(report.warnings.is_empty());
+        let names = merged
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names[..8],
+            ["CREDIT", "BCREDIT", "F_START", "BFLOOR1", "F_END", "MAP01", "THINGS", "LINEDEFS"]
+        );
+        assert_eq!(merged.lump("CREDIT").unwrap().data, b"first");
+        assert_eq!(merged.lump("BCREDIT").unwrap().data, b"second");
+        assert!(matches!(
+            Wad::merge(&inputs, &prefix("NEW")),
+            Err(WadError::BadLumpName(name)) if name == "NEWCREDIT"
+        ));
+    }
+
+    #[test]
+    fn gus_configs_are_merged_by_instrument() {
+        let inputs = [
+            wad(&[
+                (
+                    "DMXGUS",
+                    b"# base\r\n0, 2, 1, 0, acpiano\r\n1, 2, 1, 0, britepno\r\n",
+                ),
+                ("DMXGUSC", b"128, 0, 0, 0, kick1\n"),
+            ]),
+            wad(&[
+                ("DMXGUS", b"1, 5, 5, 5, honky\n2, 1, 1, 1, synpiano\n"),
+                ("DMXGUSC", b"128, 3, 3, 3, kick2\n"),
+            ]),
+        ];
+        let (merged, report) = Wad::merge(&inputs, &MergeOptions::default()).unwrap();
+        assert_eq!(
+            merged.lump("DMXGUS").unwrap().data,
+            b"# base\r\n0, 2, 1, 0, acpiano\r\n1, 5, 5, 5, honky\r\n2, 1, 1, 1, synpiano\r\n"
+        );
+        assert_eq!(
+            merged.lump("DMXGUSC").unwrap().data,
+            b"128, 3, 3, 3, kick2\n"
+        );
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn hires_blocks_merge_into_one() {
+        let png = |tag: u8| [&b"\x89PNG\r\n\x1a\n"[..], &[tag; 8]].concat();
+        let (first, second, replaced) = (png(1), png(2), png(3));
+        let inputs = [
+            wad(&[
+                ("HI_START", b""),
+                ("STARTAN2", &first),
+                ("FLOOR0_1", &replaced),
+                ("HI_END", b""),
+            ]),
+            wad(&[
+                ("HI_START", b""),
+                ("BIGDOOR1", &second),
+                ("FLOOR0_1", &second),
+                ("HI_END", b""),
+            ]),
+        ];
+        let (merged, _) = Wad::merge(&inputs, &MergeOptions::default()).unwrap();
+        let lumps = merged
+            .lumps
+            .iter()
+            .map(|l| (l.name.as_str(), l.data.as_slice()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lumps,
+            [
+                ("HI_START", &b""[..]),
+                ("STARTAN2", &first),
+                ("BIGDOOR1", &second),
+                // the replacement takes the place of the later one
+                ("FLOOR0_1", &second),
+                ("HI_END", b""),
+            ]
+        );
+    }
+
+    #[test]
+    fn dehacked_changes_to_placed_things_are_reported() {
+        let mut first = wad(&[("DEHACKED", b"Thing 2 (Trooper)\nHit points = 40\n")]);
+        add_map_with_things(&mut first, "MAP01", &[thing(3004, 7), thing(3001, 7)]);
+        let mut second = wad(&[("DEHACKED", b"Thing 2 (Trooper)\nHit points = 100\n")]);
+        add_map_with_things(&mut second, "MAP01", &[thing(3004, 7)]);
+
+        let (_, report) = Wad::merge(&[first, second], &MergeOptions::default()).unwrap();
+        let changed = report
+            .warnings
+            .into_iter()
+            .filter(|w| matches!(w, MergeWarning::DehackedThingChanged { .. }))
+            .collect::<Vec<_>>();
+        // the second input's patch wins, so only the first input's zombiemen change
+        assert_eq!(
+            changed,
+            [MergeWarning::DehackedThingChanged {
+                map: String::from("MAP01"),
+                input: 0,
+                kind: 3004,
+            }]
+        );
+    }
+
+    #[test]
+    fn patch_names_differing_in_case_are_one_patch() {
+        let (first_pnames, first_texture1) = texture_lumps(&[("BRICK", &["WALL03_7"])]);
+        let (second_pnames, second_texture1) = texture_lumps(&[("STONE", &["wall03_7", "WALL04"])]);
+        let inputs = [
+            wad(&[("PNAMES", &first_pnames), ("TEXTURE1", &first_texture1)]),
+            wad(&[("PNAMES", &second_pnames), ("TEXTURE1", &second_texture1)]),
+        ];
+        let (merged, _) = Wad::merge(&inputs, &MergeOptions::default()).unwrap();
+        let data = &merged.lump("PNAMES").unwrap().data;
+        assert_eq!(data[..4], 2i32.to_le_bytes());
+        assert_eq!(data.len(), 4 + 2 * 8);
+        let pnames = parse_pnames(data).unwrap();
+        assert_eq!(pnames, ["WALL03_7", "WALL04"]);
+        let textures = parse_textures(&merged.lump("TEXTURE1").unwrap().data, &pnames).unwrap();
+        assert_eq!(
+            textures,
+            [
+                texture("BRICK", &["WALL03_7"]),
+                texture("STONE", &["WALL03_7", "WALL04"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_resources_drops_the_maps() {
+        let (first_pnames, first_texture1) = texture_lumps(&[("BRICK", &["WALL00"])]);
+        let (second_pnames, second_texture1) = texture_lumps(&[("STONE", &["WALL01"])]);
+        let mut first = wad(&[
+            ("PNAMES", &first_pnames),
+            ("TEXTURE1", &first_texture1),
+            ("F_START", b""),
+            ("FLOOR0_1", &[1; 4096]),
+            ("F_END", b""),
+        ]);
+        add_map(&mut first, "MAP01");
+        let mut second = wad(&[
+            ("PNAMES", &second_pnames),
+            ("TEXTURE1", &second_texture1),
+            ("FF_START", b""),
+            ("FLOOR0_2", &[2; 4096]),
+            ("FF_END", b""),
+        ]);
+        add_map(&mut second, "MAP01");
+        let opts = MergeOptions {
+            resources_only: true,
+            ..MergeOptions::default()
+        };
+
+        let (merged, _) = Wad::merge(&[first, second], &opts).unwrap();
+        assert!(merged.maps().is_empty());
+        assert!(merged.lump("THINGS").is_none());
+        assert!(merged.lump("MAPINFO").is_none());
+        let names = merged
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .filter(|name| !name.contains("TEXTURE") && *name != "PNAMES")
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["F_START", "FLOOR0_1", "FLOOR0_2", "F_END"]);
+        let pnames = parse_pnames(&merged.lump("PNAMES").unwrap().data).unwrap();
+        assert_eq!(pnames, ["WALL00", "WALL01"]);
+        let textures = parse_textures(&merged.lump("TEXTURE1").unwrap().data, &pnames).unwrap();
+        assert_eq!(
+            textures,
+            [texture("BRICK", &["WALL00"]), texture("STONE", &["WALL01"])]
+        );
+    }
+
+    #[test]
+    fn strings_set_twice_for_a_language_are_reported() {
+        let inputs = [
+            wad(&[("LANGUAGE", b"[enu default]\nHUSTR_1 = \"Entryway\";\n")]),
+            wad(&[(
+                "LANGUAGE",
+                b"[fra]\nHUSTR_1 = \"Entree\";\n[enu]\nhustr_1 = \"Gate\";\n",
+            )]),
+        ];
+        let (merged, report) = Wad::merge(&inputs, &MergeOptions::default()).unwrap();
+        assert_eq!(
+            merged.lump("LANGUAGE").unwrap().data,
+            b"[enu default]\nHUSTR_1 = \"Entryway\";\n\
+              [fra]\nHUSTR_1 = \"Entree\";\n[enu]\nhustr_1 = \"Gate\";\n"
+        );
+        assert_eq!(
+            report.warnings,
+            [MergeWarning::DuplicateString {
+                language: String::from("enu"),
+                key: String::from("hustr_1"),
+            }]
+        );
+    }
+
+    #[test]
+    fn generated_level_names_say_where_maps_came_from() {
+        let mut first = Wad::new_empty(false);
+        add_map(&mut first, "MAP01");
+        let mut second = wad(&[("MAPINFO", b"map MAP01 \"Gate\"\n")]);
+        add_map(&mut second, "MAP01");
+        let inputs = [first, second];
+        let level_names = |opts: &MergeOptions| {
+            let (merged, _) = Wad::merge(&inputs, opts).unwrap();
+            merged
+                .mapinfo()
+                .into_iter()
+                .map(|e| e.name.unwrap())
+                .collect::<Vec<_>>()
+        };
+        let opts = MergeOptions {
+            generate_mapinfo: true,
+            input_names: vec![String::from("first.wad")],
+            ..MergeOptions::default()
+        };
+        assert_eq!(
+            level_names(&opts),
+            ["MAP01: was first.wad/MAP01", "MAP02: was input 1/MAP01"]
+        );
+        let opts = MergeOptions {
+            level_name_format: Some(String::from("{name}")),
+            ..opts
+        };
+        assert_eq!(level_names(&opts), ["MAP01", "Gate"]);
+        let opts = MergeOptions {
+            level_name_format: None,
+            ..opts
+        };
+        assert_eq!(level_names(&opts), ["MAP01", "MAP02"]);
+    }
+}
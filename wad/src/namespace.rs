@@ -0,0 +1,209 @@
+use std::ops::Range;
+
+use crate::Lump;
+use crate::Wad;
+
+/// A kind of resource which lives between a pair of marker lumps, like flats between `F_START`
+/// and `F_END`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Namespace {
+    Flats,
+    Sprites,
+    /// Wall patches, which textures are assembled from.
+    Patches,
+    /// High resolution replacements for textures and graphics, usually PNGs, used by GZDoom.
+    Hires,
+}
+
+impl Namespace {
+    pub const ALL: &'static [Namespace] = &[Self::Flats, Self::Sprites, Self::Patches, Self::Hires];
+
+    /// The canonical start and end markers.
+    pub fn markers(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Flats => ("F_START", "F_END"),
+            Self::Sprites => ("S_START", "S_END"),
+            Self::Patches => ("P_START", "P_END"),
+            Self::Hires => ("HI_START", "HI_END"),
+        }
+    }
+
+    /// The marker prefixes in use. PWADs often double the letter (`FF_START`) so the IWAD's own
+    /// namespace is extended rather than replaced by vanilla tools.
+    fn prefixes(self) -> &'static [&'static str] {
+        match self {
+            Self::Flats => &["F", "FF"],
+            Self::Sprites => &["S", "SS"],
+            Self::Patches => &["P", "PP"],
+            Self::Hires => &["HI"],
+        }
+    }
+
+    fn opens(self, name: &str) -> bool {
+        name.strip_suffix("_START")
+            .is_some_and(|prefix| self.prefixes().contains(&prefix))
+    }
+
+    fn closes(self, name: &str) -> bool {
+        name.strip_suffix("_END")
+            .is_some_and(|prefix| self.prefixes().contains(&prefix))
+    }
+}
+
+/// A block of lumps between a pair of namespace markers. See [`Wad::namespaces`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceBlock {
+    /// The kind of block, or `None` for a namespace smoosh doesn't know, like `TX_START`.
+    pub namespace: Option<Namespace>,
+    /// The prefix of the block's start marker, like `FF` for `FF_START`.
+    pub prefix: String,
+    /// The indices of the block's lumps, from the start marker through the end marker.
+    pub range: Range<usize>,
+}
+
+/// Whether a lump is a namespace marker of any kind, including the numbered sub-namespace markers
+/// IWADs use (`F1_START`, `P2_END`, ...).
+pub(crate) fn is_marker(name: &str) -> bool {
+    name.ends_with("_START") || name.ends_with("_END")
+}
+
+/// Finds the blocks of the given namespaces in a sequence of lump names. Each range covers the
+/// start marker through the end marker. An unterminated block runs to the end.
+pub(crate) fn namespace_blocks(
+    names: &[&str],
+    namespaces: &[Namespace],
+) -> Vec<(Namespace, Range<usize>)> {
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < names.len() {
+        let Some(&namespace) = namespaces.iter().find(|ns| ns.opens(names[i])) else {
+            i += 1;
+            continue;
+        };
+        let end = names[i + 1..]
+            .iter()
+            .position(|name| namespace.closes(name))
+            .map_or(names.len(), |pos| i + 1 + pos + 1);
+        blocks.push((namespace, i..end));
+        i = end;
+    }
+    blocks
+}
+
+impl Wad {
+    /// Lists the namespace blocks in the WAD, in directory order. Markers nested inside a block,
+    /// like the `P1_START` IWADs have inside `P_START`, belong to the outer block. An
+    /// unterminated block runs to the end of the WAD.
+    pub fn namespaces(&self) -> Vec<NamespaceBlock> {
+        let mut blocks = Vec::new();
+        let mut i = 0;
+        while i < self.lumps.len() {
+            let Some(prefix) = self.lumps[i].name.strip_suffix("_START") else {
+                i += 1;
+                continue;
+            };
+            let namespace = Namespace::ALL
+                .iter()
+                .copied()
+                .find(|ns| ns.opens(&self.lumps[i].name));
+            let closes = |name: &str| match namespace {
+                Some(namespace) => namespace.closes(name),
+                None => name.strip_suffix("_END") == Some(prefix),
+            };
+            let end = self.lumps[i + 1..]
+                .iter()
+                .position(|lump| closes(&lump.name))
+                .map_or(self.lumps.len(), |pos| i + 1 + pos + 1);
+            blocks.push(NamespaceBlock {
+                namespace,
+                prefix: prefix.to_string(),
+                range: i..end,
+            });
+            i = end;
+        }
+        blocks
+    }
+
+    /// Copies the lumps of every `namespace` block into a new WAD, in a single block between the
+    /// namespace's canonical markers. Markers nested inside the blocks are left out. The new WAD
+    /// is empty if there are no such lumps.
+    pub fn extract_namespace(&self, namespace: Namespace) -> Wad {
+        let lumps = self
+            .namespaces()
+            .into_iter()
+            .filter(|block| block.namespace == Some(namespace))
+            .flat_map(|block| &self.lumps[block.range])
+            .filter(|lump| !is_marker(&lump.name))
+            .collect::<Vec<_>>();
+        let mut wad = Wad::with_capacity(lumps.len() + 2);
+        if lumps.is_empty() {
+            return wad;
+        }
+        let (start, end) = namespace.markers();
+        wad.add_lump(Lump {
+            name: start.to_string(),
+            data: Vec::new(),
+        });
+        for lump in lumps {
+            wad.add_lump(lump.clone());
+        }
+        wad.add_lump(Lump {
+            name: end.to_string(),
+            data: Vec::new(),
+        });
+        wad
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(names: &[&str]) -> Wad {
+        let mut wad = Wad::new_empty(false);
+        for name in names {
+            wad.append_lump_bytes(*name, Vec::new()).unwrap();
+        }
+        wad
+    }
+
+    #[test]
+    fn namespaces_are_listed_with_their_ranges() {
+        let wad = named(&[
+            "PLAYPAL", "F_START", "FLOOR0_1", "F_END", "SS_START", "TROOA1", "SS_END", "P_START",
+            "P1_START", "WALL00_1", "P1_END", "P_END", "TX_START", "BRICK", "TX_END", "HI_START",
+            "STARTAN2",
+        ]);
+        let blocks = wad
+            .namespaces()
+            .into_iter()
+            .map(|block| (block.namespace, block.prefix, block.range))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            [
+                (Some(Namespace::Flats), String::from("F"), 1..4),
+                (Some(Namespace::Sprites), String::from("SS"), 4..7),
+                // the nested P1 block is part of the outer one
+                (Some(Namespace::Patches), String::from("P"), 7..12),
+                (None, String::from("TX"), 12..15),
+                (Some(Namespace::Hires), String::from("HI"), 15..17),
+            ]
+        );
+    }
+
+    #[test]
+    fn namespace_blocks_are_extracted_into_one() {
+        let wad = named(&[
+            "F_START", "FLOOR1", "F_END", "PLAYPAL", "FF_START", "F1_START", "FLOOR2", "F1_END",
+            "FF_END", "S_START", "TROOA1", "S_END",
+        ]);
+        let names = |wad: &Wad| wad.lumps.iter().map(|l| l.name.clone()).collect::<Vec<_>>();
+        let flats = wad.extract_namespace(Namespace::Flats);
+        assert_eq!(names(&flats), ["F_START", "FLOOR1", "FLOOR2", "F_END"]);
+        let sprites = wad.extract_namespace(Namespace::Sprites);
+        assert_eq!(names(&sprites), ["S_START", "TROOA1", "S_END"]);
+        assert!(wad.extract_namespace(Namespace::Patches).lumps.is_empty());
+    }
+}
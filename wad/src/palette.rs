@@ -0,0 +1,99 @@
+use crate::Lump;
+use crate::WadError;
+use crate::WadResult;
+
+/// The pixel size of each color's square in [`palette_to_png`].
+pub const DEFAULT_SWATCH_SIZE: u32 = 8;
+
+/// The largest swatch size [`palette_to_png_with_swatch_size`] accepts, which makes a 4096×4096
+/// image.
+pub const MAX_SWATCH_SIZE: u32 = 256;
+
+/// A 256-color palette, as stored in PLAYPAL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette(pub [[u8; 3]; 256]);
+
+impl Palette {
+    /// Reads the first palette of a PLAYPAL lump. The remaining palettes (damage and pickup
+    /// tints) are ignored.
+    pub fn from_lump(lump: &Lump) -> WadResult<Self> {
+        if lump.data.len() < 768 {
+            return Err(WadError::InvalidLumpSize {
+                name: lump.name.clone(),
+                size: lump.data.len(),
+            });
+        }
+        let mut colors = [[0; 3]; 256];
+        for (color, rgb) in colors.iter_mut().zip(lump.data.chunks_exact(3)) {
+            color.copy_from_slice(rgb);
+        }
+        Ok(Palette(colors))
+    }
+
+    /// The index of the color closest to `rgb`.
+    pub fn nearest(&self, rgb: [u8; 3]) -> u8 {
+        let distance = |color: &[u8; 3]| {
+            color
+                .iter()
+                .zip(rgb)
+                .map(|(&a, b)| (a as i32 - b as i32).pow(2))
+                .sum::<i32>()
+        };
+        (0..=255)
+            .min_by_key(|&i| distance(&self.0[i as usize]))
+            .unwrap()
+    }
+}
+
+/// Renders the palette as a PNG of 16×16 color swatches, read left to right, top to bottom.
+pub fn palette_to_png(palette: &Palette) -> Vec<u8> {
+    palette_to_png_with_swatch_size(palette, DEFAULT_SWATCH_SIZE)
+        .expect("the default swatch size is in range")
+}
+
+/// Like [`palette_to_png`], with each swatch `swatch_size` pixels across. Fails unless the size
+/// is between 1 and [`MAX_SWATCH_SIZE`].
+pub fn palette_to_png_with_swatch_size(palette: &Palette, swatch_size: u32) -> WadResult<Vec<u8>> {
+    if !(1..=MAX_SWATCH_SIZE).contains(&swatch_size) {
+        return Err(WadError::InvalidSwatchSize(swatch_size));
+    }
+    let side = 16 * swatch_size;
+    let mut pixels = Vec::with_capacity((side * side * 3) as usize);
+    for y in 0..side {
+        for x in 0..side {
+            let index = (y / swatch_size) * 16 + x / swatch_size;
+            pixels.extend_from_slice(&palette.0[index as usize]);
+        }
+    }
+    Ok(encode_png(side, side, png::ColorType::Rgb, &pixels))
+}
+
+/// Encodes 8-bit pixel data as a PNG.
+pub(crate) fn encode_png(width: u32, height: u32, color: png::ColorType, pixels: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    // writing into memory can't fail, and the pixel data always matches the dimensions
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(pixels).unwrap();
+    writer.finish().unwrap();
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swatch_sizes_out_of_range_are_an_error() {
+        let palette = Palette([[0; 3]; 256]);
+        for size in [0, MAX_SWATCH_SIZE + 1, u32::MAX] {
+            assert!(matches!(
+                palette_to_png_with_swatch_size(&palette, size),
+                Err(WadError::InvalidSwatchSize(_))
+            ));
+        }
+        assert!(palette_to_png_with_swatch_size(&palette, 1).is_ok());
+    }
+}
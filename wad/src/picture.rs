@@ -0,0 +1,554 @@
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+
+use crate::palette::encode_png;
+use crate::Lump;
+use crate::Palette;
+use crate::Wad;
+use crate::WadError;
+use crate::WadResult;
+
+/// The size of the picture header: width, height and the two offsets.
+const PICTURE_HEADER_SIZE: usize = 8;
+
+/// Ends a column's list of posts.
+const END_OF_COLUMN: u8 = 0xFF;
+
+/// The longest post written. Posts store their length in a byte.
+const MAX_POST_LENGTH: u16 = 254;
+
+/// The largest top delta a post can have, since a delta of 255 ends the column.
+const MAX_DELTA: i32 = 254;
+
+/// A picture in Doom's column-based patch format, used for sprites, wall patches and menu
+/// graphics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Picture {
+    pub width: u16,
+    pub height: u16,
+    /// How far left of the sprite's origin the picture's left edge is drawn.
+    pub left_offset: i16,
+    /// How far above the sprite's origin the picture's top edge is drawn.
+    pub top_offset: i16,
+    /// Palette indices in rows from the top, with `None` for transparent pixels.
+    pub pixels: Vec<Option<u8>>,
+}
+
+/// A rectangular area of a picture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Picture {
+    /// Creates a fully transparent picture.
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            left_offset: 0,
+            top_offset: 0,
+            pixels: vec![None; width as usize * height as usize],
+        }
+    }
+
+    /// Replaces the picture's offsets.
+    pub fn with_offset(self, left_offset: i16, top_offset: i16) -> Self {
+        Self {
+            left_offset,
+            top_offset,
+            ..self
+        }
+    }
+
+    /// Sets the offsets so the picture is drawn centered on its origin.
+    pub fn center(self) -> Self {
+        let (x, y) = (self.width / 2, self.height / 2);
+        self.with_offset(x as i16, y as i16)
+    }
+
+    /// The pixel at the given column and row.
+    pub fn pixel(&self, x: u16, y: u16) -> Option<u8> {
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+
+    /// The pixel at the given column and row, or `None` outside the picture.
+    fn get(&self, x: usize, y: usize) -> Option<u8> {
+        if x < self.width as usize && y < self.height as usize {
+            self.pixels[y * self.width as usize + x]
+        } else {
+            None
+        }
+    }
+
+    /// Paints `other`'s opaque pixels over this picture, with its top left corner at `(x, y)`.
+    /// Whatever falls outside this picture is cut off.
+    pub fn overlay(&mut self, other: &Picture, x: i32, y: i32) {
+        for oy in 0..other.height as i32 {
+            for ox in 0..other.width as i32 {
+                let (tx, ty) = (x + ox, y + oy);
+                if !(0..self.width as i32).contains(&tx) || !(0..self.height as i32).contains(&ty) {
+                    continue;
+                }
+                if let Some(pixel) = other.pixel(ox as u16, oy as u16) {
+                    self.pixels[ty as usize * self.width as usize + tx as usize] = Some(pixel);
+                }
+            }
+        }
+    }
+
+    /// Mixes two pictures evenly, lined up by their top left corners. Where both are opaque,
+    /// the colors are averaged and matched back to the palette; elsewhere, whichever is opaque
+    /// shows through. The result is big enough to hold both, and keeps this picture's offsets.
+    pub fn blend_average(&self, other: &Picture, palette: &Palette) -> Picture {
+        let mut out = Picture::new(self.width.max(other.width), self.height.max(other.height))
+            .with_offset(self.left_offset, self.top_offset);
+        for y in 0..out.height as usize {
+            for x in 0..out.width as usize {
+                out.pixels[y * out.width as usize + x] = match (self.get(x, y), other.get(x, y)) {
+                    (Some(a), Some(b)) => {
+                        let (a, b) = (palette.0[a as usize], palette.0[b as usize]);
+                        let mix = |i: usize| ((a[i] as u16 + b[i] as u16) / 2) as u8;
+                        Some(palette.nearest([mix(0), mix(1), mix(2)]))
+                    }
+                    (a, b) => a.or(b),
+                };
+            }
+        }
+        out
+    }
+
+    /// Cuts out part of the picture. The part of `rect` outside the picture is ignored. The
+    /// offsets are adjusted so what's left is drawn where it was.
+    pub fn crop(&self, rect: Rect) -> Picture {
+        let x = rect.x.min(self.width);
+        let y = rect.y.min(self.height);
+        let width = rect.width.min(self.width - x);
+        let height = rect.height.min(self.height - y);
+        let mut out = Picture::new(width, height).with_offset(
+            self.left_offset.saturating_sub(x as i16),
+            self.top_offset.saturating_sub(y as i16),
+        );
+        for row in 0..height {
+            for col in 0..width {
+                out.pixels[row as usize * width as usize + col as usize] =
+                    self.pixel(x + col, y + row);
+            }
+        }
+        out
+    }
+
+    /// Scales the picture to a new size, taking each pixel from the nearest one in the original.
+    /// The offsets are scaled along with it.
+    pub fn resize_nearest(&self, width: u16, height: u16) -> Picture {
+        let scale = |offset: i16, new: u16, old: u16| {
+            (offset as i32 * new as i32 / (old as i32).max(1)) as i16
+        };
+        let mut out = Picture::new(width, height).with_offset(
+            scale(self.left_offset, width, self.width),
+            scale(self.top_offset, height, self.height),
+        );
+        if self.width == 0 || self.height == 0 {
+            return out;
+        }
+        for y in 0..height as usize {
+            let src_y = y * self.height as usize / height as usize;
+            for x in 0..width as usize {
+                let src_x = x * self.width as usize / width as usize;
+                out.pixels[y * width as usize + x] = self.get(src_x, src_y);
+            }
+        }
+        out
+    }
+
+    /// Mirrors the picture left to right, the way the engine draws a sprite frame's mirrored
+    /// rotations. The left offset is mirrored about the origin too, so the flipped picture
+    /// stays lined up with it: a picture drawn `left_offset` pixels left of the origin ends
+    /// `width - left_offset` pixels left of it once flipped.
+    pub fn flip_horizontal(&self) -> Picture {
+        let mut out = Picture::new(self.width, self.height).with_offset(
+            (self.width as i16).wrapping_sub(self.left_offset),
+            self.top_offset,
+        );
+        if self.width == 0 {
+            return out;
+        }
+        let width = self.width as usize;
+        for (src, dest) in self.pixels.chunks(width).zip(out.pixels.chunks_mut(width)) {
+            for (s, d) in src.iter().rev().zip(dest) {
+                *d = *s;
+            }
+        }
+        out
+    }
+
+    /// Lays pictures out in a grid, `cols` to a row, from left to right and top to bottom. Every
+    /// cell is as big as the largest picture, with each picture in the top left of its cell.
+    /// Fails if the grid is wider or taller than a picture can be.
+    pub fn tile(pictures: &[Picture], cols: usize) -> WadResult<Picture> {
+        let cols = cols.max(1);
+        let rows = pictures.len().div_ceil(cols);
+        let cell_width = pictures.iter().map(|p| p.width).max().unwrap_or(0) as usize;
+        let cell_height = pictures.iter().map(|p| p.height).max().unwrap_or(0) as usize;
+        let width = cell_width.saturating_mul(cols.min(pictures.len()));
+        let height = cell_height.saturating_mul(rows);
+        let (Ok(out_width), Ok(out_height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(WadError::PictureTooLarge { width, height });
+        };
+        let mut out = Picture::new(out_width, out_height);
+        for (i, picture) in pictures.iter().enumerate() {
+            let x = (i % cols * cell_width) as i32;
+            let y = (i / cols * cell_height) as i32;
+            out.overlay(picture, x, y);
+        }
+        Ok(out)
+    }
+}
+
+/// Reads a picture's width and height and where each of its columns starts, checking that the
+/// column offsets are all there, and that every column starts after them and within the data.
+fn picture_columns(data: &[u8]) -> WadResult<(u16, u16, Vec<usize>)> {
+    let header = data
+        .get(..PICTURE_HEADER_SIZE)
+        .ok_or(WadError::UnexpectedEof)?;
+    let width = LittleEndian::read_u16(&header[0..]);
+    let height = LittleEndian::read_u16(&header[2..]);
+    let columns_end = PICTURE_HEADER_SIZE + width as usize * 4;
+    let offsets = data
+        .get(PICTURE_HEADER_SIZE..columns_end)
+        .ok_or(WadError::UnexpectedEof)?
+        .chunks_exact(4)
+        .map(|c| LittleEndian::read_u32(c) as usize)
+        .collect::<Vec<_>>();
+    if let Some((column, &offset)) = offsets
+        .iter()
+        .enumerate()
+        .find(|(_, offset)| !(columns_end..data.len()).contains(offset))
+    {
+        return Err(WadError::InvalidPictureColumn { column, offset });
+    }
+    Ok((width, height, offsets))
+}
+
+/// Decodes a picture lump. A post whose top delta doesn't go past the previous post's top row is
+/// read as relative to it, following the convention for pictures taller than 255 pixels.
+pub fn decode_picture(data: &[u8]) -> WadResult<Picture> {
+    // the column offsets are checked before the pixels are allocated, since any lump's first
+    // bytes can pass for a huge width and height
+    let (width, height, offsets) = picture_columns(data)?;
+    let mut pixels = vec![None; width as usize * height as usize];
+    for (x, offset) in offsets.into_iter().enumerate() {
+        let mut pos = offset;
+        let mut top = -1;
+        loop {
+            let delta = *data.get(pos).ok_or(WadError::UnexpectedEof)?;
+            if delta == END_OF_COLUMN {
+                break;
+            }
+            let delta = delta as i32;
+            top = if delta <= top { top + delta } else { delta };
+            let length = *data.get(pos + 1).ok_or(WadError::UnexpectedEof)? as usize;
+            // the post's pixels sit between two unused padding bytes
+            let post = data
+                .get(pos + 3..pos + 3 + length)
+                .ok_or(WadError::UnexpectedEof)?;
+            for (y, &pixel) in (top as usize..).zip(post) {
+                if y >= height as usize {
+                    return Err(WadError::InvalidPicture);
+                }
+                pixels[y * width as usize + x] = Some(pixel);
+            }
+            pos += 4 + length;
+        }
+    }
+    Ok(Picture {
+        width,
+        height,
+        left_offset: LittleEndian::read_i16(&data[4..]),
+        top_offset: LittleEndian::read_i16(&data[6..]),
+        pixels,
+    })
+}
+
+/// Writes the start of a post beginning at row `y`, giving its top delta. Rows past 254 can
+/// only be reached relative to the previous post, so empty posts are added as stepping stones
+/// where needed.
+fn post_delta(out: &mut Vec<u8>, top: &mut i32, y: i32) -> u8 {
+    loop {
+        // the furthest a relative delta can reach
+        let reach = (*top).min(MAX_DELTA);
+        let target = if y <= MAX_DELTA || y - *top <= reach {
+            y
+        } else if *top < MAX_DELTA {
+            MAX_DELTA
+        } else {
+            *top + reach
+        };
+        let delta = if target <= MAX_DELTA {
+            target
+        } else {
+            target - *top
+        };
+        *top = target;
+        if target == y {
+            return delta as u8;
+        }
+        out.extend_from_slice(&[delta as u8, 0, 0, 0]);
+    }
+}
+
+/// Encodes a picture lump.
+pub fn encode_picture(picture: &Picture) -> Vec<u8> {
+    let width = picture.width as usize;
+    let mut out = Vec::new();
+    out.extend_from_slice(&picture.width.to_le_bytes());
+    out.extend_from_slice(&picture.height.to_le_bytes());
+    out.extend_from_slice(&picture.left_offset.to_le_bytes());
+    out.extend_from_slice(&picture.top_offset.to_le_bytes());
+    out.resize(PICTURE_HEADER_SIZE + width * 4, 0);
+    for x in 0..picture.width {
+        let offset = out.len() as u32;
+        LittleEndian::write_u32(&mut out[PICTURE_HEADER_SIZE + x as usize * 4..], offset);
+        let mut top = -1;
+        let mut y = 0;
+        while y < picture.height {
+            if picture.pixel(x, y).is_none() {
+                y += 1;
+                continue;
+            }
+            let start = y;
+            while y < picture.height && y - start < MAX_POST_LENGTH && picture.pixel(x, y).is_some()
+            {
+                y += 1;
+            }
+            let delta = post_delta(&mut out, &mut top, start as i32);
+            out.extend_from_slice(&[delta, (y - start) as u8, 0]);
+            out.extend((start..y).map(|y| picture.pixel(x, y).unwrap()));
+            out.push(0);
+        }
+        out.push(END_OF_COLUMN);
+    }
+    out
+}
+
+/// Renders a picture as a PNG in the palette's colors, with transparent pixels left transparent.
+pub fn picture_to_png(picture: &Picture, palette: &Palette) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(picture.pixels.len() * 4);
+    for pixel in &picture.pixels {
+        match pixel {
+            Some(index) => {
+                pixels.extend_from_slice(&palette.0[*index as usize]);
+                pixels.push(u8::MAX);
+            }
+            None => pixels.extend_from_slice(&[0; 4]),
+        }
+    }
+    encode_png(
+        picture.width as u32,
+        picture.height as u32,
+        png::ColorType::Rgba,
+        &pixels,
+    )
+}
+
+/// The width and height from a picture's header, if the data looks like a picture: the header
+/// and column offsets are all there, every column starts after them and within the data, and
+/// neither dimension is zero or negative when read as signed. The columns themselves aren't
+/// decoded.
+fn picture_dimensions(data: &[u8]) -> Option<(u16, u16)> {
+    let (width, height, _) = picture_columns(data).ok()?;
+    let valid = 1..=i16::MAX as u16;
+    (valid.contains(&width) && valid.contains(&height)).then_some((width, height))
+}
+
+impl Lump {
+    /// Decodes the lump as a picture.
+    pub fn picture(&self) -> WadResult<Picture> {
+        decode_picture(&self.data)
+    }
+}
+
+impl Wad {
+    /// The width and height of a graphic, read from its header without decoding it. Gives
+    /// `None` if there's no lump by that name or it doesn't look like a picture. Flats have no
+    /// header, so they give `None` too.
+    pub fn graphic_dimensions(&self, name: &str) -> Option<(u16, u16)> {
+        picture_dimensions(&self.lump(name)?.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width` by `height` picture with a transparent diagonal, each other pixel holding the
+    /// sum of its coordinates.
+    fn sample(width: u16, height: u16) -> Picture {
+        let mut picture = Picture::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                if x != y {
+                    picture.pixels[y as usize * width as usize + x as usize] = Some((x + y) as u8);
+                }
+            }
+        }
+        picture
+    }
+
+    #[test]
+    fn pictures_round_trip_with_their_offsets() {
+        for (picture, offsets) in [
+            (sample(5, 3), (-12, -100)),
+            (sample(3, 300), (i16::MIN, i16::MAX)),
+        ] {
+            let picture = picture.with_offset(offsets.0, offsets.1);
+            let decoded = decode_picture(&encode_picture(&picture)).unwrap();
+            assert_eq!(decoded, picture);
+            let reencoded = decode_picture(&encode_picture(&decoded)).unwrap();
+            assert_eq!((reencoded.left_offset, reencoded.top_offset), offsets);
+            assert_eq!(reencoded.pixels, picture.pixels);
+        }
+    }
+
+    #[test]
+    fn text_isnt_decoded_as_a_picture() {
+        // reads as a 26708x29545 picture, whose column offsets run past the end
+        let text = b"This is a text lump, not a picture.";
+        assert!(matches!(decode_picture(text), Err(WadError::UnexpectedEof)));
+        assert_eq!(picture_dimensions(text), None);
+        // long enough to hold the column offsets, which point far past the end
+        let text = text.repeat(4096);
+        assert!(matches!(
+            decode_picture(&text),
+            Err(WadError::InvalidPictureColumn { column: 0, .. })
+        ));
+        assert_eq!(picture_dimensions(&text), None);
+    }
+
+    /// A picture from rows of pixels, with `None` for transparent ones.
+    fn from_rows(rows: &[&[Option<u8>]]) -> Picture {
+        let mut picture = Picture::new(rows[0].len() as u16, rows.len() as u16);
+        picture.pixels = rows.concat();
+        picture
+    }
+
+    #[test]
+    fn overlays_paint_opaque_pixels_at_an_offset() {
+        let small = from_rows(&[&[Some(1), None], &[Some(2), Some(3)]]);
+        let mut big = Picture::new(4, 4);
+        big.pixels.fill(Some(9));
+        big.overlay(&small, 1, 2);
+        assert_eq!(big.pixel(1, 2), Some(1));
+        // transparent pixels leave what was underneath
+        assert_eq!(big.pixel(2, 2), Some(9));
+        assert_eq!(big.pixel(1, 3), Some(2));
+        assert_eq!(big.pixel(2, 3), Some(3));
+        assert_eq!(big.pixels.iter().filter(|&&p| p != Some(9)).count(), 3);
+        // anything off the edges is cut off
+        big.overlay(&small, -1, 2);
+        assert_eq!(big.pixel(0, 3), Some(3));
+        big.overlay(&small, 4, 4);
+        assert_eq!(big.pixels.iter().filter(|&&p| p != Some(9)).count(), 4);
+    }
+
+    #[test]
+    fn blending_pictures_of_different_sizes() {
+        let gray = Palette(std::array::from_fn(|i| [i as u8; 3]));
+        let wide = from_rows(&[&[Some(10), None]]).with_offset(3, 4);
+        let tall = from_rows(&[&[Some(30)], &[Some(50)]]);
+        let blended = wide.blend_average(&tall, &gray);
+        assert_eq!((blended.width, blended.height), (2, 2));
+        assert_eq!((blended.left_offset, blended.top_offset), (3, 4));
+        assert_eq!(blended.pixels, [Some(20), None, Some(50), None]);
+    }
+
+    #[test]
+    fn tiling_pictures_of_different_sizes() {
+        let pictures = [
+            from_rows(&[&[Some(1)]]),
+            from_rows(&[&[Some(2), Some(2)]]),
+            from_rows(&[&[Some(3)], &[Some(3)], &[Some(3)]]),
+        ];
+        let tiled = Picture::tile(&pictures, 2).unwrap();
+        assert_eq!((tiled.width, tiled.height), (4, 6));
+        assert_eq!(tiled.pixel(0, 0), Some(1));
+        assert_eq!(tiled.pixel(1, 0), None);
+        assert_eq!([tiled.pixel(2, 0), tiled.pixel(3, 0)], [Some(2); 2]);
+        assert_eq!(tiled.pixel(0, 5), Some(3));
+        assert_eq!(tiled.pixels.iter().flatten().count(), 6);
+        // a row of cells wider than a picture can be
+        let wide = [Picture::new(40_000, 1), Picture::new(1, 1)];
+        assert!(matches!(
+            Picture::tile(&wide, 2),
+            Err(WadError::PictureTooLarge {
+                width: 80_000,
+                height: 1
+            })
+        ));
+        assert_eq!(Picture::tile(&wide, 1).unwrap().height, 2);
+    }
+
+    #[test]
+    fn nearest_resizing_scales_pixels_and_offsets() {
+        let small = from_rows(&[&[Some(1), None], &[Some(2), Some(3)]]).with_offset(1, -2);
+        let big = small.resize_nearest(4, 4);
+        assert_eq!(
+            big,
+            from_rows(&[
+                &[Some(1), Some(1), None, None],
+                &[Some(1), Some(1), None, None],
+                &[Some(2), Some(2), Some(3), Some(3)],
+                &[Some(2), Some(2), Some(3), Some(3)],
+            ])
+            .with_offset(2, -4)
+        );
+        assert_eq!(big.resize_nearest(2, 2), small);
+        let cropped = big.crop(Rect {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 8,
+        });
+        assert_eq!(
+            cropped,
+            from_rows(&[&[Some(1), None], &[Some(2), Some(3)], &[Some(2), Some(3)]])
+                .with_offset(1, -5)
+        );
+    }
+
+    #[test]
+    fn flipping_mirrors_pixels_and_the_left_offset() {
+        let picture = sample(5, 3).with_offset(2, 7);
+        let flipped = picture.flip_horizontal();
+        assert_eq!(flipped.left_offset, 5 - 2);
+        assert_eq!(flipped.top_offset, 7);
+        for y in 0..3 {
+            for x in 0..5 {
+                assert_eq!(flipped.pixel(x, y), picture.pixel(4 - x, y));
+            }
+        }
+        assert_eq!(flipped.flip_horizontal(), picture);
+        let empty = Picture::new(0, 4);
+        assert_eq!(empty.flip_horizontal().flip_horizontal(), empty);
+    }
+
+    #[test]
+    fn graphic_dimensions_come_from_the_header() {
+        let mut wad = Wad::new_empty(false);
+        let titlepic = encode_picture(&Picture::new(320, 200));
+        wad.append_lump_bytes("TITLEPIC", titlepic).unwrap();
+        wad.append_lump_bytes("F_START", Vec::new()).unwrap();
+        wad.append_lump_bytes("FLOOR0_1", vec![0; 4096]).unwrap();
+        wad.append_lump_bytes("F_END", Vec::new()).unwrap();
+        // a header claiming more columns than the lump holds
+        wad.append_lump_bytes("TRUNC", vec![64, 0, 64, 0, 0, 0, 0, 0])
+            .unwrap();
+        assert_eq!(wad.graphic_dimensions("TITLEPIC"), Some((320, 200)));
+        assert_eq!(wad.graphic_dimensions("FLOOR0_1"), None);
+        assert_eq!(wad.graphic_dimensions("TRUNC"), None);
+        assert_eq!(wad.graphic_dimensions("MISSING"), None);
+    }
+}
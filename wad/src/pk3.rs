@@ -0,0 +1,292 @@
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::path::Path;
+
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+use zip::ZipArchive;
+use zip::ZipWriter;
+
+use crate::classify_lump;
+use crate::lumpdir::lump_name;
+use crate::namespace::is_marker;
+use crate::namespace::namespace_blocks;
+use crate::validate_name;
+use crate::Lump;
+use crate::LumpCategory;
+use crate::Namespace;
+use crate::Wad;
+use crate::WadError;
+use crate::WadResult;
+
+/// The folders of a pk3 whose files belong in a namespace.
+const NAMESPACE_FOLDERS: &[(&str, Namespace)] = &[
+    ("flats", Namespace::Flats),
+    ("sprites", Namespace::Sprites),
+    ("patches", Namespace::Patches),
+    ("hires", Namespace::Hires),
+];
+
+/// The folder of a pk3 holding one WAD per map.
+const MAPS_FOLDER: &str = "maps";
+
+/// Lump names may hold a backslash, which can't go in a file name, so pk3s use `^` instead.
+const BACKSLASH_STANDIN: char = '^';
+
+/// The file name a lump is stored under in a pk3.
+fn file_name(lump: &Lump) -> String {
+    let name = lump
+        .name
+        .to_ascii_lowercase()
+        .replace('\\', &BACKSLASH_STANDIN.to_string());
+    format!("{name}.{}", lump.guess_extension())
+}
+
+fn zip_error(e: zip::result::ZipError) -> WadError {
+    WadError::CouldntWriteLump(e.into())
+}
+
+impl Wad {
+    /// Opens a pk3, a zip archive of loose lump files. See [`Wad::from_pk3_reader`].
+    pub fn from_pk3<P: AsRef<Path>>(path: P) -> WadResult<Self> {
+        let f = File::open(path).map_err(WadError::CouldntReadHeader)?;
+        Self::from_pk3_reader(f)
+    }
+
+    /// Reads a pk3, turning each file into a lump named after it, like [`Wad::from_lump_dir`]
+    /// does. Files in `flats/`, `sprites/`, `patches/` and `hires/` go in that namespace's
+    /// block, and each WAD in `maps/` is added whole, after everything else. Files in any other
+    /// folder become loose lumps. Members may be stored, or compressed with deflate, bzip2 or
+    /// zstd; anything else is an error.
+    pub fn from_pk3_reader<R: Read + Seek>(r: R) -> WadResult<Self> {
+        let mut archive = ZipArchive::new(r).map_err(|e| WadError::CouldntReadHeader(e.into()))?;
+        let mut loose = Vec::new();
+        let mut namespaced = vec![Vec::new(); NAMESPACE_FOLDERS.len()];
+        let mut maps = Vec::new();
+        for i in 0..archive.len() {
+            let (path, method, is_dir) = {
+                let file = archive
+                    .by_index_raw(i)
+                    .map_err(|e| WadError::CouldntReadLump(e.into()))?;
+                (file.name().to_string(), file.compression(), file.is_dir())
+            };
+            if is_dir {
+                continue;
+            }
+            if !matches!(
+                method,
+                CompressionMethod::Stored
+                    | CompressionMethod::Deflated
+                    | CompressionMethod::Bzip2
+                    | CompressionMethod::Zstd
+            ) {
+                return Err(WadError::UnsupportedCompression {
+                    file: path,
+                    method: method.to_string(),
+                });
+            }
+            let mut data = Vec::new();
+            archive
+                .by_index(i)
+                .map_err(|e| WadError::CouldntReadLump(e.into()))?
+                .read_to_end(&mut data)
+                .map_err(WadError::CouldntReadLump)?;
+
+            let (folder, file_name) = match path.split_once('/') {
+                Some((folder, rest)) => (folder.to_ascii_lowercase(), rest),
+                None => (String::new(), path.as_str()),
+            };
+            let file_name = file_name.rsplit('/').next().unwrap();
+            if folder == MAPS_FOLDER && file_name.to_ascii_lowercase().ends_with(".wad") {
+                maps.push(Wad::from_bytes(&data)?);
+                continue;
+            }
+            let name = lump_name(file_name).replace(BACKSLASH_STANDIN, "\\");
+            validate_name(&name)?;
+            let lump = Lump { name, data };
+            match NAMESPACE_FOLDERS.iter().position(|(f, _)| *f == folder) {
+                Some(ns) => namespaced[ns].push(lump),
+                None => loose.push(lump),
+            }
+        }
+
+        let mut wad = Wad::new_empty(false);
+        for lump in loose {
+            wad.add_lump(lump);
+        }
+        for ((_, namespace), lumps) in NAMESPACE_FOLDERS.iter().zip(namespaced) {
+            if lumps.is_empty() {
+                continue;
+            }
+            let (start, end) = namespace.markers();
+            wad.add_lump(Lump {
+                name: start.to_string(),
+                data: Vec::new(),
+            });
+            for lump in lumps {
+                wad.add_lump(lump);
+            }
+            wad.add_lump(Lump {
+                name: end.to_string(),
+                data: Vec::new(),
+            });
+        }
+        for map in maps {
+            for lump in map.into_lumps() {
+                wad.add_lump(lump);
+            }
+        }
+        Ok(wad)
+    }
+
+    /// Writes the WAD as a pk3, laid out the way [`Wad::from_pk3`] reads one. Each map goes in
+    /// its own WAD in `maps/`, namespaced lumps go in their namespace's folder, music and sounds
+    /// go in `music/` and `sounds/`, and everything else sits at the root. File names are the
+    /// lump names in lower case, with an extension guessed from the contents.
+    pub fn write_pk3<P: AsRef<Path>>(&self, path: P) -> WadResult<()> {
+        let f = File::create(path).map_err(WadError::CouldntWriteHeader)?;
+        let f = self.write_pk3_to(f)?;
+        f.sync_all().map_err(WadError::CouldntSync)
+    }
+
+    /// Like [`Wad::write_pk3`], writing to any seekable destination, which is handed back.
+    pub fn write_pk3_to<W: Write + Seek>(&self, w: W) -> WadResult<W> {
+        let names = self
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        let mut folder_of = vec![None; names.len()];
+        let namespaces = NAMESPACE_FOLDERS
+            .iter()
+            .map(|(_, ns)| *ns)
+            .collect::<Vec<_>>();
+        for (namespace, range) in namespace_blocks(&names, &namespaces) {
+            let (folder, _) = NAMESPACE_FOLDERS
+                .iter()
+                .find(|(_, ns)| *ns == namespace)
+                .unwrap();
+            folder_of[range].fill(Some(*folder));
+        }
+        let maps = self.maps();
+        let mut in_map = vec![false; names.len()];
+        for map in &maps {
+            in_map[map.range.clone()].fill(true);
+        }
+
+        let mut zip = ZipWriter::new(w);
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (i, lump) in self.lumps.iter().enumerate() {
+            if in_map[i] || (folder_of[i].is_some() && is_marker(&lump.name)) {
+                continue;
+            }
+            let folder = folder_of[i].or(match classify_lump(lump) {
+                LumpCategory::Music => Some("music"),
+                LumpCategory::Sound => Some("sounds"),
+                _ => None,
+            });
+            let path = match folder {
+                Some(folder) => format!("{folder}/{}", file_name(lump)),
+                None => file_name(lump),
+            };
+            zip.start_file(path, options).map_err(zip_error)?;
+            zip.write_all(&lump.data)
+                .map_err(WadError::CouldntWriteLump)?;
+        }
+        for map in &maps {
+            let mut wad = Wad::with_capacity(map.lumps.len());
+            for lump in map.lumps {
+                wad.add_lump(lump.clone());
+            }
+            let path = format!("{MAPS_FOLDER}/{}.wad", map.name.to_ascii_lowercase());
+            zip.start_file(path, options).map_err(zip_error)?;
+            zip.write_all(&wad.write_to_vec()?)
+                .map_err(WadError::CouldntWriteLump)?;
+        }
+        zip.finish().map_err(zip_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn pk3(files: &[(&str, &[u8], CompressionMethod)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, data, method) in files {
+            let options = FileOptions::default().compression_method(*method);
+            zip.start_file(*path, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn compressed_members_are_read_uncompressed() {
+        let text = b"map MAP01 \"One\" { }\n".repeat(20);
+        let bytes = pk3(&[
+            ("mapinfo.txt", &text, CompressionMethod::Deflated),
+            ("decorate.txt", b"actor Thing { }", CompressionMethod::Bzip2),
+            ("playpal.lmp", &[7; 768], CompressionMethod::Stored),
+        ]);
+        let wad = Wad::from_pk3_reader(Cursor::new(bytes)).unwrap();
+        assert_eq!(wad.lump("MAPINFO").unwrap().data, text);
+        assert_eq!(wad.lump("DECORATE").unwrap().data, b"actor Thing { }");
+        assert_eq!(wad.lump("PLAYPAL").unwrap().data, [7; 768]);
+    }
+
+    #[test]
+    fn unsupported_compression_is_an_error() {
+        let mut bytes = pk3(&[("playpal.lmp", &[7; 768], CompressionMethod::Stored)]);
+        // mark the member as PPMd-compressed, in its central directory entry
+        let central = bytes.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        bytes[central + 10..central + 12].copy_from_slice(&98u16.to_le_bytes());
+        assert!(matches!(
+            Wad::from_pk3_reader(Cursor::new(bytes)),
+            Err(WadError::UnsupportedCompression { file, .. }) if file == "playpal.lmp"
+        ));
+    }
+
+    #[test]
+    fn written_pk3s_read_back_the_same() {
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in [
+            ("PLAYPAL", &[0; 768][..]),
+            ("D_RUNNIN", b"MUS\x1a\0\0"),
+            ("DSPISTOL", &[3, 0, 0x11, 0x2b, 1, 0, 0, 0, 0x80]),
+            ("S_START", b""),
+            ("TROOA1", b"sprite"),
+            ("VILE\\1", b"sprite"),
+            ("S_END", b""),
+            ("MAP01", b""),
+            ("THINGS", b"things"),
+        ] {
+            wad.append_lump_bytes(name, data.to_vec()).unwrap();
+        }
+
+        let bytes = wad
+            .write_pk3_to(Cursor::new(Vec::new()))
+            .unwrap()
+            .into_inner();
+        let archive = ZipArchive::new(Cursor::new(bytes.as_slice())).unwrap();
+        let mut paths = archive.file_names().collect::<Vec<_>>();
+        paths.sort_unstable();
+        assert_eq!(
+            paths,
+            [
+                "maps/map01.wad",
+                "music/d_runnin.mus",
+                "playpal.lmp",
+                "sounds/dspistol.lmp",
+                "sprites/trooa1.lmp",
+                &format!("sprites/vile{BACKSLASH_STANDIN}1.lmp"),
+            ]
+        );
+        let read = Wad::from_pk3_reader(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.lumps, wad.lumps);
+    }
+}
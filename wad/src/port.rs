@@ -0,0 +1,183 @@
+use crate::Game;
+use crate::MapFormat;
+use crate::MergeOptions;
+use crate::MusicStrategy;
+use crate::Wad;
+
+/// Lumps only ZDoom-family ports understand. Hexen's own MAPINFO, SNDINFO and ANIMDEFS aren't
+/// among them, since Hexen needs them.
+const ZDOOM_LUMPS: &[&str] = &[
+    "ZMAPINFO", "DECORATE", "ZSCRIPT", "TEXTURES", "GLDEFS", "MENUDEF", "SBARINFO", "LANGUAGE",
+    "KEYCONF", "TERRAIN", "LOCKDEFS",
+];
+
+/// Lumps added by Boom and the ports descended from it.
+const BOOM_LUMPS: &[&str] = &["ANIMATED", "SWITCHES", "UMAPINFO"];
+
+/// Lumps only Eternity understands.
+const ETERNITY_LUMPS: &[&str] = &["EMAPINFO", "EDFROOT", "ESTRINGS"];
+
+/// The source port a merged WAD is made to be played with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    /// The original executables, or a faithful recreation like Chocolate Doom.
+    Vanilla,
+    Boom,
+    Mbf21,
+    ZDoom,
+    Eternity,
+}
+
+/// An input map in a format the target port can't play. See [`Port::incompatible_maps`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncompatibleMap {
+    /// The index of the input the map is in.
+    pub input: usize,
+    pub map: String,
+    pub format: MapFormat,
+}
+
+impl Port {
+    /// Whether the port can play maps in a format. ZDoom and Eternity play them all; the others
+    /// only play the binary format of the game they're running.
+    pub fn supports_format(self, format: MapFormat, game: Game) -> bool {
+        match self {
+            Self::ZDoom | Self::Eternity => true,
+            Self::Vanilla | Self::Boom | Self::Mbf21 => {
+                format
+                    == if game == Game::Hexen {
+                        MapFormat::Hexen
+                    } else {
+                        MapFormat::Doom
+                    }
+            }
+        }
+    }
+
+    /// Finds the inputs' maps the port can't play when running `game`.
+    pub fn incompatible_maps(self, wads: &[Wad], game: Game) -> Vec<IncompatibleMap> {
+        let mut maps = Vec::new();
+        for (input, wad) in wads.iter().enumerate() {
+            for map in wad.maps() {
+                if !self.supports_format(map.format, game) {
+                    maps.push(IncompatibleMap {
+                        input,
+                        map: map.name.to_string(),
+                        format: map.format,
+                    });
+                }
+            }
+        }
+        maps
+    }
+
+    /// The lumps the port has no use for.
+    fn unsupported_lumps(self) -> Vec<&'static str> {
+        let lists: &[&[&str]] = match self {
+            Self::Vanilla => &[ZDOOM_LUMPS, BOOM_LUMPS, ETERNITY_LUMPS],
+            Self::Boom | Self::Mbf21 => &[ZDOOM_LUMPS, ETERNITY_LUMPS],
+            Self::ZDoom => &[ETERNITY_LUMPS],
+            Self::Eternity => &[],
+        };
+        lists.concat()
+    }
+}
+
+impl MergeOptions {
+    /// Options suited to a port. Its unsupported lumps are excluded, and ports whose demos
+    /// must stay in sync have them stripped, since they no longer match the renumbered maps.
+    /// Vanilla also warns about maps over the engine's limits, and ports reading MAPINFO get
+    /// their maps' music through it. Other options can be set on top with struct update syntax,
+    /// like `MergeOptions { seed: Some(1), ..MergeOptions::profile(Port::Boom) }`.
+    pub fn profile(port: Port) -> MergeOptions {
+        MergeOptions {
+            excluded_lumps: port
+                .unsupported_lumps()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            strip_demos: matches!(port, Port::Vanilla | Port::Boom | Port::Mbf21),
+            warn_vanilla_limits: port == Port::Vanilla,
+            music: match port {
+                Port::Vanilla | Port::Boom | Port::Mbf21 => MusicStrategy::Rename,
+                Port::ZDoom | Port::Eternity => MusicStrategy::Mapinfo,
+            },
+            ..MergeOptions::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_suit_their_port() {
+        let vanilla = MergeOptions::profile(Port::Vanilla);
+        assert!(vanilla.strip_demos);
+        assert!(vanilla.warn_vanilla_limits);
+        assert_eq!(vanilla.music, MusicStrategy::Rename);
+        assert!(vanilla.excluded_lumps.iter().any(|l| l == "ZMAPINFO"));
+        assert!(vanilla.excluded_lumps.iter().any(|l| l == "ANIMATED"));
+
+        let zdoom = MergeOptions::profile(Port::ZDoom);
+        assert!(!zdoom.strip_demos);
+        assert!(!zdoom.warn_vanilla_limits);
+        assert_eq!(zdoom.music, MusicStrategy::Mapinfo);
+        assert_eq!(zdoom.excluded_lumps, ETERNITY_LUMPS);
+
+        let overridden = MergeOptions {
+            strip_demos: false,
+            seed: Some(1),
+            ..MergeOptions::profile(Port::Vanilla)
+        };
+        assert!(!overridden.strip_demos);
+        assert!(overridden.warn_vanilla_limits);
+    }
+
+    fn named(names: &[&str]) -> Wad {
+        let mut wad = Wad::new_empty(false);
+        for name in names {
+            wad.append_lump_bytes(*name, Vec::new()).unwrap();
+        }
+        wad
+    }
+
+    #[test]
+    fn maps_are_checked_against_the_port() {
+        let doom = named(&["MAP01", "THINGS", "LINEDEFS"]);
+        let others = named(&[
+            "MAP01", "THINGS", "LINEDEFS", "BEHAVIOR", "MAP02", "TEXTMAP", "ENDMAP",
+        ]);
+        let wads = [doom, others];
+
+        let found = Port::Vanilla.incompatible_maps(&wads, Game::Doom2);
+        assert_eq!(
+            found,
+            [
+                IncompatibleMap {
+                    input: 1,
+                    map: String::from("MAP01"),
+                    format: MapFormat::Hexen,
+                },
+                IncompatibleMap {
+                    input: 1,
+                    map: String::from("MAP02"),
+                    format: MapFormat::Udmf,
+                },
+            ]
+        );
+        assert!(Port::ZDoom.incompatible_maps(&wads, Game::Doom2).is_empty());
+        assert!(Port::Eternity.supports_format(MapFormat::Udmf, Game::Doom2));
+
+        // running Hexen, it's the Doom format that doesn't fit
+        assert!(Port::Boom.supports_format(MapFormat::Hexen, Game::Hexen));
+        assert!(!Port::Boom.supports_format(MapFormat::Doom, Game::Hexen));
+        let found = Port::Mbf21.incompatible_maps(&wads, Game::Hexen);
+        let maps = found
+            .iter()
+            .map(|m| (m.input, m.format))
+            .collect::<Vec<_>>();
+        assert_eq!(maps, [(0, MapFormat::Doom), (1, MapFormat::Udmf)]);
+    }
+}
@@ -0,0 +1,285 @@
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+
+use crate::WadError;
+use crate::WadResult;
+
+/// Checks that `data` holds exactly one record of `size` bytes. Records are read field by field
+/// from a fixed layout rather than through serde, so every byte must belong to a field: a record
+/// with more bytes than its type reads is an error, not a set of unknown fields to skip.
+fn check_size(data: &[u8], size: usize) -> WadResult<()> {
+    match data.len() {
+        n if n < size => Err(WadError::UnexpectedEof),
+        n if n > size => Err(WadError::TrailingBytes),
+        _ => Ok(()),
+    }
+}
+
+/// Reads every record in a lump. Trailing bytes are ignored.
+fn read_all<'a, T>(data: &'a [u8], size: usize) -> Vec<T>
+where
+    T: TryFrom<&'a [u8], Error = WadError>,
+{
+    data.chunks_exact(size)
+        .map(|chunk| T::try_from(chunk).expect("chunk has the record's size"))
+        .collect()
+}
+
+/// Reads an eight-byte, NUL-padded texture name.
+fn texture_name(data: &[u8]) -> String {
+    let end = data.iter().position(|&c| c == b'\0').unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// A Doom-format thing, from the THINGS lump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thing {
+    pub x: i16,
+    pub y: i16,
+    pub angle: u16,
+    pub kind: u16,
+    pub flags: u16,
+}
+
+impl Thing {
+    pub const SIZE: usize = 10;
+
+    /// Reads every thing in a Doom-format THINGS lump. Trailing bytes are ignored.
+    pub fn read_all(data: &[u8]) -> Vec<Thing> {
+        read_all(data, Self::SIZE)
+    }
+
+    /// Writes things as a Doom-format THINGS lump.
+    pub fn write_all(things: &[Thing]) -> Vec<u8> {
+        let mut data = vec![0; things.len() * Self::SIZE];
+        for (thing, record) in things.iter().zip(data.chunks_exact_mut(Self::SIZE)) {
+            LittleEndian::write_i16(&mut record[0..], thing.x);
+            LittleEndian::write_i16(&mut record[2..], thing.y);
+            LittleEndian::write_u16(&mut record[4..], thing.angle);
+            LittleEndian::write_u16(&mut record[6..], thing.kind);
+            LittleEndian::write_u16(&mut record[8..], thing.flags);
+        }
+        data
+    }
+}
+
+impl TryFrom<&[u8]> for Thing {
+    type Error = WadError;
+
+    fn try_from(data: &[u8]) -> WadResult<Self> {
+        check_size(data, Self::SIZE)?;
+        Ok(Thing {
+            x: LittleEndian::read_i16(&data[0..]),
+            y: LittleEndian::read_i16(&data[2..]),
+            angle: LittleEndian::read_u16(&data[4..]),
+            kind: LittleEndian::read_u16(&data[6..]),
+            flags: LittleEndian::read_u16(&data[8..]),
+        })
+    }
+}
+
+/// A map vertex, from the VERTEXES lump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vertex {
+    pub x: i16,
+    pub y: i16,
+}
+
+impl Vertex {
+    pub const SIZE: usize = 4;
+
+    /// Reads every vertex in a VERTEXES lump. Trailing bytes are ignored.
+    pub fn read_all(data: &[u8]) -> Vec<Vertex> {
+        read_all(data, Self::SIZE)
+    }
+}
+
+impl TryFrom<&[u8]> for Vertex {
+    type Error = WadError;
+
+    fn try_from(data: &[u8]) -> WadResult<Self> {
+        check_size(data, Self::SIZE)?;
+        Ok(Vertex {
+            x: LittleEndian::read_i16(&data[0..]),
+            y: LittleEndian::read_i16(&data[2..]),
+        })
+    }
+}
+
+/// A Doom-format linedef, from the LINEDEFS lump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Linedef {
+    pub start_vertex: u16,
+    pub end_vertex: u16,
+    pub flags: u16,
+    pub special: u16,
+    pub tag: u16,
+    pub front_sidedef: u16,
+    /// `0xFFFF` for one-sided lines.
+    pub back_sidedef: u16,
+}
+
+impl Linedef {
+    pub const SIZE: usize = 14;
+
+    /// Reads every linedef in a Doom-format LINEDEFS lump. Trailing bytes are ignored.
+    pub fn read_all(data: &[u8]) -> Vec<Linedef> {
+        read_all(data, Self::SIZE)
+    }
+}
+
+impl TryFrom<&[u8]> for Linedef {
+    type Error = WadError;
+
+    fn try_from(data: &[u8]) -> WadResult<Self> {
+        check_size(data, Self::SIZE)?;
+        let field = |i: usize| LittleEndian::read_u16(&data[i * 2..]);
+        Ok(Linedef {
+            start_vertex: field(0),
+            end_vertex: field(1),
+            flags: field(2),
+            special: field(3),
+            tag: field(4),
+            front_sidedef: field(5),
+            back_sidedef: field(6),
+        })
+    }
+}
+
+/// A sidedef, from the SIDEDEFS lump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sidedef {
+    pub x_offset: i16,
+    pub y_offset: i16,
+    pub upper_texture: String,
+    pub lower_texture: String,
+    pub middle_texture: String,
+    pub sector: u16,
+}
+
+impl Sidedef {
+    pub const SIZE: usize = 30;
+
+    /// Reads every sidedef in a SIDEDEFS lump. Trailing bytes are ignored.
+    pub fn read_all(data: &[u8]) -> Vec<Sidedef> {
+        read_all(data, Self::SIZE)
+    }
+}
+
+impl TryFrom<&[u8]> for Sidedef {
+    type Error = WadError;
+
+    fn try_from(data: &[u8]) -> WadResult<Self> {
+        check_size(data, Self::SIZE)?;
+        Ok(Sidedef {
+            x_offset: LittleEndian::read_i16(&data[0..]),
+            y_offset: LittleEndian::read_i16(&data[2..]),
+            upper_texture: texture_name(&data[4..12]),
+            lower_texture: texture_name(&data[12..20]),
+            middle_texture: texture_name(&data[20..28]),
+            sector: LittleEndian::read_u16(&data[28..]),
+        })
+    }
+}
+
+/// A sector, from the SECTORS lump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sector {
+    pub floor_height: i16,
+    pub ceiling_height: i16,
+    pub floor_texture: String,
+    pub ceiling_texture: String,
+    pub light_level: i16,
+    pub special: u16,
+    pub tag: u16,
+}
+
+impl Sector {
+    pub const SIZE: usize = 26;
+
+    /// Reads every sector in a SECTORS lump. Trailing bytes are ignored.
+    pub fn read_all(data: &[u8]) -> Vec<Sector> {
+        read_all(data, Self::SIZE)
+    }
+}
+
+impl TryFrom<&[u8]> for Sector {
+    type Error = WadError;
+
+    fn try_from(data: &[u8]) -> WadResult<Self> {
+        check_size(data, Self::SIZE)?;
+        Ok(Sector {
+            floor_height: LittleEndian::read_i16(&data[0..]),
+            ceiling_height: LittleEndian::read_i16(&data[2..]),
+            floor_texture: texture_name(&data[4..12]),
+            ceiling_texture: texture_name(&data[12..20]),
+            light_level: LittleEndian::read_i16(&data[20..]),
+            special: LittleEndian::read_u16(&data[22..]),
+            tag: LittleEndian::read_u16(&data[24..]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_must_cover_every_byte() {
+        let thing = [1, 0, 2, 0, 90, 0, 1, 0, 7, 0];
+        assert!(Thing::try_from(&thing[..]).is_ok());
+        let longer = [&thing[..], &[0, 0]].concat();
+        assert!(matches!(
+            Thing::try_from(&longer[..]),
+            Err(WadError::TrailingBytes)
+        ));
+        assert!(matches!(
+            Thing::try_from(&thing[..8]),
+            Err(WadError::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn vertexes_are_four_bytes() {
+        assert_eq!(
+            Vertex::try_from(&[0xff, 0xff, 0x40, 0][..]).unwrap(),
+            Vertex { x: -1, y: 64 }
+        );
+        assert!(matches!(
+            Vertex::try_from(&[0, 0, 0][..]),
+            Err(WadError::UnexpectedEof)
+        ));
+        assert!(matches!(
+            Vertex::try_from(&[0, 0, 0, 0, 0, 0, 0][..]),
+            Err(WadError::TrailingBytes)
+        ));
+        // but whole lumps may end in a partial record
+        assert_eq!(
+            Vertex::read_all(&[0, 0, 0, 0, 0, 0, 0]),
+            [Vertex { x: 0, y: 0 }]
+        );
+    }
+
+    #[test]
+    fn things_round_trip() {
+        let things = [
+            Thing {
+                x: -32,
+                y: 64,
+                angle: 270,
+                kind: 3001,
+                flags: 7,
+            },
+            Thing {
+                x: 0,
+                y: 0,
+                angle: 0,
+                kind: 1,
+                flags: 0,
+            },
+        ];
+        let data = Thing::write_all(&things);
+        assert_eq!(data.len(), 2 * Thing::SIZE);
+        assert_eq!(Thing::read_all(&data), things);
+    }
+}
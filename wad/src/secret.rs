@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::parse_mapinfo;
+use crate::MapFormat;
+use crate::MapInfoEntry;
+use crate::MapSlice;
+use crate::Wad;
+
+/// Linedef specials which end the level through the secret exit.
+const SECRET_EXIT_SPECIALS: &[u16] = &[51, 124, 198];
+
+/// Decides which of a WAD's maps are secret levels, reached only through a secret exit.
+pub trait SecretDetector: fmt::Debug + Send + Sync {
+    /// The marker names of the WAD's secret maps.
+    fn secret_maps(&self, wad: &Wad) -> HashSet<String>;
+}
+
+/// Treats every map named as some map's `secretnext` in the WAD's MAPINFO as secret.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MapinfoSecrets;
+
+impl SecretDetector for MapinfoSecrets {
+    fn secret_maps(&self, wad: &Wad) -> HashSet<String> {
+        wad.mapinfo()
+            .into_iter()
+            .filter_map(|entry| entry.secret_next)
+            .collect()
+    }
+}
+
+/// Treats the maps in the slots the original games reserve for secret levels as secret: `MAP31`
+/// and `MAP32` in Doom II, `ExM9` in Doom.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlotSecrets;
+
+impl SecretDetector for SlotSecrets {
+    fn secret_maps(&self, wad: &Wad) -> HashSet<String> {
+        wad.maps()
+            .into_iter()
+            .filter(|map| {
+                matches!(map.name, "MAP31" | "MAP32")
+                    || (map.name.len() == 4
+                        && map.name.starts_with('E')
+                        && map.name.ends_with("M9"))
+            })
+            .map(|map| map.name.to_string())
+            .collect()
+    }
+}
+
+impl Wad {
+    /// The map definitions from the WAD's MAPINFO or ZMAPINFO lumps.
+    pub fn mapinfo(&self) -> Vec<MapInfoEntry> {
+        self.lumps
+            .iter()
+            .filter(|lump| {
+                lump.name.eq_ignore_ascii_case("MAPINFO")
+                    || lump.name.eq_ignore_ascii_case("ZMAPINFO")
+            })
+            .flat_map(|lump| parse_mapinfo(&String::from_utf8_lossy(&lump.data)))
+            .collect()
+    }
+}
+
+impl MapSlice<'_> {
+    /// Whether any of the map's linedefs is a secret exit. Only the Doom format is understood.
+    pub fn has_secret_exit(&self) -> bool {
+        let (MapFormat::Doom, Some(linedefs)) = (self.format, self.lump("LINEDEFS")) else {
+            return false;
+        };
+        linedefs.data.chunks_exact(14).any(|linedef| {
+            let special = u16::from_le_bytes([linedef[6], linedef[7]]);
+            SECRET_EXIT_SPECIALS.contains(&special)
+        })
+    }
+}
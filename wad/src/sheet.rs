@@ -0,0 +1,170 @@
+use crate::classify_lump;
+use crate::namespace::is_marker;
+use crate::namespace::namespace_blocks;
+use crate::picture_to_png;
+use crate::LumpCategory;
+use crate::Namespace;
+use crate::Palette;
+use crate::Picture;
+use crate::Wad;
+use crate::WadResult;
+
+/// The size of a label's characters, in pixels.
+const GLYPH_WIDTH: u16 = 3;
+const GLYPH_HEIGHT: u16 = 5;
+
+/// The space left around each picture in a contact sheet.
+const CELL_PADDING: u16 = 2;
+
+/// The rows of a character in the label font, top to bottom, with the leftmost pixel in the
+/// highest bit. Characters the font doesn't have are left blank.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        _ => [0; GLYPH_HEIGHT as usize],
+    }
+}
+
+/// Writes `text` in the label font, in palette color `color`.
+fn label(text: &str, color: u8) -> Picture {
+    let len = text.chars().count() as u16;
+    let mut out = Picture::new((len * (GLYPH_WIDTH + 1)).saturating_sub(1), GLYPH_HEIGHT);
+    for (i, c) in text.chars().enumerate() {
+        for (y, row) in glyph(c).into_iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                    let px = i * (GLYPH_WIDTH + 1) as usize + x as usize;
+                    out.pixels[y * out.width as usize + px] = Some(color);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Renders every graphic in the WAD (sprites, patches and loose pictures like `TITLEPIC`) onto
+/// one PNG, in a grid with each picture's name under it. Lumps which don't decode as pictures
+/// are left out, as are flats, which are stored raw, and pictures too large to fit in a cell. A
+/// WAD without any graphics gets a single transparent pixel. Fails if the grid would be too
+/// large for one picture.
+pub fn contact_sheet(wad: &Wad, palette: &Palette) -> WadResult<Vec<u8>> {
+    let names = wad
+        .lumps
+        .iter()
+        .map(|l| l.name.as_str())
+        .collect::<Vec<_>>();
+    let mut skip = vec![false; names.len()];
+    for map in wad.maps() {
+        skip[map.range].fill(true);
+    }
+    for (_, range) in namespace_blocks(&names, &[Namespace::Flats, Namespace::Hires]) {
+        skip[range].fill(true);
+    }
+    let white = palette.nearest([255, 255, 255]);
+    let cells = wad
+        .lumps
+        .iter()
+        .zip(skip)
+        .filter(|(lump, skip)| {
+            !skip && !is_marker(&lump.name) && classify_lump(lump) == LumpCategory::Other
+        })
+        .filter_map(|(lump, _)| Some((lump, lump.picture().ok()?)))
+        .filter(|(_, picture)| picture.width > 0 && picture.height > 0)
+        .filter_map(|(lump, picture)| {
+            let name = label(&lump.name, white);
+            let mut cell = Picture::new(
+                picture
+                    .width
+                    .max(name.width)
+                    .checked_add(2 * CELL_PADDING)?,
+                picture
+                    .height
+                    .checked_add(GLYPH_HEIGHT + 1 + 2 * CELL_PADDING)?,
+            );
+            let padding = CELL_PADDING as i32;
+            cell.overlay(&picture, padding, padding);
+            cell.overlay(&name, padding, padding + picture.height as i32 + 1);
+            Some(cell)
+        })
+        .collect::<Vec<_>>();
+    if cells.is_empty() {
+        return Ok(picture_to_png(&Picture::new(1, 1), palette));
+    }
+    let cols = (cells.len() as f64).sqrt().ceil() as usize;
+    Ok(picture_to_png(&Picture::tile(&cells, cols)?, palette))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode_picture;
+
+    fn sprite(width: u16, height: u16) -> Vec<u8> {
+        let mut picture = Picture::new(width, height);
+        picture.pixels.fill(Some(1));
+        encode_picture(&picture)
+    }
+
+    fn png_size(png: &[u8]) -> (u32, u32) {
+        let reader = png::Decoder::new(png).read_info().unwrap();
+        let info = reader.info();
+        (info.width, info.height)
+    }
+
+    #[test]
+    fn sprites_are_laid_out_with_their_names() {
+        let palette = Palette([[0; 3]; 256]);
+        let mut wad = Wad::new_empty(false);
+        wad.append_lump_bytes("S_START", vec![]).unwrap();
+        wad.append_lump_bytes("TROOA1", sprite(4, 4)).unwrap();
+        wad.append_lump_bytes("TROOB1", sprite(5, 2)).unwrap();
+        wad.append_lump_bytes("S_END", vec![]).unwrap();
+        wad.append_lump_bytes("JUNK", vec![1, 2, 3]).unwrap();
+
+        let png = contact_sheet(&wad, &palette).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        // two cells side by side, each as wide as a six-letter label and as tall as the larger
+        // sprite, its label, and the padding
+        assert_eq!(png_size(&png), (2 * 27, 14));
+
+        let empty = contact_sheet(&Wad::new_empty(false), &palette).unwrap();
+        assert_eq!(png_size(&empty), (1, 1));
+    }
+}
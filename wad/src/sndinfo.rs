@@ -0,0 +1,57 @@
+use std::collections::HashSet;
+
+use crate::Wad;
+
+/// A `logicalname lumpname` line from a SNDINFO lump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoundAlias {
+    /// The name the sound is known by, e.g. `weapons/pistol`.
+    pub name: String,
+    /// The lump holding the sound data, e.g. `DSPISTOL`.
+    pub lump: String,
+}
+
+/// Reads the sound aliases from the text of a SNDINFO lump. `$` directives are skipped, including
+/// the braced blocks of `$random` and friends, as are lines that don't look like an alias.
+pub fn parse_sndinfo(text: &str) -> Vec<SoundAlias> {
+    let mut aliases = Vec::new();
+    let mut depth = 0usize;
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap();
+        let opens = line.matches('{').count();
+        let closes = line.matches('}').count();
+        let in_block = depth > 0;
+        depth = (depth + opens).saturating_sub(closes);
+        if in_block || line.trim_start().starts_with('$') {
+            continue;
+        }
+        let tokens = line
+            .split_whitespace()
+            .map(|token| token.trim_matches('"'))
+            .collect::<Vec<_>>();
+        if let [name, lump] = tokens[..] {
+            aliases.push(SoundAlias {
+                name: name.to_string(),
+                lump: lump.to_string(),
+            });
+        }
+    }
+    aliases
+}
+
+impl Wad {
+    /// Finds the SNDINFO aliases whose lump is missing from the WAD.
+    pub fn dangling_sound_refs(&self) -> Vec<SoundAlias> {
+        let names = self
+            .lumps
+            .iter()
+            .map(|lump| lump.name.to_ascii_uppercase())
+            .collect::<HashSet<_>>();
+        self.lumps
+            .iter()
+            .filter(|lump| lump.name.eq_ignore_ascii_case("SNDINFO"))
+            .flat_map(|lump| parse_sndinfo(&String::from_utf8_lossy(&lump.data)))
+            .filter(|alias| !names.contains(&alias.lump.to_ascii_uppercase()))
+            .collect()
+    }
+}
@@ -0,0 +1,149 @@
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+
+use crate::Lump;
+use crate::WadError;
+use crate::WadResult;
+
+/// The format number at the start of every DMX sound.
+const DMX_FORMAT: u16 = 3;
+
+/// The size of the DMX header: format number, sample rate and sample count.
+const DMX_HEADER_SIZE: usize = 8;
+
+/// The padding samples DMX puts on either end of the sound.
+const DMX_PADDING: usize = 16;
+
+/// A sound effect in the DMX format Doom uses: 8-bit unsigned mono PCM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmxSound {
+    pub sample_rate: u16,
+    /// The samples, without the format's padding.
+    pub samples: Vec<u8>,
+}
+
+/// Decodes a DMX sound lump. The padding samples at either end are dropped.
+pub fn decode_dmx(data: &[u8]) -> WadResult<DmxSound> {
+    if data.len() < DMX_HEADER_SIZE {
+        return Err(WadError::UnexpectedEof);
+    }
+    let format = LittleEndian::read_u16(&data[0..]);
+    if format != DMX_FORMAT {
+        return Err(WadError::InvalidSoundFormat(format));
+    }
+    let sample_rate = LittleEndian::read_u16(&data[2..]);
+    let count = LittleEndian::read_u32(&data[4..]) as usize;
+    let samples = data[DMX_HEADER_SIZE..]
+        .get(..count)
+        .ok_or(WadError::UnexpectedEof)?;
+    // Sounds too short to hold the padding were presumably written without it.
+    let samples = if count >= DMX_PADDING * 2 {
+        &samples[DMX_PADDING..count - DMX_PADDING]
+    } else {
+        samples
+    };
+    Ok(DmxSound {
+        sample_rate,
+        samples: samples.to_vec(),
+    })
+}
+
+/// Encodes a DMX sound lump, padding either end with copies of the first and last samples.
+pub fn encode_dmx(sound: &DmxSound) -> Vec<u8> {
+    let first = sound.samples.first().copied().unwrap_or(128);
+    let last = sound.samples.last().copied().unwrap_or(128);
+    let count = sound.samples.len() + DMX_PADDING * 2;
+    let mut out = Vec::with_capacity(DMX_HEADER_SIZE + count);
+    out.extend_from_slice(&DMX_FORMAT.to_le_bytes());
+    out.extend_from_slice(&sound.sample_rate.to_le_bytes());
+    out.extend_from_slice(&(count as u32).to_le_bytes());
+    out.extend(std::iter::repeat_n(first, DMX_PADDING));
+    out.extend_from_slice(&sound.samples);
+    out.extend(std::iter::repeat_n(last, DMX_PADDING));
+    out
+}
+
+/// Writes a sound out as a mono, 8-bit PCM WAV file.
+pub fn dmx_to_wav(sound: &DmxSound) -> Vec<u8> {
+    let data_size = sound.samples.len() as u32;
+    let mut out = Vec::with_capacity(44 + sound.samples.len());
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_size).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    // one byte per sample, so the byte rate is the sample rate
+    out.extend_from_slice(&(sound.sample_rate as u32).to_le_bytes());
+    out.extend_from_slice(&(sound.sample_rate as u32).to_le_bytes());
+    // block alignment and bits per sample
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&8u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_size.to_le_bytes());
+    out.extend_from_slice(&sound.samples);
+    out
+}
+
+impl Lump {
+    /// Decodes the lump as a DMX sound.
+    pub fn dmx_sound(&self) -> WadResult<DmxSound> {
+        decode_dmx(&self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sound() -> DmxSound {
+        DmxSound {
+            sample_rate: 11025,
+            samples: vec![128, 200, 255, 0, 60, 128],
+        }
+    }
+
+    #[test]
+    fn dmx_sounds_round_trip() {
+        let lump = Lump {
+            name: String::from("DSPISTOL"),
+            data: encode_dmx(&sound()),
+        };
+        assert_eq!(lump.data.len(), DMX_HEADER_SIZE + 6 + DMX_PADDING * 2);
+        assert_eq!(lump.data[..8], [3, 0, 0x11, 0x2B, 38, 0, 0, 0]);
+        // padded with the end samples
+        assert_eq!(lump.data[8..24], [128; 16]);
+        assert_eq!(lump.dmx_sound().unwrap(), sound());
+        assert!(matches!(
+            decode_dmx(&lump.data[..lump.data.len() - 1]),
+            Err(WadError::UnexpectedEof)
+        ));
+        let mut wrong_format = lump.data.clone();
+        wrong_format[0] = 2;
+        assert!(matches!(
+            decode_dmx(&wrong_format),
+            Err(WadError::InvalidSoundFormat(2))
+        ));
+    }
+
+    #[test]
+    fn wavs_report_the_rate_and_sample_count() {
+        let decoded = decode_dmx(&encode_dmx(&sound())).unwrap();
+        let wav = dmx_to_wav(&decoded);
+        let u32_at = |offset: usize| LittleEndian::read_u32(&wav[offset..]);
+        let u16_at = |offset: usize| LittleEndian::read_u16(&wav[offset..]);
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32_at(4), wav.len() as u32 - 8);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        // PCM, mono, 8 bits
+        assert_eq!((u16_at(20), u16_at(22), u16_at(34)), (1, 1, 8));
+        assert_eq!(u32_at(24), 11025);
+        assert_eq!(u32_at(28), 11025);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(40), 6);
+        assert_eq!(wav[44..], sound().samples);
+    }
+}
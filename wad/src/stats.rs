@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use crate::classify_lump;
+use crate::map::group_maps;
+use crate::namespace::is_marker;
+use crate::LumpCategory;
+use crate::MapFormat;
+use crate::MapSlice;
+use crate::Thing;
+use crate::Wad;
+
+/// The flag a thing has if it appears on the hardest skill levels.
+const SKILL_HARD: u16 = 4;
+
+/// The flag a thing has if it only appears in multiplayer.
+const MULTIPLAYER_ONLY: u16 = 16;
+
+/// Overall figures about a WAD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct WadStats {
+    pub lump_count: usize,
+    pub map_count: usize,
+    /// The size the WAD has when written, in bytes.
+    pub total_size: u64,
+    /// How many of the lumps outside of maps fall into each category. Markers aren't counted.
+    pub categories: BTreeMap<LumpCategory, usize>,
+    /// How many names are shared by more than one lump outside of maps. Only the last lump with
+    /// a given name is normally used.
+    pub collisions: usize,
+}
+
+impl Wad {
+    /// Computes overall figures about the WAD.
+    pub fn stats(&self) -> WadStats {
+        let names = self
+            .lumps
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        let maps = group_maps(&names);
+        let mut in_map = vec![false; names.len()];
+        for map in &maps {
+            in_map[map.range.clone()].fill(true);
+        }
+        let mut categories = BTreeMap::new();
+        let mut name_counts = HashMap::new();
+        for (i, lump) in self.lumps.iter().enumerate() {
+            if in_map[i] || is_marker(&lump.name) {
+                continue;
+            }
+            *categories.entry(classify_lump(lump)).or_insert(0) += 1;
+            *name_counts.entry(lump.name.as_str()).or_insert(0) += 1;
+        }
+        WadStats {
+            lump_count: self.lumps.len(),
+            map_count: maps.len(),
+            total_size: self.total_size(),
+            categories,
+            collisions: name_counts.values().filter(|&&count| count > 1).count(),
+        }
+    }
+}
+
+/// How many hit points a Doom or Doom II monster spawns with, by editor number.
+fn monster_health(kind: u16) -> Option<u32> {
+    Some(match kind {
+        3004 => 20,
+        9 => 30,
+        84 => 50,
+        3001 => 60,
+        65 => 70,
+        3006 => 100,
+        3002 | 58 => 150,
+        66 => 300,
+        3005 | 71 => 400,
+        69 | 68 => 500,
+        67 => 600,
+        64 => 700,
+        3003 => 1000,
+        7 => 3000,
+        16 => 4000,
+        _ => return None,
+    })
+}
+
+impl MapSlice<'_> {
+    /// A rough measure of how hard the map is: the total hit points of the monsters it places
+    /// for single player on the hardest skill levels. Only the Doom format is understood, so
+    /// Hexen and UDMF maps score 0.
+    pub fn difficulty_score(&self) -> u32 {
+        let Some(things) = self
+            .lump("THINGS")
+            .filter(|_| self.format == MapFormat::Doom)
+        else {
+            return 0;
+        };
+        Thing::read_all(&things.data)
+            .into_iter()
+            .filter(|thing| thing.flags & SKILL_HARD != 0 && thing.flags & MULTIPLAYER_ONLY == 0)
+            .filter_map(|thing| monster_health(thing.kind))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_count_lumps_outside_of_maps() {
+        let sound = [3, 0, 0x11, 0x2B, 0, 0, 0, 0];
+        let mut wad = Wad::new_empty(false);
+        for (name, data) in [
+            ("PLAYPAL", &[0; 768][..]),
+            ("COLORMAP", &[0; 256]),
+            ("ENDOOM", &[0; 4000]),
+            ("DEMO1", b""),
+            ("D_E1M1", b"MUS\x1a"),
+            ("DSPISTOL", &sound),
+            ("DSPISTOL", &sound),
+            ("E1M1", b""),
+            ("THINGS", b""),
+            ("LINEDEFS", b""),
+            ("E1M2", b""),
+            ("THINGS", b""),
+            ("S_START", b""),
+            ("TROOA1", b""),
+            ("S_END", b""),
+        ] {
+            wad.append_lump_bytes(name, data.to_vec()).unwrap();
+        }
+        let stats = wad.stats();
+        assert_eq!(stats.lump_count, 15);
+        assert_eq!(stats.map_count, 2);
+        assert_eq!(
+            stats.total_size,
+            12 + 15 * 16 + 768 + 256 + 4000 + 4 + 2 * 8
+        );
+        assert_eq!(stats.total_size, wad.write_to_vec().unwrap().len() as u64);
+        assert_eq!(
+            stats.categories,
+            BTreeMap::from([
+                (LumpCategory::Palette, 2),
+                (LumpCategory::Music, 1),
+                (LumpCategory::Sound, 2),
+                (LumpCategory::Demo, 1),
+                (LumpCategory::Other, 2),
+            ])
+        );
+        // THINGS is in both maps, but that's no collision
+        assert_eq!(stats.collisions, 1);
+    }
+}
@@ -0,0 +1,224 @@
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+
+use crate::WadError;
+use crate::WadResult;
+
+/// The size of a texture definition before its patch list.
+const TEXTURE_HEADER_SIZE: usize = 22;
+
+/// The size of one patch placement within a texture definition.
+const PATCH_SIZE: usize = 10;
+
+/// A wall texture from a TEXTURE1 or TEXTURE2 lump, built out of patches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Texture {
+    pub name: String,
+    pub flags: u32,
+    pub width: i16,
+    pub height: i16,
+    pub patches: Vec<TexturePatch>,
+}
+
+/// One patch placed within a texture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TexturePatch {
+    pub origin_x: i16,
+    pub origin_y: i16,
+    /// The patch's lump name, looked up through PNAMES.
+    pub patch: String,
+}
+
+fn name8(data: &[u8]) -> String {
+    let end = data.iter().position(|&c| c == b'\0').unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+fn write_name8(out: &mut Vec<u8>, name: &str) {
+    let mut bytes = [0; 8];
+    let len = name.len().min(8);
+    bytes[..len].copy_from_slice(&name.as_bytes()[..len]);
+    out.extend_from_slice(&bytes);
+}
+
+fn invalid(name: &str, data: &[u8]) -> WadError {
+    WadError::InvalidLumpSize {
+        name: name.to_string(),
+        size: data.len(),
+    }
+}
+
+/// Reads the patch names from a PNAMES lump.
+pub fn parse_pnames(data: &[u8]) -> WadResult<Vec<String>> {
+    let count = data
+        .get(..4)
+        .map(LittleEndian::read_i32)
+        .ok_or_else(|| invalid("PNAMES", data))?;
+    let names = data[4..]
+        .chunks_exact(8)
+        .take(count.max(0) as usize)
+        .map(name8)
+        .collect::<Vec<_>>();
+    if names.len() != count.max(0) as usize {
+        return Err(invalid("PNAMES", data));
+    }
+    Ok(names)
+}
+
+/// Writes a PNAMES lump.
+pub fn build_pnames(names: &[String]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + names.len() * 8);
+    out.extend_from_slice(&(names.len() as i32).to_le_bytes());
+    for name in names {
+        write_name8(&mut out, name);
+    }
+    out
+}
+
+/// Reads the textures from a TEXTURE1 or TEXTURE2 lump, resolving patch indices through the
+/// names from the matching PNAMES.
+pub fn parse_textures(data: &[u8], pnames: &[String]) -> WadResult<Vec<Texture>> {
+    let bad = || invalid("TEXTURE", data);
+    let count = data.get(..4).map(LittleEndian::read_i32).ok_or_else(bad)?;
+    let mut textures = Vec::new();
+    for i in 0..count.max(0) as usize {
+        let offset = data
+            .get(4 + i * 4..8 + i * 4)
+            .map(LittleEndian::read_i32)
+            .ok_or_else(bad)?;
+        let offset = usize::try_from(offset).map_err(|_| bad())?;
+        let header_end = offset.checked_add(TEXTURE_HEADER_SIZE).ok_or_else(bad)?;
+        let header = data.get(offset..header_end).ok_or_else(bad)?;
+        let patch_count = LittleEndian::read_i16(&header[20..]).max(0) as usize;
+        let patch_data = data
+            .get(header_end..)
+            .and_then(|rest| rest.get(..patch_count * PATCH_SIZE))
+            .ok_or_else(bad)?;
+        let name = name8(&header[..8]);
+        let patches = patch_data
+            .chunks_exact(PATCH_SIZE)
+            .map(|patch| {
+                let index = LittleEndian::read_i16(&patch[4..]);
+                let name = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| pnames.get(index))
+                    .ok_or_else(|| WadError::InvalidPatchIndex {
+                        texture: name.clone(),
+                        index,
+                    })?;
+                Ok(TexturePatch {
+                    origin_x: LittleEndian::read_i16(&patch[0..]),
+                    origin_y: LittleEndian::read_i16(&patch[2..]),
+                    patch: name.clone(),
+                })
+            })
+            .collect::<WadResult<_>>()?;
+        textures.push(Texture {
+            name,
+            flags: LittleEndian::read_u32(&header[8..]),
+            width: LittleEndian::read_i16(&header[12..]),
+            height: LittleEndian::read_i16(&header[14..]),
+            patches,
+        });
+    }
+    Ok(textures)
+}
+
+/// Writes a TEXTURE1 or TEXTURE2 lump, referring to patches by their index in `pnames`. Patches
+/// which aren't listed there yet, ignoring case, are added to the end.
+pub fn build_textures(textures: &[Texture], pnames: &mut Vec<String>) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(textures.len() as i32).to_le_bytes());
+    let mut offset = 4 + textures.len() * 4;
+    for texture in textures {
+        out.extend_from_slice(&(offset as i32).to_le_bytes());
+        offset += TEXTURE_HEADER_SIZE + texture.patches.len() * PATCH_SIZE;
+    }
+    for texture in textures {
+        write_name8(&mut out, &texture.name);
+        out.extend_from_slice(&texture.flags.to_le_bytes());
+        out.extend_from_slice(&texture.width.to_le_bytes());
+        out.extend_from_slice(&texture.height.to_le_bytes());
+        // the obsolete column directory
+        out.extend_from_slice(&0i32.to_le_bytes());
+        out.extend_from_slice(&(texture.patches.len() as i16).to_le_bytes());
+        for patch in &texture.patches {
+            let index = match pnames
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(&patch.patch))
+            {
+                Some(index) => index,
+                None => {
+                    pnames.push(patch.patch.clone());
+                    pnames.len() - 1
+                }
+            };
+            out.extend_from_slice(&patch.origin_x.to_le_bytes());
+            out.extend_from_slice(&patch.origin_y.to_le_bytes());
+            out.extend_from_slice(&(index as i16).to_le_bytes());
+            // the unused stepdir and colormap fields
+            out.extend_from_slice(&1i16.to_le_bytes());
+            out.extend_from_slice(&0i16.to_le_bytes());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(name: &str, patches: &[&str]) -> Texture {
+        Texture {
+            name: name.to_string(),
+            flags: 0,
+            width: 64,
+            height: 128,
+            patches: patches
+                .iter()
+                .map(|patch| TexturePatch {
+                    origin_x: 0,
+                    origin_y: 0,
+                    patch: patch.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn textures_round_trip() {
+        let textures = [
+            texture("BRICK", &["WALL00", "WALL01"]),
+            texture("SKY1", &[]),
+        ];
+        let mut pnames = vec![String::from("wall01")];
+        let data = build_textures(&textures, &mut pnames);
+        assert_eq!(pnames, ["wall01", "WALL00"]);
+        let parsed = parse_textures(&data, &pnames).unwrap();
+        assert_eq!(parsed[0].patches[0].patch, "WALL00");
+        assert_eq!(parsed[0].patches[1].patch, "wall01");
+        assert_eq!(parsed[1], textures[1]);
+        assert_eq!(parse_pnames(&build_pnames(&pnames)).unwrap(), pnames);
+    }
+
+    #[test]
+    fn bad_texture_offsets_are_an_error() {
+        for offset in [-1i32, i32::MAX] {
+            let mut data = 1i32.to_le_bytes().to_vec();
+            data.extend_from_slice(&offset.to_le_bytes());
+            assert!(matches!(
+                parse_textures(&data, &[]),
+                Err(WadError::InvalidLumpSize { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn unknown_patch_indices_are_an_error() {
+        let data = build_textures(&[texture("BRICK", &["WALL00"])], &mut Vec::new());
+        assert!(matches!(
+            parse_textures(&data, &[]),
+            Err(WadError::InvalidPatchIndex { index: 0, .. })
+        ));
+    }
+}
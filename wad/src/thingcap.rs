@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use crate::Thing;
+
+/// What a thing is, as far as balancing a map goes. See [`classify_thing`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThingClass {
+    Monster,
+    Weapon,
+    Ammo,
+    Health,
+    /// Everything else: player starts, keys, armor, decorations and so on.
+    Other,
+}
+
+/// Classifies a Doom or Doom II thing by its editor number. Commander Keen and the Icon of Sin
+/// count as [`ThingClass::Other`], since maps rely on them to open doors and end the game.
+pub fn classify_thing(kind: u16) -> ThingClass {
+    match kind {
+        3004 | 9 | 65 | 3001 | 3002 | 58 | 3006 | 3005 | 69 | 3003 | 68 | 71 | 66 | 67 | 64
+        | 16 | 7 | 84 => ThingClass::Monster,
+        2005 | 2001 | 82 | 2002 | 2003 | 2004 | 2006 => ThingClass::Weapon,
+        2007 | 2048 | 2008 | 2049 | 2010 | 2046 | 2047 | 17 | 8 => ThingClass::Ammo,
+        2011 | 2012 | 2014 | 2013 | 83 => ThingClass::Health,
+        _ => ThingClass::Other,
+    }
+}
+
+/// How far to cut down one class of thing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapStrategy {
+    /// Keep at most this many.
+    AtMost(usize),
+    /// Remove this percentage of them, rounding the number kept up.
+    ReduceBy(u8),
+}
+
+impl CapStrategy {
+    /// How many of `count` things to keep.
+    fn keep(self, count: usize) -> usize {
+        match self {
+            Self::AtMost(max) => count.min(max),
+            Self::ReduceBy(percent) => {
+                let kept = 100 - percent.min(100) as usize;
+                (count * kept).div_ceil(100)
+            }
+        }
+    }
+}
+
+/// Caps on the things in each map, for flattening the difficulty of a merged compilation. This
+/// is experimental: a map whose exit depends on killing a particular monster may become
+/// unfinishable. See [`crate::MergeOptions::thing_cap`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThingCap {
+    pub monsters: Option<CapStrategy>,
+    pub ammo: Option<CapStrategy>,
+    pub health: Option<CapStrategy>,
+}
+
+impl ThingCap {
+    fn strategy(&self, class: ThingClass) -> Option<CapStrategy> {
+        match class {
+            ThingClass::Monster => self.monsters,
+            ThingClass::Ammo => self.ammo,
+            ThingClass::Health => self.health,
+            ThingClass::Weapon | ThingClass::Other => None,
+        }
+    }
+}
+
+/// Drops things beyond the caps. The things kept from each class are spread evenly through the
+/// original list rather than taken from its start, so every part of the map keeps some.
+pub fn cap_things(things: &[Thing], cap: &ThingCap) -> Vec<Thing> {
+    let mut counts = HashMap::<ThingClass, usize>::new();
+    for thing in things {
+        *counts.entry(classify_thing(thing.kind)).or_default() += 1;
+    }
+    let mut seen = HashMap::<ThingClass, usize>::new();
+    let mut out = Vec::with_capacity(things.len());
+    for thing in things {
+        let class = classify_thing(thing.kind);
+        let Some(strategy) = cap.strategy(class) else {
+            out.push(*thing);
+            continue;
+        };
+        let count = counts[&class];
+        let keep = strategy.keep(count);
+        let i = seen.entry(class).or_default();
+        // keeps `keep` of the `count`, spaced evenly
+        if (*i + 1) * keep / count > *i * keep / count {
+            out.push(*thing);
+        }
+        *i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thing(x: i16, kind: u16) -> Thing {
+        Thing {
+            x,
+            y: 0,
+            angle: 0,
+            kind,
+            flags: 7,
+        }
+    }
+
+    #[test]
+    fn capped_things_are_spread_through_the_map() {
+        let mut things = vec![thing(-1, 1), thing(-2, 2001), thing(-3, 72)];
+        things.extend((0..10).map(|x| thing(x, 3004)));
+        let cap = ThingCap {
+            monsters: Some(CapStrategy::AtMost(3)),
+            ..ThingCap::default()
+        };
+        let kept = cap_things(&things, &cap);
+        let xs = kept.iter().map(|t| t.x).collect::<Vec<_>>();
+        // the player start, the weapon and the Commander Keen all stay
+        assert_eq!(xs, [-1, -2, -3, 3, 6, 9]);
+
+        let health = (0..5).map(|x| thing(x, 2011)).collect::<Vec<_>>();
+        let cap = ThingCap {
+            health: Some(CapStrategy::ReduceBy(50)),
+            ..ThingCap::default()
+        };
+        assert_eq!(cap_things(&health, &cap).len(), 3);
+        let cap = ThingCap {
+            health: Some(CapStrategy::ReduceBy(200)),
+            ..ThingCap::default()
+        };
+        assert!(cap_things(&health, &cap).is_empty());
+        assert_eq!(cap_things(&health, &ThingCap::default()), health);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::WadError;
+use crate::WadResult;
+
+/// What a UDMF TEXTMAP holds, as far as counting goes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextMapSummary {
+    /// The game the map is for, e.g. `zdoom`.
+    pub namespace: Option<String>,
+    pub things: usize,
+    pub vertices: usize,
+    pub linedefs: usize,
+    pub sidedefs: usize,
+    pub sectors: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Ident(&'a str),
+    /// A number, string or keyword value.
+    Value(&'a str),
+    Punct(u8),
+}
+
+/// Splits TEXTMAP source into tokens, skipping whitespace and comments.
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn skip_trivia(&mut self) -> WadResult<()> {
+        loop {
+            let rest = &self.text.as_bytes()[self.pos..];
+            match rest {
+                [c, ..] if c.is_ascii_whitespace() => self.pos += 1,
+                [b'/', b'/', ..] => {
+                    self.pos += rest.iter().position(|&c| c == b'\n').unwrap_or(rest.len());
+                }
+                [b'/', b'*', ..] => {
+                    let len = self.text[self.pos + 2..]
+                        .find("*/")
+                        .ok_or_else(|| malformed("unterminated comment"))?;
+                    self.pos += 2 + len + 2;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn next(&mut self) -> WadResult<Option<Token<'a>>> {
+        self.skip_trivia()?;
+        let bytes = self.text.as_bytes();
+        let start = self.pos;
+        let Some(&c) = bytes.get(start) else {
+            return Ok(None);
+        };
+        let token = match c {
+            b'{' | b'}' | b'=' | b';' => {
+                self.pos += 1;
+                Token::Punct(c)
+            }
+            b'"' => {
+                let mut end = start + 1;
+                loop {
+                    match bytes.get(end) {
+                        Some(b'"') => break,
+                        Some(b'\\') => end += 2,
+                        Some(_) => end += 1,
+                        None => return Err(malformed("unterminated string")),
+                    }
+                }
+                self.pos = end + 1;
+                Token::Value(&self.text[start..self.pos])
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                self.pos += bytes[start..]
+                    .iter()
+                    .position(|&c| !c.is_ascii_alphanumeric() && c != b'_')
+                    .unwrap_or(bytes.len() - start);
+                Token::Ident(&self.text[start..self.pos])
+            }
+            c if c.is_ascii_digit() || matches!(c, b'+' | b'-' | b'.') => {
+                self.pos += bytes[start..]
+                    .iter()
+                    .position(|&c| !c.is_ascii_alphanumeric() && !matches!(c, b'+' | b'-' | b'.'))
+                    .unwrap_or(bytes.len() - start);
+                Token::Value(&self.text[start..self.pos])
+            }
+            _ => {
+                return Err(malformed(&format!(
+                    "unexpected character `{}`",
+                    self.text[start..].chars().next().unwrap()
+                )))
+            }
+        };
+        Ok(Some(token))
+    }
+
+    fn expect(&mut self, punct: u8) -> WadResult<()> {
+        match self.next()? {
+            Some(Token::Punct(c)) if c == punct => Ok(()),
+            _ => Err(malformed(&format!("expected `{}`", punct as char))),
+        }
+    }
+
+    /// Reads the rest of an assignment, after its `=`, giving the assigned value.
+    fn value(&mut self) -> WadResult<&'a str> {
+        // keywords like `true` are values too
+        let value = match self.next()? {
+            Some(Token::Value(value) | Token::Ident(value)) => value,
+            _ => return Err(malformed("expected a value")),
+        };
+        self.expect(b';')?;
+        Ok(value)
+    }
+}
+
+fn malformed(reason: &str) -> WadError {
+    WadError::MalformedTextMap(reason.to_string())
+}
+
+/// Reads a TEXTMAP just far enough to find its namespace and count its blocks.
+pub fn parse_textmap(text: &str) -> WadResult<TextMapSummary> {
+    let mut lexer = Lexer { text, pos: 0 };
+    let mut summary = TextMapSummary::default();
+    while let Some(token) = lexer.next()? {
+        let Token::Ident(name) = token else {
+            return Err(malformed("expected a field or block name"));
+        };
+        match lexer.next()? {
+            Some(Token::Punct(b'{')) => {
+                loop {
+                    match lexer.next()? {
+                        Some(Token::Punct(b'}')) => break,
+                        Some(Token::Ident(_)) => {
+                            lexer.expect(b'=')?;
+                            lexer.value()?;
+                        }
+                        _ => return Err(malformed(&format!("unterminated {name} block"))),
+                    }
+                }
+                let count = match name.to_ascii_lowercase().as_str() {
+                    "thing" => &mut summary.things,
+                    "vertex" => &mut summary.vertices,
+                    "linedef" => &mut summary.linedefs,
+                    "sidedef" => &mut summary.sidedefs,
+                    "sector" => &mut summary.sectors,
+                    _ => continue,
+                };
+                *count += 1;
+            }
+            Some(Token::Punct(b'=')) => {
+                let value = lexer.value()?;
+                if name.eq_ignore_ascii_case("namespace") {
+                    summary.namespace = Some(value.trim_matches('"').to_string());
+                }
+            }
+            _ => return Err(malformed(&format!("expected `=` or `{{` after {name}"))),
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_counted() {
+        let text = r#"
+            // a comment
+            namespace = "zdoom";
+            thing { x = 0.0; y = -32.5; type = 1; }
+            Thing { type = 3004; ambush = true; }
+            /* vertices */
+            vertex { x = 0; y = 0; }
+            vertex { x = 64; y = 0; }
+            linedef { v1 = 0; v2 = 1; sidefront = 0; comment = "a \"quoted\" } brace"; }
+            sidedef { sector = 0; texturemiddle = "STARTAN2"; }
+            sector { texturefloor = "FLOOR4_8"; heightceiling = 128; }
+            somethingelse { foo = 1; }
+        "#;
+        assert_eq!(
+            parse_textmap(text).unwrap(),
+            TextMapSummary {
+                namespace: Some(String::from("zdoom")),
+                things: 2,
+                vertices: 2,
+                linedefs: 1,
+                sidedefs: 1,
+                sectors: 1,
+            }
+        );
+        assert_eq!(parse_textmap("").unwrap(), TextMapSummary::default());
+    }
+
+    #[test]
+    fn malformed_textmaps_are_an_error() {
+        for text in [
+            "thing { type = 1;",
+            "thing { type = 1 }",
+            "thing { type 1; }",
+            "namespace = ;",
+            "namespace",
+            "= 1;",
+            "thing { comment = \"unterminated; }",
+            "/* unterminated",
+            "thing { type = 1; } #",
+        ] {
+            assert!(
+                matches!(parse_textmap(text), Err(WadError::MalformedTextMap(_))),
+                "{text:?}"
+            );
+        }
+    }
+}